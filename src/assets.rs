@@ -0,0 +1,433 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rustybuzz::ttf_parser::{self, name_id};
+
+use crate::error::Result;
+
+const DEFAULT_TEMPLATE: &str = include_str!("../assets/templates/template.html");
+const DEFAULT_TEX_TEMPLATE: &str = include_str!("../assets/templates/template.tex");
+pub(crate) const DEFAULT_STYLE_SHEET: &str = include_str!("../assets/styles/light.css");
+
+const ROBOTO_REGULAR: &[u8] = include_bytes!("../assets/fonts/Roboto-Regular.ttf");
+const ROBOTO_BOLD: &[u8] = include_bytes!("../assets/fonts/Roboto-Bold.ttf");
+const ROBOTO_ITALIC: &[u8] = include_bytes!("../assets/fonts/Roboto-Italic.ttf");
+const ROBOTO_BOLD_ITALIC: &[u8] = include_bytes!("../assets/fonts/Roboto-BoldItalic.ttf");
+
+// name of the per project override directory, relative to the project root
+pub const PROJECT_DIR: &str = ".jamdr";
+
+// the roles and faces override fonts are named after, like `heading-bold-italic.ttf`
+const FONT_ROLES: [&str; 3] = ["body", "heading", "monospace"];
+const FONT_FACES: [&str; 4] = ["regular", "bold", "italic", "bold-italic"];
+
+// everything a backend needs besides the markdown itself,
+// kept in memory so rendering never touches the filesystem
+#[derive(Clone)]
+pub struct Assets {
+    pub template: String,
+    // the preamble and frame of latex documents, see `Tex`
+    pub tex_template: String,
+    pub style_sheet: String,
+    pub fonts: FontData,
+}
+
+// the assets compiled into the binary
+impl Default for Assets {
+    fn default() -> Self {
+        Assets {
+            template: DEFAULT_TEMPLATE.to_string(),
+            tex_template: DEFAULT_TEX_TEMPLATE.to_string(),
+            style_sheet: DEFAULT_STYLE_SHEET.to_string(),
+            fonts: FontData::default(),
+        }
+    }
+}
+
+impl Assets {
+    // embedded defaults, overridden by the user config dir, overridden by the project dir
+    pub fn discover(project_root: impl AsRef<Path>) -> Result<Self> {
+        let mut assets = Assets::default();
+
+        for dir in Assets::override_dirs(project_root) {
+            assets.override_from(dir)?;
+        }
+
+        Ok(assets)
+    }
+
+    // the closest directory above `file` that has a `.jamdr` dir, or the one `file` is in,
+    // so a document finds its overrides no matter where jamdr is run from
+    pub fn project_root(file: impl AsRef<Path>) -> PathBuf {
+        let dir = match file.as_ref().parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+
+        dir.ancestors()
+            .find(|ancestor| ancestor.join(PROJECT_DIR).is_dir())
+            .unwrap_or(&dir)
+            .to_path_buf()
+    }
+
+    // lowest precedence first
+    pub fn override_dirs(project_root: impl AsRef<Path>) -> Vec<PathBuf> {
+        let mut dirs = vec![];
+
+        if let Some(config_dir) = dirs::config_dir() {
+            dirs.push(config_dir.join("jamdr"));
+        }
+
+        dirs.push(project_root.as_ref().join(PROJECT_DIR));
+        dirs
+    }
+
+    // replaces every asset that exists in `dir`, missing files and directories are skipped
+    pub fn override_from(&mut self, dir: impl AsRef<Path>) -> Result<()> {
+        let dir = dir.as_ref();
+
+        if let Some(template) = read_if_exists(dir.join("templates/template.html"))? {
+            self.template = String::from_utf8_lossy(&template).into_owned();
+        }
+
+        if let Some(template) = read_if_exists(dir.join("templates/template.tex"))? {
+            self.tex_template = String::from_utf8_lossy(&template).into_owned();
+        }
+
+        if let Some(style_sheet) = read_if_exists(dir.join("styles/light.css"))? {
+            self.style_sheet = String::from_utf8_lossy(&style_sheet).into_owned();
+        }
+
+        self.fonts.override_from(dir.join("fonts"))
+    }
+
+    // loads assets from a directory laid out like the `assets/` folder in this repo
+    pub fn from_dir(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref();
+
+        Ok(Assets {
+            template: fs::read_to_string(dir.join("templates/template.html"))?,
+            // newer than the other assets, older directories don't have it
+            tex_template: read_if_exists(dir.join("templates/template.tex"))?
+                .map_or(DEFAULT_TEX_TEMPLATE.to_string(), |template| {
+                    String::from_utf8_lossy(&template).into_owned()
+                }),
+            style_sheet: fs::read_to_string(dir.join("styles/light.css"))?,
+            fonts: FontData::from_dir(dir.join("fonts"))?,
+        })
+    }
+}
+
+// the four faces of a family, families without italics get them synthesized
+// by slanting the upright faces
+#[derive(Clone)]
+pub struct FontFamily {
+    pub(crate) regular: Arc<[u8]>,
+    pub(crate) bold: Arc<[u8]>,
+    pub(crate) italic: Option<Arc<[u8]>>,
+    pub(crate) bold_italic: Option<Arc<[u8]>>,
+}
+
+impl FontFamily {
+    pub fn new(
+        regular: Vec<u8>,
+        bold: Vec<u8>,
+        italic: Option<Vec<u8>>,
+        bold_italic: Option<Vec<u8>>,
+    ) -> Self {
+        FontFamily {
+            regular: regular.into(),
+            bold: bold.into(),
+            italic: italic.map(Into::into),
+            bold_italic: bold_italic.map(Into::into),
+        }
+    }
+
+    // loads the regular face at `path` and the other faces next to it, named like
+    // `Family-Bold.ttf`, `Family-Italic.ttf` or `Family-BoldOblique.ttf`, a missing bold
+    // face falls back to the regular one
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let regular = fs::read(path)?;
+
+        let stem = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default();
+        let stem = stem.strip_suffix("-Regular").unwrap_or(stem);
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("ttf");
+
+        let sibling = |suffixes: &[&str]| -> Result<Option<Vec<u8>>> {
+            for suffix in suffixes {
+                let sibling = path.with_file_name(format!("{}-{}.{}", stem, suffix, extension));
+
+                if let Some(data) = read_if_exists(sibling)? {
+                    return Ok(Some(data));
+                }
+            }
+
+            Ok(None)
+        };
+
+        Ok(FontFamily::new(
+            regular.clone(),
+            sibling(&["Bold"])?.unwrap_or(regular),
+            sibling(&["Italic", "Oblique"])?,
+            sibling(&["BoldItalic", "BoldOblique"])?,
+        ))
+    }
+
+    // the face for a weight and slant, and whether its slant has to be synthesized
+    pub(crate) fn face(&self, bold: bool, italic: bool) -> (&Arc<[u8]>, bool) {
+        match (bold, italic) {
+            (false, false) => (&self.regular, false),
+            (true, false) => (&self.bold, false),
+            (false, true) => self
+                .italic
+                .as_ref()
+                .map_or((&self.regular, true), |face| (face, false)),
+            (true, true) => self
+                .bold_italic
+                .as_ref()
+                .map_or((&self.bold, true), |face| (face, false)),
+        }
+    }
+
+    pub(crate) fn faces(&self) -> impl Iterator<Item = &Arc<[u8]>> {
+        [&self.regular, &self.bold]
+            .into_iter()
+            .chain(&self.italic)
+            .chain(&self.bold_italic)
+    }
+
+    // the typographic family name of the regular face, or its plain family name,
+    // documents that refer to fonts by name use it
+    pub(crate) fn family_name(&self) -> Option<String> {
+        let face = ttf_parser::Face::parse(&self.regular, 0).ok()?;
+        let names: Vec<_> = face.names().into_iter().collect();
+
+        [name_id::TYPOGRAPHIC_FAMILY, name_id::FAMILY]
+            .iter()
+            .find_map(|id| {
+                names
+                    .iter()
+                    .filter(|name| name.name_id == *id && name.is_unicode())
+                    .find_map(|name| name.to_string())
+            })
+    }
+}
+
+// raw font files, read from disk once per batch and shared between render threads
+#[derive(Clone)]
+pub struct FontData {
+    pub(crate) body: FontFamily,
+    // none uses the body fonts
+    pub(crate) headings: Option<FontFamily>,
+    pub(crate) monospace: Option<FontFamily>,
+    // tried in order for characters the faces above have no glyphs for
+    pub(crate) fallbacks: Vec<Arc<[u8]>>,
+}
+
+impl Default for FontData {
+    fn default() -> Self {
+        FontData {
+            body: FontFamily {
+                regular: ROBOTO_REGULAR.into(),
+                bold: ROBOTO_BOLD.into(),
+                italic: Some(ROBOTO_ITALIC.into()),
+                bold_italic: Some(ROBOTO_BOLD_ITALIC.into()),
+            },
+            headings: None,
+            monospace: None,
+            fallbacks: vec![],
+        }
+    }
+}
+
+impl FontData {
+    pub fn new(regular: Vec<u8>, bold: Vec<u8>, italic: Vec<u8>, bold_italic: Vec<u8>) -> Self {
+        FontData::from_family(FontFamily::new(
+            regular,
+            bold,
+            Some(italic),
+            Some(bold_italic),
+        ))
+    }
+
+    // uses `body` for every role
+    pub fn from_family(body: FontFamily) -> Self {
+        FontData {
+            body,
+            headings: None,
+            monospace: None,
+            fallbacks: vec![],
+        }
+    }
+
+    pub fn set_body(&mut self, family: FontFamily) {
+        self.body = family;
+    }
+
+    pub fn set_headings(&mut self, family: FontFamily) {
+        self.headings = Some(family);
+    }
+
+    pub fn set_monospace(&mut self, family: FontFamily) {
+        self.monospace = Some(family);
+    }
+
+    // adds a font to the end of the fallback chain
+    pub fn push_fallback(&mut self, data: Vec<u8>) {
+        self.fallbacks.push(data.into());
+    }
+
+    pub fn load(
+        regular_path: impl AsRef<Path>,
+        bold_path: impl AsRef<Path>,
+        italic_path: impl AsRef<Path>,
+        bold_italic_path: impl AsRef<Path>,
+    ) -> Result<Self> {
+        Ok(FontData::new(
+            fs::read(regular_path)?,
+            fs::read(bold_path)?,
+            fs::read(italic_path)?,
+            fs::read(bold_italic_path)?,
+        ))
+    }
+
+    // expects the roboto file names used in `assets/fonts`
+    pub fn from_dir(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref();
+
+        FontData::load(
+            dir.join("Roboto-Regular.ttf"),
+            dir.join("Roboto-Bold.ttf"),
+            dir.join("Roboto-Italic.ttf"),
+            dir.join("Roboto-BoldItalic.ttf"),
+        )
+    }
+
+    // fonts named after their role and face, like `body-regular.ttf` or `monospace-bold.otf`,
+    // replace that face, heading and monospace faces start out as the body faces
+    pub fn override_from(&mut self, dir: impl AsRef<Path>) -> Result<()> {
+        let dir = dir.as_ref();
+
+        for role in FONT_ROLES {
+            let mut family = match role {
+                "heading" => self.headings.clone(),
+                "monospace" => self.monospace.clone(),
+                _ => None,
+            }
+            .unwrap_or_else(|| self.body.clone());
+            let mut found = false;
+
+            for face in FONT_FACES {
+                let Some(data) = read_font(dir, &format!("{}-{}", role, face))? else {
+                    continue;
+                };
+                let data = data.into();
+                found = true;
+
+                match face {
+                    "regular" => family.regular = data,
+                    "bold" => family.bold = data,
+                    "italic" => family.italic = Some(data),
+                    _ => family.bold_italic = Some(data),
+                }
+            }
+
+            match (role, found) {
+                (_, false) => {}
+                ("heading", true) => self.headings = Some(family),
+                ("monospace", true) => self.monospace = Some(family),
+                _ => self.body = family,
+            }
+        }
+
+        // every font in `fallback/`, in file name order
+        let fallback_dir = dir.join("fallback");
+
+        if fallback_dir.is_dir() {
+            let mut paths = fs::read_dir(fallback_dir)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            paths.sort();
+
+            for path in paths {
+                if matches!(
+                    path.extension().and_then(|extension| extension.to_str()),
+                    Some("ttf" | "otf")
+                ) {
+                    self.push_fallback(fs::read(path)?);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// `name` as a ttf or otf file in `dir`
+fn read_font(dir: &Path, name: &str) -> Result<Option<Vec<u8>>> {
+    match read_if_exists(dir.join(format!("{}.ttf", name)))? {
+        Some(data) => Ok(Some(data)),
+        None => read_if_exists(dir.join(format!("{}.otf", name))),
+    }
+}
+
+pub(crate) fn read_if_exists(path: PathBuf) -> Result<Option<Vec<u8>>> {
+    if path.is_file() {
+        Ok(Some(fs::read(path)?))
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn fonts_override_the_faces_of_their_role() {
+        let dir = TempDir::new("assets-fonts");
+        dir.write("body-bold.ttf", ROBOTO_ITALIC);
+        dir.write("monospace-regular.otf", ROBOTO_BOLD);
+
+        let mut fonts = FontData::default();
+        fonts.override_from(&*dir).unwrap();
+        let monospace = fonts.monospace.unwrap();
+
+        assert_eq!(&*fonts.body.regular, ROBOTO_REGULAR);
+        assert_eq!(&*fonts.body.bold, ROBOTO_ITALIC);
+        assert_eq!(&*monospace.regular, ROBOTO_BOLD);
+        assert_eq!(&*monospace.bold, ROBOTO_ITALIC);
+        assert!(fonts.headings.is_none());
+    }
+
+    #[test]
+    fn overrides_are_found_from_the_document() {
+        let dir = TempDir::new("assets-project");
+        dir.write(".jamdr/templates/template.html", "project template");
+        let document = dir.write("docs/chapters/one.md", "# One\n");
+
+        let root = Assets::project_root(&document);
+        let assets = Assets::discover(&root).unwrap();
+
+        assert_eq!(root, dir.canonicalize().unwrap());
+        assert_eq!(assets.template, "project template");
+    }
+
+    #[test]
+    fn documents_outside_a_project_use_their_own_dir() {
+        let dir = TempDir::new("assets-loose");
+        let document = dir.write("one.md", "# One\n");
+
+        assert_eq!(Assets::project_root(document), dir.canonicalize().unwrap());
+    }
+}
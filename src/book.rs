@@ -0,0 +1,82 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use pulldown_cmark::{Event, Parser, Tag};
+use serde::Deserialize;
+
+use crate::error::{Error, Result};
+
+// the chapters that go into one pdf, from a book.toml like
+//
+// title = "The Manual"
+// chapters = ["intro.md", "usage/basics.md"]
+//
+// without `chapters` they are the documents the SUMMARY.md next to it links to, in
+// order, like an mdbook, paths are relative to the manifest
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct Book {
+    pub title: Option<String>,
+    pub chapters: Vec<PathBuf>,
+}
+
+impl Book {
+    pub fn load(path: impl AsRef<Path>) -> Result<Book> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)?;
+        let mut book: Book = toml::from_str(&source)
+            .map_err(|err| Error::Config(format!("{}: {}", path.display(), err)))?;
+
+        let relative_to = path.parent().unwrap_or(Path::new("."));
+
+        if book.chapters.is_empty() {
+            let summary = relative_to.join("SUMMARY.md");
+            let summary = fs::read_to_string(&summary).map_err(|err| {
+                Error::Config(format!(
+                    "{} lists no chapters and {} can't be read: {}",
+                    path.display(),
+                    summary.display(),
+                    err
+                ))
+            })?;
+
+            book.chapters = summary_chapters(&summary);
+        }
+
+        if book.chapters.is_empty() {
+            return Err(Error::Config(format!(
+                "{}: a book needs at least one chapter",
+                path.display()
+            )));
+        }
+
+        book.chapters = book
+            .chapters
+            .iter()
+            .map(|chapter| relative_to.join(chapter))
+            .collect();
+
+        Ok(book)
+    }
+
+    // every file is a chapter, in the order given
+    pub fn from_files(chapters: Vec<PathBuf>) -> Book {
+        Book {
+            title: None,
+            chapters,
+        }
+    }
+}
+
+// the local markdown files a summary links to, links without a file, like to
+// drafts, are left out
+fn summary_chapters(summary: &str) -> Vec<PathBuf> {
+    Parser::new(summary)
+        .filter_map(|event| match event {
+            Event::Start(Tag::Link(_, url, _)) => Some(url.to_string()),
+            _ => None,
+        })
+        .filter(|url| !url.contains("://") && url.split('#').next().unwrap().ends_with(".md"))
+        .map(|url| PathBuf::from(url.split('#').next().unwrap()))
+        .collect()
+}
@@ -1,12 +1,39 @@
 use clap::{Parser, Subcommand, ValueEnum};
-use std::path::PathBuf;
+use jamdr::BackendType;
+use std::path::{Path, PathBuf};
 use std::process;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None, arg_required_else_help = true)]
 pub struct Arguments {
+    #[arg(long = "theme", default_value = "light")]
+    pub theme: String,
+
     #[arg(long = "css")]
-    pub custom_css: Option<String>,
+    pub custom_css: Option<PathBuf>,
+
+    // replace the theme's style sheet instead of appending to it
+    #[arg(long = "replace-css", default_value = "false", requires = "custom_css")]
+    pub replace_css: bool,
+
+    // fonts to try, in order, for characters the document fonts can't draw
+    #[arg(long = "fallback-font")]
+    pub fallback_fonts: Vec<PathBuf>,
+
+    // path to the regular face, or the name of an installed family, see `jamdr fonts`
+    #[arg(long = "body-font")]
+    pub body_font: Option<String>,
+
+    #[arg(long = "heading-font")]
+    pub heading_font: Option<String>,
+
+    // used for inline code
+    #[arg(long = "mono-font")]
+    pub monospace_font: Option<String>,
+
+    // language used for hyphenation, unless the document's front matter sets one
+    #[arg(long = "lang", default_value = "en")]
+    pub lang: String,
 
     #[arg(short = 'w', long = "watch", default_value = "false")]
     pub watch: bool,
@@ -14,13 +41,31 @@ pub struct Arguments {
     #[arg(long = "stdout", default_value = "false")]
     pub stdout: bool,
 
+    // svg and png write one file per page and always use the inhouse layout
     #[arg(short = 't', long = "type", default_value = "pdf")]
     pub output_type: Option<OutputType>,
 
+    // resolution of png pages
+    #[arg(long = "dpi", default_value = "150")]
+    pub dpi: f32,
+
+    // only write a small preview of the first page, for -t png
+    #[arg(long = "thumbnail", default_value = "false")]
+    pub thumbnail: bool,
+
+    // line width of -t txt
+    #[arg(long = "wrap", default_value = "80")]
+    pub wrap: usize,
+
+    // lays all files out as the chapters of one pdf, in the order given, a book.toml
+    // given instead of markdown files lists the chapters itself
+    #[arg(long = "merge", default_value = "false")]
+    pub merge: bool,
+
     #[arg(short = 'o', long = "output")]
     pub output_path: Option<PathBuf>,
 
-    #[arg(short = 'b', long = "backend", default_value = "chromium")]
+    #[arg(short = 'b', long = "backend", default_value = "inhouse")]
     pub backend: BackendType,
 
     #[arg()]
@@ -31,29 +76,70 @@ pub struct Arguments {
 }
 
 impl Arguments {
-    pub fn validate_args(self) -> Arguments {
-        if self.file_paths.len() > 1 && (self.stdout || self.output_path.is_some()) {
+    pub fn validate_args(mut self) -> Arguments {
+        if self.book_manifest().is_some() {
+            self.merge = true;
+        }
+
+        // an epub puts every file into one book
+        let merged = (self.output_type == Some(OutputType::Epub) || self.merge) && !self.stdout;
+
+        if self.merge && self.output_type != Some(OutputType::Pdf) {
+            eprintln!("only pdf files can be merged, epub books always are");
+            process::exit(1);
+        }
+
+        if self.file_paths.len() > 1 && !merged && (self.stdout || self.output_path.is_some()) {
             eprintln!("output name cannot be specified if multiple files are given");
             process::exit(1);
         }
 
-        return self;
+        if self.dpi <= 0.0 {
+            eprintln!("dpi has to be a positive number");
+            process::exit(1);
+        }
+
+        if self.wrap == 0 {
+            eprintln!("wrap has to be a positive number");
+            process::exit(1);
+        }
+
+        self
+    }
+
+    // the single book.toml given instead of documents
+    pub fn book_manifest(&self) -> Option<&Path> {
+        match self.file_paths.as_slice() {
+            [path]
+                if path
+                    .extension()
+                    .is_some_and(|extension| extension == "toml") =>
+            {
+                Some(path)
+            }
+            _ => None,
+        }
     }
 }
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     Render {},
+    // lists the font families found on the system
+    Fonts {},
+    // prints a document styled for the terminal
+    View { file: PathBuf },
 }
 
 #[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq, Parser)]
 pub enum OutputType {
-    PDF,
-    HTML,
-}
-
-#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq, Parser)]
-pub enum BackendType {
-    Inhouse,
-    Chromium,
+    Pdf,
+    Html,
+    Svg,
+    Png,
+    Epub,
+    Docx,
+    Odt,
+    Tex,
+    Txt,
 }
@@ -1,8 +1,173 @@
-use figment::providers::{Format, Serialized, Toml};
-use figment::Figment;
-
+use clap::{Parser, ValueEnum};
 use serde::{Deserialize, Serialize};
-use toml;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Config {}
+use crate::assets::{Assets, FontData, FontFamily};
+use crate::css::{CustomCss, StyleSheet};
+use crate::error::Result;
+use crate::render::Style;
+use crate::theme::Theme;
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq, Parser, Serialize, Deserialize)]
+pub enum BackendType {
+    Inhouse,
+    Chromium,
+}
+
+#[derive(Clone)]
+pub struct Config {
+    pub backend: BackendType,
+    pub style: Style,
+    pub assets: Assets,
+    // language of the documents, front matter can override it per document
+    pub lang: String,
+}
+
+impl Config {
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+}
+
+#[derive(Default)]
+pub struct ConfigBuilder {
+    backend: Option<BackendType>,
+    style: Option<Style>,
+    assets: Option<Assets>,
+    template: Option<String>,
+    tex_template: Option<String>,
+    style_sheet: Option<String>,
+    fonts: Option<FontData>,
+    custom_css: Option<CustomCss>,
+    lang: Option<String>,
+    fallback_fonts: Vec<Vec<u8>>,
+    body_font: Option<FontFamily>,
+    heading_font: Option<FontFamily>,
+    monospace_font: Option<FontFamily>,
+}
+
+impl ConfigBuilder {
+    pub fn backend(mut self, backend: BackendType) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    pub fn style(mut self, style: Style) -> Self {
+        self.style = Some(style);
+        self
+    }
+
+    // sets both the inhouse style and the style sheet
+    pub fn theme(mut self, theme: Theme) -> Self {
+        self.style = Some(theme.style);
+        self.style_sheet = Some(theme.style_sheet);
+        self
+    }
+
+    // sets every asset at once, the individual setters below take precedence
+    pub fn assets(mut self, assets: Assets) -> Self {
+        self.assets = Some(assets);
+        self
+    }
+
+    pub fn template(mut self, template: impl Into<String>) -> Self {
+        self.template = Some(template.into());
+        self
+    }
+
+    pub fn tex_template(mut self, template: impl Into<String>) -> Self {
+        self.tex_template = Some(template.into());
+        self
+    }
+
+    pub fn style_sheet(mut self, style_sheet: impl Into<String>) -> Self {
+        self.style_sheet = Some(style_sheet.into());
+        self
+    }
+
+    pub fn fonts(mut self, fonts: FontData) -> Self {
+        self.fonts = Some(fonts);
+        self
+    }
+
+    // applied on top of everything else, to the style sheet and the inhouse style
+    pub fn custom_css(mut self, custom_css: CustomCss) -> Self {
+        self.custom_css = Some(custom_css);
+        self
+    }
+
+    // tried before the fallback fonts found in the override dirs
+    pub fn fallback_font(mut self, data: Vec<u8>) -> Self {
+        self.fallback_fonts.push(data);
+        self
+    }
+
+    // the per role fonts take precedence over the ones set through css
+    pub fn body_font(mut self, family: FontFamily) -> Self {
+        self.body_font = Some(family);
+        self
+    }
+
+    pub fn heading_font(mut self, family: FontFamily) -> Self {
+        self.heading_font = Some(family);
+        self
+    }
+
+    pub fn monospace_font(mut self, family: FontFamily) -> Self {
+        self.monospace_font = Some(family);
+        self
+    }
+
+    pub fn lang(mut self, lang: impl Into<String>) -> Self {
+        self.lang = Some(lang.into());
+        self
+    }
+
+    // anything not set falls back to the assets embedded in the binary
+    pub fn build(self) -> Result<Config> {
+        let assets = self.assets.unwrap_or_default();
+        let mut style = self.style.unwrap_or_default();
+        let mut style_sheet = self.style_sheet.unwrap_or(assets.style_sheet);
+        let mut fonts = self.fonts.unwrap_or(assets.fonts);
+        let discovered = std::mem::take(&mut fonts.fallbacks);
+
+        for data in self.fallback_fonts {
+            fonts.push_fallback(data);
+        }
+
+        fonts.fallbacks.extend(discovered);
+
+        if let Some(custom_css) = self.custom_css {
+            StyleSheet::parse(&custom_css.source).apply(
+                &mut style,
+                &mut fonts,
+                &custom_css.base_dir,
+            )?;
+            style_sheet = custom_css.combine(style_sheet);
+        }
+
+        if let Some(family) = self.body_font {
+            fonts.set_body(family);
+        }
+
+        if let Some(family) = self.heading_font {
+            fonts.set_headings(family);
+        }
+
+        if let Some(family) = self.monospace_font {
+            fonts.set_monospace(family);
+        }
+
+        Ok(Config {
+            // chromium printing is disabled, so it has to be asked for
+            backend: self.backend.unwrap_or(BackendType::Inhouse),
+            style,
+            assets: Assets {
+                template: self.template.unwrap_or(assets.template),
+                tex_template: self.tex_template.unwrap_or(assets.tex_template),
+                style_sheet,
+                fonts,
+            },
+            lang: self.lang.unwrap_or_else(|| "en".to_string()),
+        })
+    }
+}
@@ -0,0 +1,637 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use printpdf::{Color, Mm, Pt};
+
+use crate::assets::{FontData, FontFamily};
+use crate::error::{Error, Result};
+use crate::render::{Direction, Hyphens, PageBreak, Style, TextAlign};
+use crate::theme::parse_color;
+
+// how a custom style sheet is combined with the theme's style sheet on the html path
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CssMode {
+    Append,
+    Replace,
+}
+
+// a user supplied style sheet that drives both backends
+#[derive(Clone, Debug)]
+pub struct CustomCss {
+    pub source: String,
+    // @font-face urls are relative to this
+    pub base_dir: PathBuf,
+    pub mode: CssMode,
+}
+
+impl CustomCss {
+    pub fn load(path: impl AsRef<Path>, mode: CssMode) -> Result<CustomCss> {
+        let path = path.as_ref();
+
+        Ok(CustomCss {
+            source: fs::read_to_string(path)?,
+            base_dir: path.parent().unwrap_or(Path::new(".")).to_path_buf(),
+            mode,
+        })
+    }
+
+    // returns the style sheet for the html path
+    pub fn combine(&self, style_sheet: String) -> String {
+        match self.mode {
+            CssMode::Append => style_sheet + "\n" + &self.source,
+            CssMode::Replace => self.source.clone(),
+        }
+    }
+}
+
+// a flat list of rules, at-rules other than @font-face and @page are skipped
+#[derive(Debug, Default)]
+pub struct StyleSheet {
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+pub struct Rule {
+    pub selectors: Vec<String>,
+    pub declarations: Vec<(String, String)>,
+}
+
+impl StyleSheet {
+    pub fn parse(source: &str) -> StyleSheet {
+        let source = strip_comments(source);
+        let mut rules = vec![];
+        let mut rest = source.as_str();
+
+        while let Some(open) = rest.find('{') {
+            let prelude = rest[..open].trim();
+            let close = match matching_brace(rest, open) {
+                Some(close) => close,
+                None => break,
+            };
+            let body = &rest[open + 1..close];
+            rest = &rest[close + 1..];
+
+            // nested at-rules like @media only apply to screens of a certain size, ignore them
+            if prelude.starts_with('@') && prelude != "@font-face" && !prelude.starts_with("@page")
+            {
+                continue;
+            }
+
+            rules.push(Rule {
+                selectors: prelude
+                    .split(',')
+                    .map(|selector| selector.split_whitespace().collect::<Vec<_>>().join(" "))
+                    .filter(|selector| !selector.is_empty())
+                    .collect(),
+                declarations: body
+                    .split(';')
+                    .filter_map(|declaration| declaration.split_once(':'))
+                    .map(|(property, value)| {
+                        let value = value.trim().trim_end_matches("!important").trim();
+                        (property.trim().to_lowercase(), value.to_string())
+                    })
+                    .collect(),
+            });
+        }
+
+        StyleSheet { rules }
+    }
+
+    // maps the supported subset of properties onto the inhouse style and fonts,
+    // urls in @font-face are resolved relative to `base_dir`
+    pub fn apply(&self, style: &mut Style, fonts: &mut FontData, base_dir: &Path) -> Result<()> {
+        let mut font_faces: HashMap<String, FontFaces> = HashMap::new();
+        let mut declared = Declared::default();
+
+        for rule in &self.rules {
+            if rule
+                .selectors
+                .iter()
+                .any(|selector| selector == "@font-face")
+            {
+                add_font_face(&mut font_faces, rule);
+                continue;
+            }
+
+            for selector in &rule.selectors {
+                let element = match selector.strip_prefix(".markdown-body") {
+                    Some("") => "body",
+                    Some(rest) => rest.trim(),
+                    None => selector.as_str(),
+                };
+
+                for (property, value) in &rule.declarations {
+                    apply_declaration(style, &mut declared, element, property, value)?;
+                }
+            }
+        }
+
+        // body margins sit inside the page margins, like they do when printing from a browser
+        style.vertical_padding += Mm::from(Pt(declared.body_margin.0));
+        style.horizontal_padding += Mm::from(Pt(declared.body_margin.1));
+
+        // the first family of the list that was declared with @font-face wins, the ones
+        // after it become fallbacks, generic families like sans-serif stay on the default fonts
+        if let Some(families) = declared.font_family {
+            let mut families = families
+                .split(',')
+                .map(unquote)
+                .filter_map(|family| font_faces.get(&family.to_lowercase()));
+
+            if let Some(family) = families.next() {
+                let mut fallbacks = families
+                    .map(|family| family.load(base_dir).map(|family| family.regular))
+                    .collect::<Result<Vec<_>>>()?;
+                fallbacks.append(&mut fonts.fallbacks);
+
+                fonts.body = family.load(base_dir)?;
+                fonts.fallbacks = fallbacks;
+            }
+        }
+
+        // headings and code only pick a family, fallbacks are shared with the body
+        for (families, role) in [
+            (declared.heading_font_family, &mut fonts.headings),
+            (declared.monospace_font_family, &mut fonts.monospace),
+        ] {
+            let family = families.and_then(|families| {
+                families
+                    .split(',')
+                    .map(unquote)
+                    .find_map(|family| font_faces.get(&family.to_lowercase()))
+            });
+
+            if let Some(family) = family {
+                *role = Some(family.load(base_dir)?);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// declarations that can only be resolved once the whole sheet has been read
+#[derive(Default)]
+struct Declared {
+    font_family: Option<String>,
+    heading_font_family: Option<String>,
+    monospace_font_family: Option<String>,
+    // vertical, horizontal, in points
+    body_margin: (f32, f32),
+}
+
+fn apply_declaration(
+    style: &mut Style,
+    declared: &mut Declared,
+    element: &str,
+    property: &str,
+    value: &str,
+) -> Result<()> {
+    let heading = match element {
+        "h1" => Some(0),
+        "h2" => Some(1),
+        "h3" => Some(2),
+        "h4" => Some(3),
+        "h5" => Some(4),
+        "h6" => Some(5),
+        _ => None,
+    };
+
+    if let Some(level) = heading {
+        match property {
+            // em and % are relative to the body text, like they would be in the browser
+            "font-size" => {
+                if let Some(size) = parse_length(value, style.font_size) {
+                    style.heading_sizes[level] = size;
+                }
+            }
+            "border-bottom-color" | "border-color" | "border-bottom" => {
+                set_color(&mut style.rule_color, value)
+            }
+            "font-family" => declared.heading_font_family = Some(value.to_string()),
+            "break-before" | "page-break-before" if level == 0 => {
+                style.h1_break = match value {
+                    "page" | "always" => PageBreak::Page,
+                    "right" | "recto" => PageBreak::Right,
+                    "auto" => PageBreak::Auto,
+                    _ => style.h1_break,
+                }
+            }
+            "column-span" if level == 0 => match value {
+                "all" => style.h1_span = true,
+                "none" => style.h1_span = false,
+                _ => {}
+            },
+            "break-after" | "page-break-after" => {
+                if let Some(avoid) = parse_avoid(value) {
+                    style.keep_with_next = avoid;
+                }
+            }
+            _ => {}
+        }
+
+        return Ok(());
+    }
+
+    match (element, property) {
+        ("html" | "body", "font-family") => declared.font_family = Some(value.to_string()),
+        ("code" | "pre" | "kbd" | "samp", "font-family") => {
+            declared.monospace_font_family = Some(value.to_string())
+        }
+        ("html" | "body", "font-size") => {
+            if let Some(size) = parse_length(value, style.font_size) {
+                style.font_size = size;
+            }
+        }
+        ("html" | "body", "color") => set_color(&mut style.text_color, value),
+        ("html" | "body", "background-color" | "background") => {
+            style.background_color = match value {
+                "transparent" | "none" => None,
+                color => parse_color(color).ok().or(style.background_color.take()),
+            }
+        }
+        ("html" | "body", "line-height") => {
+            if let Some(line_height) = parse_line_height(value, style.font_size) {
+                style.line_height = line_height;
+            }
+        }
+        ("html" | "body" | "p", "text-align") => {
+            if let Some(text_align) = parse_text_align(value) {
+                style.text_align = text_align;
+            }
+        }
+        ("html" | "body", "direction") => {
+            style.direction = match value {
+                "ltr" => Direction::Ltr,
+                "rtl" => Direction::Rtl,
+                _ => style.direction,
+            }
+        }
+        ("html" | "body" | "p", "hyphens") => {
+            style.hyphens = match value {
+                "none" => Hyphens::None,
+                "manual" => Hyphens::Manual,
+                "auto" => Hyphens::Auto,
+                _ => style.hyphens,
+            }
+        }
+        ("html" | "body" | "p", "orphans") => {
+            if let Ok(orphans) = value.parse() {
+                style.orphans = orphans;
+            }
+        }
+        ("html" | "body" | "p", "widows") => {
+            if let Ok(widows) = value.parse() {
+                style.widows = widows;
+            }
+        }
+        ("html" | "body", "columns" | "column-count") => {
+            // `columns` can carry a width as well, only the count is used
+            if let Some(columns) = value.split_whitespace().find_map(|part| part.parse().ok()) {
+                style.columns = columns;
+            }
+        }
+        ("html" | "body", "column-gap") => {
+            let gap = match value {
+                "normal" => Some(style.font_size),
+                gap => parse_length(gap, style.font_size),
+            };
+
+            if let Some(gap) = gap {
+                style.column_gap = Mm::from(Pt(gap));
+            }
+        }
+        ("html" | "body", "column-rule" | "column-rule-color") => {
+            if value == "none" {
+                style.column_rule = None;
+            } else {
+                let mut color = style.column_rule.take().unwrap_or(style.rule_color.clone());
+                set_color(&mut color, value);
+                style.column_rule = Some(color);
+            }
+        }
+        ("pre" | "img" | "table", "break-inside" | "page-break-inside") => {
+            if let Some(avoid) = parse_avoid(value) {
+                style.keep_together = avoid;
+            }
+        }
+        ("@page", "margin") => {
+            let (vertical, horizontal) = parse_margin(value, style.font_size);
+
+            if let Some(vertical) = vertical {
+                style.vertical_padding = Mm::from(Pt(vertical));
+            }
+
+            if let Some(horizontal) = horizontal {
+                style.horizontal_padding = Mm::from(Pt(horizontal));
+            }
+        }
+        ("html" | "body", "margin") => {
+            let (vertical, horizontal) = parse_margin(value, style.font_size);
+            declared.body_margin = (vertical.unwrap_or(0.0), horizontal.unwrap_or(0.0));
+        }
+        ("a", "color") => set_color(&mut style.link_color, value),
+        ("pre", "background-color" | "background") => set_color(&mut style.code_background, value),
+        (
+            "hr",
+            "border"
+            | "border-bottom"
+            | "border-color"
+            | "border-bottom-color"
+            | "background-color",
+        ) => set_color(&mut style.rule_color, value),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+// the four faces of a family declared with @font-face
+#[derive(Default, Debug)]
+struct FontFaces {
+    regular: Option<String>,
+    bold: Option<String>,
+    italic: Option<String>,
+    bold_italic: Option<String>,
+}
+
+impl FontFaces {
+    // a missing regular or bold face falls back to another upright one,
+    // missing italics are synthesized
+    fn load(&self, base_dir: &Path) -> Result<FontFamily> {
+        let regular = self
+            .regular
+            .as_ref()
+            .or(self.bold.as_ref())
+            .or(self.italic.as_ref())
+            .or(self.bold_italic.as_ref())
+            .ok_or_else(|| Error::Config("@font-face without a src".to_string()))?;
+
+        let read = |face: &String| fs::read(base_dir.join(face));
+        let regular = read(regular)?;
+
+        Ok(FontFamily::new(
+            regular.clone(),
+            self.bold.as_ref().map(read).transpose()?.unwrap_or(regular),
+            self.italic.as_ref().map(read).transpose()?,
+            self.bold_italic.as_ref().map(read).transpose()?,
+        ))
+    }
+}
+
+fn add_font_face(font_faces: &mut HashMap<String, FontFaces>, rule: &Rule) {
+    let get = |name: &str| {
+        rule.declarations
+            .iter()
+            .find(|(property, _)| property == name)
+            .map(|(_, value)| value.as_str())
+    };
+
+    let (Some(family), Some(src)) = (get("font-family"), get("src")) else {
+        return;
+    };
+
+    // only local files can be embedded, data urls and remote fonts are skipped
+    let Some(path) = src
+        .split("url(")
+        .skip(1)
+        .filter_map(|url| url.split_once(')'))
+        .map(|(url, _)| unquote(url))
+        .find(|url| !url.contains(':'))
+    else {
+        return;
+    };
+
+    let bold = matches!(
+        get("font-weight"),
+        Some("bold" | "bolder" | "600" | "700" | "800" | "900")
+    );
+    let italic = matches!(get("font-style"), Some("italic" | "oblique"));
+
+    let family = font_faces
+        .entry(unquote(family).to_lowercase())
+        .or_default();
+    let face = match (bold, italic) {
+        (false, false) => &mut family.regular,
+        (true, false) => &mut family.bold,
+        (false, true) => &mut family.italic,
+        (true, true) => &mut family.bold_italic,
+    };
+
+    *face = Some(path);
+}
+
+// lengths are returned in points, `em_size` is the font size em and % are relative to
+pub(crate) fn parse_length(value: &str, em_size: f32) -> Option<f32> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f32 = number.parse().ok()?;
+
+    match unit {
+        "pt" => Some(number),
+        "px" => Some(number * 0.75),
+        "mm" => Some(number * 72.0 / 25.4),
+        "cm" => Some(number * 72.0 / 2.54),
+        "in" => Some(number * 72.0),
+        "em" | "rem" => Some(number * em_size),
+        "%" => Some(number / 100.0 * em_size),
+        "" if number == 0.0 => Some(0.0),
+        _ => None,
+    }
+}
+
+// margin: <all> | <vertical> <horizontal> | ..., `auto` comes back as none
+fn parse_margin(value: &str, em_size: f32) -> (Option<f32>, Option<f32>) {
+    let margins = value
+        .split_whitespace()
+        .map(|margin| parse_length(margin, em_size))
+        .collect::<Vec<_>>();
+
+    match margins.as_slice() {
+        [all] => (*all, *all),
+        [vertical, horizontal, ..] => (*vertical, *horizontal),
+        [] => (None, None),
+    }
+}
+
+// line-height is stored as a multiple of the font size
+fn parse_line_height(value: &str, font_size: f32) -> Option<f32> {
+    match value.parse::<f32>() {
+        Ok(scale) => Some(scale),
+        Err(_) if value == "normal" => Some(1.2),
+        Err(_) => parse_length(value, font_size).map(|height| height / font_size),
+    }
+}
+
+fn parse_text_align(value: &str) -> Option<TextAlign> {
+    match value {
+        "left" | "start" => Some(TextAlign::Left),
+        "justify" => Some(TextAlign::Justify),
+        "center" => Some(TextAlign::Center),
+        "right" | "end" => Some(TextAlign::Right),
+        _ => None,
+    }
+}
+
+// whether a break-* property asks to avoid page breaks, none for values that
+// don't concern page breaks
+fn parse_avoid(value: &str) -> Option<bool> {
+    match value {
+        "avoid" | "avoid-page" => Some(true),
+        "auto" => Some(false),
+        _ => None,
+    }
+}
+
+// invalid or unsupported colors are ignored, like a browser would,
+// shorthands like `border: 1px solid #ddd` are searched for the color part
+fn set_color(target: &mut Color, value: &str) {
+    let color = match value.find("rgb") {
+        Some(start) => value[start..]
+            .find(')')
+            .and_then(|end| parse_color(&value[start..start + end + 1]).ok()),
+        None => value
+            .split_whitespace()
+            .find_map(|part| parse_color(part).ok()),
+    };
+
+    if let Some(color) = color {
+        *target = color;
+    }
+}
+
+fn unquote(value: &str) -> String {
+    value
+        .trim()
+        .trim_matches('"')
+        .trim_matches('\'')
+        .to_string()
+}
+
+fn strip_comments(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut rest = source;
+
+    while let Some(start) = rest.find("/*") {
+        out.push_str(&rest[..start]);
+
+        match rest[start + 2..].find("*/") {
+            Some(end) => rest = &rest[start + 2 + end + 2..],
+            None => rest = "",
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+fn matching_brace(source: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+
+    for (i, c) in source[open..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::Assets;
+    use crate::theme::hex_color;
+
+    fn styled(css: &str) -> Style {
+        let mut style = Style::default();
+        let mut fonts = Assets::default().fonts;
+        StyleSheet::parse(css)
+            .apply(&mut style, &mut fonts, Path::new("."))
+            .unwrap();
+
+        style
+    }
+
+    #[test]
+    fn font_sizes_are_converted_to_points() {
+        assert_eq!(styled("body { font-size: 16px; }").font_size, 12.0);
+        assert_eq!(
+            styled(".markdown-body { font-size: 11pt; }").font_size,
+            11.0
+        );
+    }
+
+    #[test]
+    fn heading_sizes_are_relative_to_the_body() {
+        let style =
+            styled("body { font-size: 12pt; } h1 { font-size: 2em; } h3 { font-size: 50%; }");
+
+        assert_eq!(style.heading_sizes[0], 24.0);
+        assert_eq!(style.heading_sizes[2], 6.0);
+        assert_eq!(style.heading_sizes[1], Style::default().heading_sizes[1]);
+    }
+
+    #[test]
+    fn colors_are_read_from_values_and_shorthands() {
+        let style = styled(
+            "body { color: #ff0000; } a { color: rgb(0, 128, 0); } hr { border: 1px solid #00f; }",
+        );
+
+        assert_eq!(hex_color(&style.text_color), "FF0000");
+        assert_eq!(hex_color(&style.link_color), "008000");
+        assert_eq!(hex_color(&style.rule_color), "0000FF");
+    }
+
+    #[test]
+    fn page_and_body_margins_add_up() {
+        let style = styled("@page { margin: 72pt 36pt; } body { margin: 10pt; }");
+
+        assert!((style.vertical_padding.into_pt().0 - 82.0).abs() < 0.01);
+        assert!((style.horizontal_padding.into_pt().0 - 46.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn line_heights_become_multiples_of_the_font_size() {
+        assert_eq!(styled("body { line-height: 1.5; }").line_height, 1.5);
+        assert_eq!(styled("body { line-height: normal; }").line_height, 1.2);
+        assert_eq!(
+            styled("body { font-size: 10pt; line-height: 15pt; }").line_height,
+            1.5
+        );
+    }
+
+    #[test]
+    fn code_blocks_take_the_pre_background() {
+        let style = styled("pre { background-color: #222222; }");
+
+        assert_eq!(hex_color(&style.code_background), "222222");
+    }
+
+    #[test]
+    fn unknown_and_invalid_values_are_ignored() {
+        let default = Style::default();
+        let style = styled(
+            "body { font-size: large; color: not-a-color; line-height: tall; text-align: middle; } \
+             h2 { font-size: huge; } blink { color: red; } body { shimmer: 1; }",
+        );
+
+        assert_eq!(style.font_size, default.font_size);
+        assert_eq!(hex_color(&style.text_color), hex_color(&default.text_color));
+        assert_eq!(style.line_height, default.line_height);
+        assert_eq!(style.text_align, default.text_align);
+        assert_eq!(style.heading_sizes, default.heading_sizes);
+    }
+}
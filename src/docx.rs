@@ -0,0 +1,991 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+
+use printpdf::Mm;
+use pulldown_cmark::{Alignment, Tag};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::front_matter::FrontMatter;
+use crate::page_break;
+use crate::render::{render_each, Backend, Direction, PageBreak, Style};
+use crate::theme::hex_color;
+use crate::util::{escape, image_info};
+use crate::walk::{self, Walker};
+
+// writes documents as word files, walking the same markdown events as the inhouse
+// layout does, the look comes from the style of the config through word styles
+pub struct Docx {}
+
+impl Backend for Docx {
+    // relative image paths start next to the document
+    fn render_document(markdown: &str, path: &Path, config: &Config) -> Result<Vec<u8>> {
+        let (front_matter, markdown) = FrontMatter::split(markdown)?;
+        let mut style = config.style.clone();
+        front_matter.apply(&mut style)?;
+
+        let markdown = page_break::mark(markdown, style.asterisk_breaks);
+        let events = walk::parse(&markdown);
+
+        let base = path.parent().unwrap_or(Path::new(""));
+        let mut writer = DocxWriter::new(base, &style);
+        walk::walk(&events, &mut writer);
+        writer.finish();
+
+        let lang = front_matter.lang.as_deref().unwrap_or(&config.lang);
+        writer.package(&front_matter, lang, config)
+    }
+
+    fn render_files(
+        files: &HashMap<PathBuf, String>,
+        config: &Config,
+    ) -> Result<HashMap<PathBuf, Vec<u8>>> {
+        render_each(files, |path, content| {
+            let docx = Docx::render_document(content, path, config)?;
+
+            Ok(vec![(path.with_extension("docx"), docx)])
+        })
+    }
+}
+
+// a relationship of the document or the footnotes part
+struct Relationship {
+    kind: &'static str,
+    target: String,
+    external: bool,
+}
+
+// a numbering instance, every list gets its own so numbered lists restart
+struct List {
+    // the first number of an ordered list, none for bullets
+    start: Option<u64>,
+    level: usize,
+}
+
+struct TableState {
+    alignments: Vec<Alignment>,
+    cell: usize,
+    head: bool,
+}
+
+// relationship ids below this are taken by the fixed parts of the document
+const FIRST_RELATIONSHIP: usize = 10;
+
+// sizes in twentieths of a point
+const TWIPS_PER_MM: f32 = 56.692_91;
+const EMU_PER_TWIP: u64 = 635;
+const LIST_INDENT: usize = 720;
+
+struct DocxWriter<'a> {
+    base: &'a Path,
+    style: &'a Style,
+
+    body: String,
+    footnotes: String,
+    in_footnote: bool,
+    // the next paragraph starts a footnote and gets its number in front
+    footnote_start: bool,
+    footnote_ids: HashMap<String, usize>,
+    defined_footnotes: HashSet<usize>,
+
+    relationships: Vec<Relationship>,
+    footnote_relationships: Vec<Relationship>,
+    media: Vec<(String, Vec<u8>)>,
+    drawings: usize,
+
+    lists: Vec<List>,
+    // the numbering instances of the lists that are open
+    open_lists: Vec<usize>,
+    // the next paragraph is the first of a list item and gets its bullet
+    item_start: bool,
+    quote_depth: usize,
+
+    paragraph: bool,
+    bold: usize,
+    italic: usize,
+    strike: usize,
+    // whether the open link became a hyperlink, links to anchors stay plain text
+    link: Option<bool>,
+    code_block: Option<String>,
+    table: Option<TableState>,
+    // the url and alt text of the image being read
+    image: Option<(String, String)>,
+}
+
+impl<'a> DocxWriter<'a> {
+    fn new(base: &'a Path, style: &'a Style) -> Self {
+        DocxWriter {
+            base,
+            style,
+            body: String::new(),
+            footnotes: String::new(),
+            in_footnote: false,
+            footnote_start: false,
+            footnote_ids: HashMap::new(),
+            defined_footnotes: HashSet::new(),
+            relationships: vec![],
+            footnote_relationships: vec![],
+            media: vec![],
+            drawings: 0,
+            lists: vec![],
+            open_lists: vec![],
+            item_start: false,
+            quote_depth: 0,
+            paragraph: false,
+            bold: 0,
+            italic: 0,
+            strike: 0,
+            link: None,
+            code_block: None,
+            table: None,
+            image: None,
+        }
+    }
+
+    // word wants the body to end in a paragraph
+    fn finish(&mut self) {
+        if self.body.ends_with("</w:tbl>") || self.body.is_empty() {
+            self.body.push_str("<w:p/>");
+        }
+    }
+
+    // where paragraphs and runs go, the body or the footnote being written
+    fn out(&mut self) -> &mut String {
+        match self.in_footnote {
+            true => &mut self.footnotes,
+            false => &mut self.body,
+        }
+    }
+
+    fn open_paragraph(&mut self, style: Option<&str>, alignment: Option<&str>) {
+        let style = style.or(match (self.quote_depth, self.in_footnote) {
+            (0, true) => Some("FootnoteText"),
+            (0, false) => None,
+            _ => Some("Quote"),
+        });
+
+        let mut properties = String::new();
+
+        if let Some(style) = style {
+            properties.push_str(&format!("<w:pStyle w:val=\"{}\"/>", style));
+        }
+
+        if let Some(list) = self.open_lists.last() {
+            match self.item_start {
+                true => properties.push_str(&format!(
+                    "<w:numPr><w:ilvl w:val=\"{}\"/><w:numId w:val=\"{}\"/></w:numPr>",
+                    self.open_lists.len() - 1,
+                    list
+                )),
+                // later paragraphs of an item line up with the text after the bullet
+                false => properties.push_str(&format!(
+                    "<w:ind w:left=\"{}\"/>",
+                    LIST_INDENT * self.open_lists.len()
+                )),
+            }
+        }
+
+        if let Some(alignment) = alignment {
+            properties.push_str(&format!("<w:jc w:val=\"{}\"/>", alignment));
+        }
+
+        self.item_start = false;
+        self.paragraph = true;
+
+        let paragraph = match properties.is_empty() {
+            true => "<w:p>".to_string(),
+            false => format!("<w:p><w:pPr>{}</w:pPr>", properties),
+        };
+        self.out().push_str(&paragraph);
+
+        if self.footnote_start {
+            self.footnote_start = false;
+            self.footnotes.push_str(
+                "<w:r><w:rPr><w:rStyle w:val=\"FootnoteReference\"/></w:rPr><w:footnoteRef/></w:r><w:r><w:t xml:space=\"preserve\"> </w:t></w:r>",
+            );
+        }
+    }
+
+    fn close_paragraph(&mut self) {
+        if self.paragraph {
+            self.paragraph = false;
+            self.out().push_str("</w:p>");
+        }
+    }
+
+    // tight list items and table cells have their text without a paragraph around it
+    fn ensure_paragraph(&mut self) {
+        if !self.paragraph {
+            self.open_paragraph(None, None);
+        }
+    }
+
+    fn run(&mut self, text: &str, style: Option<&str>) {
+        self.ensure_paragraph();
+
+        let mut properties = String::new();
+        let style = style.or(match self.link {
+            Some(true) => Some("Hyperlink"),
+            _ => None,
+        });
+
+        if let Some(style) = style {
+            properties.push_str(&format!("<w:rStyle w:val=\"{}\"/>", style));
+        }
+
+        if self.bold > 0 {
+            properties.push_str("<w:b/>");
+        }
+
+        if self.italic > 0 {
+            properties.push_str("<w:i/>");
+        }
+
+        if self.strike > 0 {
+            properties.push_str("<w:strike/>");
+        }
+
+        let run = match properties.is_empty() {
+            true => format!(
+                "<w:r><w:t xml:space=\"preserve\">{}</w:t></w:r>",
+                escape(text)
+            ),
+            false => format!(
+                "<w:r><w:rPr>{}</w:rPr><w:t xml:space=\"preserve\">{}</w:t></w:r>",
+                properties,
+                escape(text)
+            ),
+        };
+        self.out().push_str(&run);
+    }
+
+    // images word can't show, or that can't be read, are replaced by their alt text
+    fn image(&mut self, url: &str, alt: &str) {
+        let data = match url.contains("://") {
+            true => None,
+            false => fs::read(self.base.join(url)).ok(),
+        };
+
+        let Some((data, extension, (width, height))) = data.and_then(|data| {
+            let (extension, size) = image_info(&data)?;
+            Some((data, extension, size))
+        }) else {
+            eprintln!("warning: could not embed image {}", url);
+            self.run(alt, None);
+            return;
+        };
+
+        let name = format!("image{}.{}", self.media.len() + 1, extension);
+        self.media.push((name.clone(), data));
+
+        let id = self.relationship(Relationship {
+            kind: "image",
+            target: format!("media/{}", name),
+            external: false,
+        });
+
+        // pixels at 96 dpi, shrunk to the width of the text
+        let max_width = self.text_width() as u64 * EMU_PER_TWIP;
+        let (mut cx, mut cy) = (width as u64 * 9525, height as u64 * 9525);
+
+        if cx > max_width {
+            cy = cy * max_width / cx;
+            cx = max_width;
+        }
+
+        self.drawings += 1;
+        let drawing = format!(
+            concat!(
+                "<w:r><w:drawing><wp:inline distT=\"0\" distB=\"0\" distL=\"0\" distR=\"0\">",
+                "<wp:extent cx=\"{cx}\" cy=\"{cy}\"/><wp:docPr id=\"{n}\" name=\"Picture {n}\" descr=\"{alt}\"/>",
+                "<a:graphic xmlns:a=\"http://schemas.openxmlformats.org/drawingml/2006/main\">",
+                "<a:graphicData uri=\"http://schemas.openxmlformats.org/drawingml/2006/picture\">",
+                "<pic:pic xmlns:pic=\"http://schemas.openxmlformats.org/drawingml/2006/picture\">",
+                "<pic:nvPicPr><pic:cNvPr id=\"{n}\" name=\"{name}\"/><pic:cNvPicPr/></pic:nvPicPr>",
+                "<pic:blipFill><a:blip r:embed=\"{id}\"/><a:stretch><a:fillRect/></a:stretch></pic:blipFill>",
+                "<pic:spPr><a:xfrm><a:off x=\"0\" y=\"0\"/><a:ext cx=\"{cx}\" cy=\"{cy}\"/></a:xfrm>",
+                "<a:prstGeom prst=\"rect\"><a:avLst/></a:prstGeom></pic:spPr>",
+                "</pic:pic></a:graphicData></a:graphic></wp:inline></w:drawing></w:r>"
+            ),
+            cx = cx,
+            cy = cy,
+            n = self.drawings,
+            alt = escape(alt),
+            name = name,
+            id = id
+        );
+
+        self.ensure_paragraph();
+        self.out().push_str(&drawing);
+    }
+
+    // footnotes are numbered in the order they are first mentioned
+    fn footnote_id(&mut self, label: &str) -> usize {
+        let next = self.footnote_ids.len() + 1;
+        *self.footnote_ids.entry(label.to_string()).or_insert(next)
+    }
+
+    // adds a relationship to the part being written and returns its id
+    fn relationship(&mut self, relationship: Relationship) -> String {
+        match self.in_footnote {
+            true => {
+                self.footnote_relationships.push(relationship);
+                format!("rId{}", self.footnote_relationships.len())
+            }
+            false => {
+                self.relationships.push(relationship);
+                format!("rId{}", FIRST_RELATIONSHIP + self.relationships.len())
+            }
+        }
+    }
+
+    // between the horizontal margins, in twips
+    fn text_width(&self) -> usize {
+        twips(self.style.width - self.style.horizontal_padding * 2.0)
+    }
+
+    fn package(&self, front_matter: &FrontMatter, lang: &str, config: &Config) -> Result<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        let mut parts = vec![
+            ("[Content_Types].xml".to_string(), self.content_types()),
+            ("_rels/.rels".to_string(), PACKAGE_RELATIONSHIPS.to_string()),
+            ("docProps/core.xml".to_string(), core(front_matter, lang)),
+            ("word/document.xml".to_string(), self.document()),
+            (
+                "word/styles.xml".to_string(),
+                styles(self.style, lang, config),
+            ),
+            ("word/numbering.xml".to_string(), self.numbering()),
+            ("word/footnotes.xml".to_string(), self.footnotes()),
+            ("word/settings.xml".to_string(), SETTINGS.to_string()),
+            (
+                "word/_rels/document.xml.rels".to_string(),
+                relationships(&self.relationships, true),
+            ),
+        ];
+
+        if !self.footnote_relationships.is_empty() {
+            parts.push((
+                "word/_rels/footnotes.xml.rels".to_string(),
+                relationships(&self.footnote_relationships, false),
+            ));
+        }
+
+        for (name, content) in parts {
+            zip.start_file(name, options)?;
+            zip.write_all(content.as_bytes())?;
+        }
+
+        for (name, data) in &self.media {
+            zip.start_file(format!("word/media/{}", name), options)?;
+            zip.write_all(data)?;
+        }
+
+        Ok(zip.finish()?.into_inner())
+    }
+
+    fn content_types(&self) -> String {
+        let main = "application/vnd.openxmlformats-officedocument.wordprocessingml";
+        let overrides = [
+            ("/word/document.xml", "document.main+xml"),
+            ("/word/styles.xml", "styles+xml"),
+            ("/word/numbering.xml", "numbering+xml"),
+            ("/word/footnotes.xml", "footnotes+xml"),
+            ("/word/settings.xml", "settings+xml"),
+        ];
+
+        let mut types = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+            "<Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">",
+            "<Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\"/>",
+            "<Default Extension=\"xml\" ContentType=\"application/xml\"/>",
+            "<Default Extension=\"png\" ContentType=\"image/png\"/>",
+            "<Default Extension=\"jpeg\" ContentType=\"image/jpeg\"/>",
+            "<Default Extension=\"gif\" ContentType=\"image/gif\"/>",
+            "<Override PartName=\"/docProps/core.xml\" ContentType=\"application/vnd.openxmlformats-package.core-properties+xml\"/>",
+        ));
+
+        for (part, kind) in overrides {
+            types.push_str(&format!(
+                "<Override PartName=\"{}\" ContentType=\"{}.{}\"/>",
+                part, main, kind
+            ));
+        }
+
+        types.push_str("</Types>");
+        types
+    }
+
+    fn document(&self) -> String {
+        let style = self.style;
+
+        format!(
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+                "<w:document {}><w:body>{}",
+                "<w:sectPr><w:pgSz w:w=\"{}\" w:h=\"{}\"/>",
+                "<w:pgMar w:top=\"{v}\" w:right=\"{h}\" w:bottom=\"{v}\" w:left=\"{h}\" w:header=\"708\" w:footer=\"708\" w:gutter=\"0\"/>",
+                "{}</w:sectPr></w:body></w:document>"
+            ),
+            NAMESPACES,
+            self.body,
+            twips(style.width),
+            twips(style.height),
+            match style.columns {
+                1 => String::new(),
+                columns => format!(
+                    "<w:cols w:num=\"{}\" w:space=\"{}\"{}/>",
+                    columns,
+                    twips(style.column_gap),
+                    match style.column_rule {
+                        Some(_) => " w:sep=\"1\"",
+                        None => "",
+                    }
+                ),
+            },
+            v = twips(style.vertical_padding),
+            h = twips(style.horizontal_padding),
+        )
+    }
+
+    // a bullet and a decimal definition, every list refers to one of them
+    fn numbering(&self) -> String {
+        let mut numbering = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<w:numbering {}>",
+            NAMESPACES
+        );
+
+        for (id, ordered) in [(0, false), (1, true)] {
+            numbering.push_str(&format!(
+                "<w:abstractNum w:abstractNumId=\"{}\"><w:multiLevelType w:val=\"hybridMultilevel\"/>",
+                id
+            ));
+
+            for level in 0..9 {
+                let (format, text) = match ordered {
+                    true => ("decimal", format!("%{}.", level + 1)),
+                    false => (
+                        "bullet",
+                        ["\u{2022}", "\u{25e6}", "\u{25aa}"][level % 3].to_string(),
+                    ),
+                };
+
+                numbering.push_str(&format!(
+                    concat!(
+                        "<w:lvl w:ilvl=\"{}\"><w:start w:val=\"1\"/><w:numFmt w:val=\"{}\"/>",
+                        "<w:lvlText w:val=\"{}\"/><w:lvlJc w:val=\"left\"/>",
+                        "<w:pPr><w:ind w:left=\"{}\" w:hanging=\"360\"/></w:pPr></w:lvl>"
+                    ),
+                    level,
+                    format,
+                    text,
+                    LIST_INDENT * (level + 1)
+                ));
+            }
+
+            numbering.push_str("</w:abstractNum>");
+        }
+
+        for (i, list) in self.lists.iter().enumerate() {
+            let overrides = match list.start {
+                Some(start) => format!(
+                    "<w:lvlOverride w:ilvl=\"{}\"><w:startOverride w:val=\"{}\"/></w:lvlOverride>",
+                    list.level, start
+                ),
+                None => String::new(),
+            };
+
+            numbering.push_str(&format!(
+                "<w:num w:numId=\"{}\"><w:abstractNumId w:val=\"{}\"/>{}</w:num>",
+                i + 1,
+                list.start.is_some() as usize,
+                overrides
+            ));
+        }
+
+        numbering.push_str("</w:numbering>");
+        numbering
+    }
+
+    // footnotes that are referenced but never defined come out empty
+    fn footnotes(&self) -> String {
+        let mut footnotes = format!(
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<w:footnotes {}>",
+                "<w:footnote w:type=\"separator\" w:id=\"-1\"><w:p><w:r><w:separator/></w:r></w:p></w:footnote>",
+                "<w:footnote w:type=\"continuationSeparator\" w:id=\"0\"><w:p><w:r><w:continuationSeparator/></w:r></w:p></w:footnote>",
+                "{}"
+            ),
+            NAMESPACES, self.footnotes
+        );
+
+        let mut undefined: Vec<usize> = self
+            .footnote_ids
+            .values()
+            .filter(|id| !self.defined_footnotes.contains(id))
+            .copied()
+            .collect();
+        undefined.sort_unstable();
+
+        for id in undefined {
+            footnotes.push_str(&format!(
+                "<w:footnote w:id=\"{}\"><w:p><w:pPr><w:pStyle w:val=\"FootnoteText\"/></w:pPr></w:p></w:footnote>",
+                id
+            ));
+        }
+
+        footnotes.push_str("</w:footnotes>");
+        footnotes
+    }
+}
+
+impl<'a> Walker<'a> for DocxWriter<'a> {
+    fn start(&mut self, tag: Tag<'a>) {
+        match tag {
+            Tag::Paragraph => self.open_paragraph(None, None),
+            Tag::Heading(level, _, _) => {
+                self.close_paragraph();
+                self.open_paragraph(Some(&format!("Heading{}", level as usize)), None);
+            }
+            Tag::BlockQuote => {
+                self.close_paragraph();
+                self.quote_depth += 1;
+            }
+            Tag::CodeBlock(_) => {
+                self.close_paragraph();
+                self.code_block = Some(String::new());
+            }
+            Tag::List(start) => {
+                self.close_paragraph();
+                self.lists.push(List {
+                    start,
+                    level: self.open_lists.len(),
+                });
+                self.open_lists.push(self.lists.len());
+            }
+            Tag::Item => {
+                self.close_paragraph();
+                self.item_start = true;
+            }
+            Tag::FootnoteDefinition(label) => {
+                self.close_paragraph();
+
+                let id = self.footnote_id(&label);
+                self.defined_footnotes.insert(id);
+                self.in_footnote = true;
+                self.footnote_start = true;
+                self.footnotes
+                    .push_str(&format!("<w:footnote w:id=\"{}\">", id));
+            }
+            Tag::Table(alignments) => {
+                self.close_paragraph();
+
+                let width = self.text_width() / alignments.len().max(1);
+                let grid: String = alignments
+                    .iter()
+                    .map(|_| format!("<w:gridCol w:w=\"{}\"/>", width))
+                    .collect();
+
+                self.out().push_str(&format!(
+                    "<w:tbl><w:tblPr><w:tblStyle w:val=\"TableGrid\"/><w:tblW w:w=\"0\" w:type=\"auto\"/></w:tblPr><w:tblGrid>{}</w:tblGrid>",
+                    grid
+                ));
+                self.table = Some(TableState {
+                    alignments,
+                    cell: 0,
+                    head: false,
+                });
+            }
+            Tag::TableHead => {
+                self.out().push_str("<w:tr><w:trPr><w:tblHeader/></w:trPr>");
+                let table = self.table.as_mut().unwrap();
+                table.head = true;
+                table.cell = 0;
+            }
+            Tag::TableRow => {
+                self.out().push_str("<w:tr>");
+                self.table.as_mut().unwrap().cell = 0;
+            }
+            Tag::TableCell => {
+                let table = self.table.as_ref().unwrap();
+                let width = self.text_width() / table.alignments.len().max(1);
+                let alignment = match table.alignments.get(table.cell) {
+                    Some(Alignment::Center) => Some("center"),
+                    Some(Alignment::Right) => Some("right"),
+                    _ => None,
+                };
+
+                if table.head {
+                    self.bold += 1;
+                }
+
+                self.out().push_str(&format!(
+                    "<w:tc><w:tcPr><w:tcW w:w=\"{}\" w:type=\"dxa\"/></w:tcPr>",
+                    width
+                ));
+                self.open_paragraph(None, alignment);
+            }
+            Tag::Emphasis => self.italic += 1,
+            Tag::Strong => self.bold += 1,
+            Tag::Strikethrough => self.strike += 1,
+            Tag::Link(_, url, _) => {
+                self.ensure_paragraph();
+
+                if url.starts_with('#') {
+                    self.link = Some(false);
+                } else {
+                    let id = self.relationship(Relationship {
+                        kind: "hyperlink",
+                        target: url.to_string(),
+                        external: true,
+                    });
+
+                    self.out()
+                        .push_str(&format!("<w:hyperlink r:id=\"{}\">", id));
+                    self.link = Some(true);
+                }
+            }
+            Tag::Image(_, url, _) => self.image = Some((url.to_string(), String::new())),
+        }
+    }
+
+    fn end(&mut self, tag: Tag<'a>) {
+        match tag {
+            Tag::Paragraph | Tag::Heading(..) => self.close_paragraph(),
+            Tag::BlockQuote => {
+                self.close_paragraph();
+                self.quote_depth -= 1;
+            }
+            Tag::CodeBlock(_) => {
+                let code = self.code_block.take().unwrap();
+                self.open_paragraph(Some("SourceCode"), None);
+
+                for (i, line) in code.trim_end_matches('\n').split('\n').enumerate() {
+                    if i > 0 {
+                        self.out().push_str("<w:r><w:br/></w:r>");
+                    }
+
+                    self.run(line, None);
+                }
+
+                self.close_paragraph();
+            }
+            Tag::List(_) => {
+                self.close_paragraph();
+                self.open_lists.pop();
+            }
+            Tag::Item => {
+                // an empty item still shows its bullet
+                if self.item_start {
+                    self.open_paragraph(None, None);
+                }
+
+                self.close_paragraph();
+            }
+            Tag::FootnoteDefinition(_) => {
+                if self.footnote_start {
+                    self.open_paragraph(None, None);
+                }
+
+                self.close_paragraph();
+                self.footnotes.push_str("</w:footnote>");
+                self.in_footnote = false;
+            }
+            Tag::Table(_) => {
+                self.out().push_str("</w:tbl>");
+                self.table = None;
+            }
+            Tag::TableHead => {
+                self.out().push_str("</w:tr>");
+                self.table.as_mut().unwrap().head = false;
+            }
+            Tag::TableRow => self.out().push_str("</w:tr>"),
+            Tag::TableCell => {
+                self.close_paragraph();
+                self.out().push_str("</w:tc>");
+
+                let table = self.table.as_mut().unwrap();
+                table.cell += 1;
+
+                if table.head {
+                    self.bold -= 1;
+                }
+            }
+            Tag::Emphasis => self.italic -= 1,
+            Tag::Strong => self.bold -= 1,
+            Tag::Strikethrough => self.strike -= 1,
+            Tag::Link(..) => {
+                if self.link.take() == Some(true) {
+                    self.out().push_str("</w:hyperlink>");
+                }
+            }
+            Tag::Image(..) => {
+                let (url, alt) = self.image.take().unwrap();
+                self.image(&url, &alt);
+            }
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if let Some(code) = &mut self.code_block {
+            code.push_str(text);
+        } else if let Some((_, alt)) = &mut self.image {
+            alt.push_str(text);
+        } else {
+            self.run(text, None);
+        }
+    }
+
+    fn rule(&mut self) {
+        self.close_paragraph();
+        self.out().push_str(
+            "<w:p><w:pPr><w:pBdr><w:bottom w:val=\"single\" w:sz=\"6\" w:space=\"1\" w:color=\"auto\"/></w:pBdr></w:pPr></w:p>",
+        );
+    }
+
+    fn code(&mut self, code: &str) {
+        self.run(code, Some("VerbatimChar"));
+    }
+
+    fn footnote_reference(&mut self, label: &str) {
+        let id = self.footnote_id(label);
+        self.ensure_paragraph();
+        self.out().push_str(&format!(
+            "<w:r><w:rPr><w:rStyle w:val=\"FootnoteReference\"/></w:rPr><w:footnoteReference w:id=\"{}\"/></w:r>",
+            id
+        ));
+    }
+
+    fn soft_break(&mut self) {
+        self.text(" ");
+    }
+
+    fn hard_break(&mut self) {
+        self.ensure_paragraph();
+        self.out().push_str("<w:r><w:br/></w:r>");
+    }
+
+    fn task_list_marker(&mut self, checked: bool) {
+        match checked {
+            true => self.run("\u{2612} ", None),
+            false => self.run("\u{2610} ", None),
+        }
+    }
+
+    fn page_break(&mut self) {
+        self.out()
+            .push_str("<w:p><w:r><w:br w:type=\"page\"/></w:r></w:p>");
+    }
+}
+
+const NAMESPACES: &str = concat!(
+    "xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\" ",
+    "xmlns:r=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships\" ",
+    "xmlns:wp=\"http://schemas.openxmlformats.org/drawingml/2006/wordprocessingDrawing\""
+);
+
+const PACKAGE_RELATIONSHIPS: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+    "<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">",
+    "<Relationship Id=\"rId1\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument\" Target=\"word/document.xml\"/>",
+    "<Relationship Id=\"rId2\" Type=\"http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties\" Target=\"docProps/core.xml\"/>",
+    "</Relationships>"
+);
+
+const SETTINGS: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+    "<w:settings xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\">",
+    "<w:footnotePr><w:footnote w:id=\"-1\"/><w:footnote w:id=\"0\"/></w:footnotePr>",
+    "</w:settings>"
+);
+
+// the relationships of the document also point at its fixed parts
+fn relationships(relationships: &[Relationship], document: bool) -> String {
+    let base = "http://schemas.openxmlformats.org/officeDocument/2006/relationships";
+    let mut xml = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+        "<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">"
+    ));
+
+    let (fixed, first) = match document {
+        true => (
+            vec![
+                ("rId1", "styles", "styles.xml"),
+                ("rId2", "numbering", "numbering.xml"),
+                ("rId3", "footnotes", "footnotes.xml"),
+                ("rId4", "settings", "settings.xml"),
+            ],
+            FIRST_RELATIONSHIP + 1,
+        ),
+        false => (vec![], 1),
+    };
+
+    for (id, kind, target) in fixed {
+        xml.push_str(&format!(
+            "<Relationship Id=\"{}\" Type=\"{}/{}\" Target=\"{}\"/>",
+            id, base, kind, target
+        ));
+    }
+
+    for (i, relationship) in relationships.iter().enumerate() {
+        xml.push_str(&format!(
+            "<Relationship Id=\"rId{}\" Type=\"{}/{}\" Target=\"{}\"{}/>",
+            first + i,
+            base,
+            relationship.kind,
+            escape(&relationship.target),
+            match relationship.external {
+                true => " TargetMode=\"External\"",
+                false => "",
+            }
+        ));
+    }
+
+    xml.push_str("</Relationships>");
+    xml
+}
+
+fn core(front_matter: &FrontMatter, lang: &str) -> String {
+    let mut core = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+        "<cp:coreProperties xmlns:cp=\"http://schemas.openxmlformats.org/package/2006/metadata/core-properties\" ",
+        "xmlns:dc=\"http://purl.org/dc/elements/1.1/\">"
+    ));
+
+    let properties = [
+        ("title", front_matter.title.as_deref()),
+        ("creator", front_matter.author.as_deref()),
+        ("description", front_matter.description.as_deref()),
+        ("language", Some(lang)),
+    ];
+
+    for (element, value) in properties {
+        if let Some(value) = value {
+            core.push_str(&format!(
+                "<dc:{}>{}</dc:{}>",
+                element,
+                escape(value),
+                element
+            ));
+        }
+    }
+
+    core.push_str("</cp:coreProperties>");
+    core
+}
+
+// word styles from the inhouse style, fonts are referred to by their family names
+// since word files don't carry them
+fn styles(style: &Style, lang: &str, config: &Config) -> String {
+    let fonts = &config.assets.fonts;
+    let body_font = fonts.body.family_name().unwrap_or("Calibri".to_string());
+    let heading_font = fonts
+        .headings
+        .as_ref()
+        .and_then(|family| family.family_name())
+        .unwrap_or(body_font.clone());
+    let monospace_font = fonts
+        .monospace
+        .as_ref()
+        .and_then(|family| family.family_name())
+        .unwrap_or("Courier New".to_string());
+
+    let half_points = |size: f32| (size * 2.0).round() as usize;
+    let line = (style.line_height * 240.0).round() as usize;
+    let rfonts = |name: &str| {
+        format!(
+            "<w:rFonts w:ascii=\"{0}\" w:hAnsi=\"{0}\" w:cs=\"{0}\"/>",
+            escape(name)
+        )
+    };
+
+    let mut styles = format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<w:styles {}>",
+            "<w:docDefaults><w:rPrDefault><w:rPr>{}<w:color w:val=\"{}\"/>",
+            "<w:sz w:val=\"{}\"/><w:szCs w:val=\"{}\"/><w:lang w:val=\"{}\"/></w:rPr></w:rPrDefault>",
+            "<w:pPrDefault><w:pPr>{}<w:spacing w:after=\"120\" w:line=\"{}\" w:lineRule=\"auto\"/></w:pPr></w:pPrDefault>",
+            "</w:docDefaults>",
+            "<w:style w:type=\"paragraph\" w:default=\"1\" w:styleId=\"Normal\"><w:name w:val=\"Normal\"/><w:qFormat/></w:style>"
+        ),
+        NAMESPACES,
+        rfonts(&body_font),
+        hex_color(&style.text_color),
+        half_points(style.font_size),
+        half_points(style.font_size),
+        escape(lang),
+        match style.direction {
+            Direction::Rtl => "<w:bidi/>",
+            _ => "",
+        },
+        line
+    );
+
+    for (i, size) in style.heading_sizes.iter().enumerate() {
+        let level = i + 1;
+        let keep_with_next = match style.keep_with_next {
+            true => "<w:keepNext/>",
+            false => "",
+        };
+        let page_break = match (level, style.h1_break) {
+            (1, PageBreak::Page | PageBreak::Right) => "<w:pageBreakBefore/>",
+            _ => "",
+        };
+
+        styles.push_str(&format!(
+            concat!(
+                "<w:style w:type=\"paragraph\" w:styleId=\"Heading{0}\"><w:name w:val=\"heading {0}\"/>",
+                "<w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/><w:qFormat/>",
+                "<w:pPr>{1}<w:keepLines/>{2}<w:spacing w:before=\"240\" w:after=\"120\"/><w:outlineLvl w:val=\"{3}\"/></w:pPr>",
+                "<w:rPr>{4}<w:b/><w:sz w:val=\"{5}\"/><w:szCs w:val=\"{5}\"/></w:rPr></w:style>"
+            ),
+            level,
+            keep_with_next,
+            page_break,
+            i,
+            rfonts(&heading_font),
+            half_points(*size)
+        ));
+    }
+
+    styles.push_str(&format!(
+        concat!(
+            "<w:style w:type=\"paragraph\" w:styleId=\"Quote\"><w:name w:val=\"Quote\"/><w:basedOn w:val=\"Normal\"/><w:qFormat/>",
+            "<w:pPr><w:ind w:left=\"720\"/></w:pPr><w:rPr><w:i/></w:rPr></w:style>",
+            "<w:style w:type=\"paragraph\" w:customStyle=\"1\" w:styleId=\"SourceCode\"><w:name w:val=\"Source Code\"/><w:basedOn w:val=\"Normal\"/>",
+            "<w:pPr><w:shd w:val=\"clear\" w:color=\"auto\" w:fill=\"F6F8FA\"/><w:spacing w:line=\"240\" w:lineRule=\"auto\"/></w:pPr>",
+            "<w:rPr>{0}<w:sz w:val=\"{1}\"/><w:szCs w:val=\"{1}\"/></w:rPr></w:style>",
+            "<w:style w:type=\"character\" w:customStyle=\"1\" w:styleId=\"VerbatimChar\"><w:name w:val=\"Verbatim Char\"/>",
+            "<w:rPr>{0}<w:shd w:val=\"clear\" w:color=\"auto\" w:fill=\"F6F8FA\"/></w:rPr></w:style>",
+            "<w:style w:type=\"character\" w:styleId=\"Hyperlink\"><w:name w:val=\"Hyperlink\"/>",
+            "<w:rPr><w:color w:val=\"{2}\"/><w:u w:val=\"single\"/></w:rPr></w:style>",
+            "<w:style w:type=\"paragraph\" w:styleId=\"FootnoteText\"><w:name w:val=\"footnote text\"/><w:basedOn w:val=\"Normal\"/>",
+            "<w:pPr><w:spacing w:after=\"0\" w:line=\"240\" w:lineRule=\"auto\"/></w:pPr><w:rPr><w:sz w:val=\"{3}\"/><w:szCs w:val=\"{3}\"/></w:rPr></w:style>",
+            "<w:style w:type=\"character\" w:styleId=\"FootnoteReference\"><w:name w:val=\"footnote reference\"/>",
+            "<w:rPr><w:vertAlign w:val=\"superscript\"/></w:rPr></w:style>",
+            "<w:style w:type=\"table\" w:styleId=\"TableGrid\"><w:name w:val=\"Table Grid\"/><w:tblPr><w:tblBorders>",
+            "<w:top w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"{4}\"/><w:left w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"{4}\"/>",
+            "<w:bottom w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"{4}\"/><w:right w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"{4}\"/>",
+            "<w:insideH w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"{4}\"/><w:insideV w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"{4}\"/>",
+            "</w:tblBorders></w:tblPr></w:style>",
+            "</w:styles>"
+        ),
+        rfonts(&monospace_font),
+        half_points(style.font_size * 0.9),
+        hex_color(&style.link_color),
+        half_points(style.font_size * 0.8),
+        hex_color(&style.rule_color),
+    ));
+
+    styles
+}
+
+fn twips(length: Mm) -> usize {
+    (length.0 * TWIPS_PER_MM).round() as usize
+}
@@ -0,0 +1,644 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::assets::FontFamily;
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::front_matter::FrontMatter;
+use crate::render::{self, Direction};
+use crate::util::escape;
+
+// packages documents as an epub 3 book, each document is a chapter
+//
+// the chapters are the html the chromium backend prints, with the fonts of the config
+// and the local images they show bundled into the book, the front matter of the first
+// document describes the book
+pub struct Epub {}
+
+struct Chapter {
+    file: String,
+    // already escaped for xml
+    title: String,
+    lang: String,
+    dir: Option<&'static str>,
+    body: String,
+    headings: Vec<Heading>,
+}
+
+struct Heading {
+    level: usize,
+    title: String,
+    href: String,
+}
+
+// a file in the book besides the chapters
+struct Resource {
+    href: String,
+    media_type: &'static str,
+    data: Arc<[u8]>,
+}
+
+impl Epub {
+    pub fn render_book(documents: &[(&Path, &str)], config: &Config) -> Result<Vec<u8>> {
+        if documents.is_empty() {
+            return Err(Error::Config(
+                "an epub needs at least one document".to_string(),
+            ));
+        }
+
+        let mut resources = vec![];
+        let mut images = HashMap::new();
+        let mut chapters = vec![];
+        let mut metadata = None;
+
+        for (i, (path, document)) in documents.iter().enumerate() {
+            let (front_matter, markdown) = FrontMatter::split(document)?;
+            let file = format!("chapter-{}.xhtml", i + 1);
+
+            let mut style = config.style.clone();
+            front_matter.apply(&mut style)?;
+            let html = render::markdown_html(markdown, style.asterisk_breaks);
+            let html = bundle_images(&html, path, &mut images, &mut resources);
+            let (body, headings) = number_headings(&html, &file);
+
+            let title = match &front_matter.title {
+                Some(title) => escape(title),
+                None => headings.first().map_or_else(
+                    || escape(&path.file_stem().unwrap().to_string_lossy()),
+                    |heading| heading.title.clone(),
+                ),
+            };
+
+            chapters.push(Chapter {
+                file,
+                title,
+                lang: front_matter.lang.clone().unwrap_or(config.lang.clone()),
+                dir: match front_matter.dir.unwrap_or(config.style.direction) {
+                    Direction::Auto => None,
+                    Direction::Ltr => Some("ltr"),
+                    Direction::Rtl => Some("rtl"),
+                },
+                body,
+                headings,
+            });
+
+            metadata.get_or_insert(front_matter);
+        }
+
+        let metadata = metadata.unwrap();
+        let style_sheet = format!(
+            "{}\n.page-break {{ page-break-after: always; }}\n{}",
+            config.assets.style_sheet,
+            font_faces(config, &mut resources)
+        );
+
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        // the mimetype has to come first and uncompressed, readers sniff it
+        zip.start_file("mimetype", stored)?;
+        zip.write_all(b"application/epub+zip")?;
+
+        zip.start_file("META-INF/container.xml", deflated)?;
+        zip.write_all(CONTAINER.as_bytes())?;
+
+        zip.start_file("OEBPS/content.opf", deflated)?;
+        zip.write_all(package(&metadata, &chapters, &resources).as_bytes())?;
+
+        zip.start_file("OEBPS/nav.xhtml", deflated)?;
+        zip.write_all(navigation(&chapters).as_bytes())?;
+
+        zip.start_file("OEBPS/style.css", deflated)?;
+        zip.write_all(style_sheet.as_bytes())?;
+
+        for chapter in &chapters {
+            zip.start_file(format!("OEBPS/{}", chapter.file), deflated)?;
+            zip.write_all(
+                xhtml(
+                    &chapter.title,
+                    &chapter.lang,
+                    chapter.dir,
+                    &format!("<body class=\"markdown-body\">\n{}</body>", chapter.body),
+                )
+                .as_bytes(),
+            )?;
+        }
+
+        for resource in &resources {
+            zip.start_file(format!("OEBPS/{}", resource.href), deflated)?;
+            zip.write_all(&resource.data)?;
+        }
+
+        Ok(zip.finish()?.into_inner())
+    }
+}
+
+const CONTAINER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+fn package(metadata: &FrontMatter, chapters: &[Chapter], resources: &[Resource]) -> String {
+    let first = &chapters[0];
+    let mut opf = String::new();
+
+    opf.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    opf.push_str(&format!(
+        "<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"id\" xml:lang=\"{}\"{}>\n",
+        escape(&first.lang),
+        first.dir.map_or(String::new(), |dir| format!(" dir=\"{}\"", dir))
+    ));
+
+    opf.push_str("  <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n");
+    opf.push_str(&format!(
+        "    <dc:identifier id=\"id\">{}</dc:identifier>\n",
+        identifier(chapters)
+    ));
+    opf.push_str(&format!("    <dc:title>{}</dc:title>\n", first.title));
+    opf.push_str(&format!(
+        "    <dc:language>{}</dc:language>\n",
+        escape(&first.lang)
+    ));
+
+    let optional = [
+        ("creator", &metadata.author),
+        ("date", &metadata.date),
+        ("description", &metadata.description),
+    ];
+
+    for (element, value) in optional {
+        if let Some(value) = value {
+            opf.push_str(&format!(
+                "    <dc:{}>{}</dc:{}>\n",
+                element,
+                escape(value),
+                element
+            ));
+        }
+    }
+
+    opf.push_str(&format!(
+        "    <meta property=\"dcterms:modified\">{}</meta>\n",
+        timestamp()
+    ));
+    opf.push_str("  </metadata>\n");
+
+    opf.push_str("  <manifest>\n");
+    opf.push_str("    <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n");
+    opf.push_str("    <item id=\"style\" href=\"style.css\" media-type=\"text/css\"/>\n");
+
+    for (i, chapter) in chapters.iter().enumerate() {
+        opf.push_str(&format!(
+            "    <item id=\"chapter-{}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n",
+            i + 1,
+            chapter.file
+        ));
+    }
+
+    for (i, resource) in resources.iter().enumerate() {
+        opf.push_str(&format!(
+            "    <item id=\"resource-{}\" href=\"{}\" media-type=\"{}\"/>\n",
+            i + 1,
+            resource.href,
+            resource.media_type
+        ));
+    }
+
+    opf.push_str("  </manifest>\n");
+
+    match first.dir {
+        Some("rtl") => opf.push_str("  <spine page-progression-direction=\"rtl\">\n"),
+        _ => opf.push_str("  <spine>\n"),
+    }
+
+    for i in 0..chapters.len() {
+        opf.push_str(&format!("    <itemref idref=\"chapter-{}\"/>\n", i + 1));
+    }
+
+    opf.push_str("  </spine>\n");
+    opf.push_str("</package>\n");
+    opf
+}
+
+// the table of contents, headings nest by level, chapters without headings
+// are listed by their title
+fn navigation(chapters: &[Chapter]) -> String {
+    let mut entries = vec![];
+
+    for chapter in chapters {
+        match chapter.headings.is_empty() {
+            true => entries.push(Heading {
+                level: 1,
+                title: chapter.title.clone(),
+                href: chapter.file.clone(),
+            }),
+            false => entries.extend(chapter.headings.iter().map(|heading| Heading {
+                level: heading.level,
+                title: heading.title.clone(),
+                href: heading.href.clone(),
+            })),
+        }
+    }
+
+    let mut list = String::new();
+    nav_list(&mut list, &entries, 2);
+
+    let body = format!(
+        "<body>\n  <nav epub:type=\"toc\" id=\"toc\">\n    <h1>{}</h1>\n{}  </nav>\n</body>",
+        chapters[0].title, list
+    );
+
+    xhtml(
+        &chapters[0].title,
+        &chapters[0].lang,
+        chapters[0].dir,
+        &body,
+    )
+}
+
+// the first entry and every entry not deeper than the shallowest one start an item,
+// the entries after them up to the next item become its children
+fn nav_list(out: &mut String, entries: &[Heading], depth: usize) {
+    let shallowest = entries.iter().map(|entry| entry.level).min().unwrap();
+    let indent = "  ".repeat(depth);
+
+    out.push_str(&format!("{}<ol>\n", indent));
+
+    let mut i = 0;
+    while i < entries.len() {
+        let children = entries[i + 1..]
+            .iter()
+            .position(|entry| entry.level <= shallowest)
+            .unwrap_or(entries.len() - i - 1);
+
+        out.push_str(&format!(
+            "{}  <li><a href=\"{}\">{}</a>",
+            indent, entries[i].href, entries[i].title
+        ));
+
+        if children > 0 {
+            out.push('\n');
+            nav_list(out, &entries[i + 1..i + 1 + children], depth + 2);
+            out.push_str(&format!("{}  ", indent));
+        }
+
+        out.push_str("</li>\n");
+        i += 1 + children;
+    }
+
+    out.push_str(&format!("{}</ol>\n", indent));
+}
+
+fn xhtml(title: &str, lang: &str, dir: Option<&str>, body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" lang="{lang}" xml:lang="{lang}"{dir}>
+<head>
+  <title>{title}</title>
+  <link rel="stylesheet" type="text/css" href="style.css"/>
+</head>
+{body}
+</html>
+"#,
+        lang = escape(lang),
+        dir = dir.map_or(String::new(), |dir| format!(" dir=\"{}\"", dir)),
+        title = title,
+        body = body
+    )
+}
+
+// gives every heading an id to link to from the table of contents,
+// comrak writes headings without attributes
+fn number_headings(html: &str, file: &str) -> (String, Vec<Heading>) {
+    let mut out = String::with_capacity(html.len());
+    let mut headings = vec![];
+    let mut rest = html;
+
+    while let Some(start) = rest.find("<h") {
+        let tag = &rest[start..];
+        let level = match tag.as_bytes().get(2..4) {
+            Some([level @ b'1'..=b'6', b'>']) => (level - b'0') as usize,
+            _ => {
+                out.push_str(&rest[..start + 2]);
+                rest = &tag[2..];
+                continue;
+            }
+        };
+
+        let content = &tag[4..];
+        let end = content
+            .find(&format!("</h{}>", level))
+            .unwrap_or(content.len());
+        let id = format!("heading-{}", headings.len() + 1);
+
+        headings.push(Heading {
+            level,
+            title: strip_tags(&content[..end]),
+            href: format!("{}#{}", file, id),
+        });
+
+        out.push_str(&rest[..start]);
+        out.push_str(&format!("<h{} id=\"{}\">", level, id));
+        rest = content;
+    }
+
+    out.push_str(rest);
+    (out, headings)
+}
+
+// copies the local images `html` shows into the book and points it at the copies,
+// remote images are left alone, the same file is only bundled once
+fn bundle_images(
+    html: &str,
+    document: &Path,
+    images: &mut HashMap<PathBuf, String>,
+    resources: &mut Vec<Resource>,
+) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find("<img src=\"") {
+        let value = &rest[start + 10..];
+        let end = value.find('"').unwrap_or(value.len());
+        let src = value[..end].replace("&amp;", "&");
+
+        out.push_str(&rest[..start + 10]);
+        rest = &value[end..];
+
+        let local = !src.contains("://") && !src.starts_with("data:");
+        let path = document.parent().unwrap_or(Path::new("")).join(&src);
+        let media_type = path
+            .extension()
+            .and_then(|extension| image_type(&extension.to_string_lossy().to_lowercase()));
+
+        let href = match (local, media_type) {
+            (true, Some(media_type)) => match images.get(&path) {
+                Some(href) => Some(href.clone()),
+                None => match fs::read(&path) {
+                    Ok(data) => {
+                        let extension = path.extension().unwrap().to_string_lossy();
+                        let href = format!("images/image-{}.{}", images.len() + 1, extension);
+
+                        resources.push(Resource {
+                            href: href.clone(),
+                            media_type,
+                            data: data.into(),
+                        });
+                        images.insert(path.clone(), href.clone());
+                        Some(href)
+                    }
+                    Err(err) => {
+                        eprintln!("warning: could not read image {}: {}", path.display(), err);
+                        None
+                    }
+                },
+            },
+            _ => None,
+        };
+
+        match href {
+            Some(href) => out.push_str(&href),
+            None => out.push_str(&value[..end]),
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+fn image_type(extension: &str) -> Option<&'static str> {
+    match extension {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "svg" => Some("image/svg+xml"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+// embeds the fonts of the config and makes the text use them, like in the pdf,
+// missing italics are left to the reader to synthesize
+fn font_faces(config: &Config, resources: &mut Vec<Resource>) -> String {
+    let fonts = &config.assets.fonts;
+    let roles: [(&str, Option<&FontFamily>, &str); 3] = [
+        ("body", Some(&fonts.body), ".markdown-body"),
+        (
+            "headings",
+            fonts.headings.as_ref(),
+            ".markdown-body h1, .markdown-body h2, .markdown-body h3, \
+             .markdown-body h4, .markdown-body h5, .markdown-body h6",
+        ),
+        (
+            "monospace",
+            fonts.monospace.as_ref(),
+            ".markdown-body code, .markdown-body kbd, .markdown-body pre",
+        ),
+    ];
+
+    let mut css = String::new();
+
+    for (role, family, selector) in roles {
+        let Some(family) = family else {
+            continue;
+        };
+
+        let faces = [
+            (Some(&family.regular), "normal", "normal"),
+            (Some(&family.bold), "bold", "normal"),
+            (family.italic.as_ref(), "normal", "italic"),
+            (family.bold_italic.as_ref(), "bold", "italic"),
+        ];
+
+        for (face, weight, style) in faces {
+            let Some(face) = face else {
+                continue;
+            };
+
+            let href = match resources.iter().find(|resource| resource.data == *face) {
+                Some(resource) => resource.href.clone(),
+                None => {
+                    let (extension, media_type) = match face.starts_with(b"OTTO") {
+                        true => ("otf", "font/otf"),
+                        false => ("ttf", "font/ttf"),
+                    };
+                    let number = resources
+                        .iter()
+                        .filter(|resource| resource.href.starts_with("fonts/"))
+                        .count()
+                        + 1;
+                    let href = format!("fonts/font-{}.{}", number, extension);
+
+                    resources.push(Resource {
+                        href: href.clone(),
+                        media_type,
+                        data: face.clone(),
+                    });
+                    href
+                }
+            };
+
+            css.push_str(&format!(
+                "@font-face {{ font-family: \"jamdr-{}\"; src: url({}); font-weight: {}; font-style: {}; }}\n",
+                role, href, weight, style
+            ));
+        }
+
+        // code keeps a monospace font when the reader drops embedded ones
+        let generic = match role {
+            "monospace" => ", monospace",
+            _ => "",
+        };
+
+        css.push_str(&format!(
+            "{} {{ font-family: \"jamdr-{}\"{}; }}\n",
+            selector, role, generic
+        ));
+    }
+
+    css
+}
+
+// the same chapters always get the same identifier, so readers keep their place
+// when a book is rebuilt
+fn identifier(chapters: &[Chapter]) -> String {
+    let mut hasher = DefaultHasher::new();
+
+    for chapter in chapters {
+        chapter.title.hash(&mut hasher);
+        chapter.file.hash(&mut hasher);
+    }
+
+    let hash = format!("{:016x}", hasher.finish());
+
+    format!(
+        "urn:jamdr:{}-{}-{}-{}",
+        &hash[..4],
+        &hash[4..8],
+        &hash[8..12],
+        &hash[12..]
+    )
+}
+
+// now in utc, like 2024-01-31T12:00:00Z
+fn timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (days, time) = (seconds / 86400, seconds % 86400);
+
+    // days since 1970-01-01 to a civil date, see Howard Hinnant's date algorithms
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+fn strip_tags(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut in_tag = false;
+
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::io::Read;
+    use zip::ZipArchive;
+
+    // every file in the book, by name, in the order they were written
+    fn unpack(documents: &[(&Path, &str)]) -> Vec<(String, String)> {
+        let book = Epub::render_book(documents, &testing::config()).unwrap();
+        let mut archive = ZipArchive::new(Cursor::new(book)).unwrap();
+
+        (0..archive.len())
+            .map(|i| {
+                let mut file = archive.by_index(i).unwrap();
+                let mut content = vec![];
+                file.read_to_end(&mut content).unwrap();
+                // fonts and images come out garbled, they aren't looked at
+                let content = String::from_utf8_lossy(&content).into_owned();
+                (file.name().to_string(), content)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_chapter_is_packaged_listed_and_linked() {
+        let files = unpack(&[
+            (Path::new("one.md"), "# One\n\ntext\n\n## Part\n\nmore\n"),
+            (
+                Path::new("two.md"),
+                "---\ntitle: Second\n---\nno headings\n",
+            ),
+        ]);
+        let file = |name: &str| {
+            files
+                .iter()
+                .find(|(file, _)| file == name)
+                .map(|(_, content)| content.as_str())
+                .unwrap_or_else(|| panic!("{} is missing", name))
+        };
+
+        assert_eq!(files[0], ("mimetype".into(), "application/epub+zip".into()));
+        assert!(file("META-INF/container.xml").contains("full-path=\"OEBPS/content.opf\""));
+
+        let opf = file("OEBPS/content.opf");
+        let nav = file("OEBPS/nav.xhtml");
+
+        for i in 1..=2 {
+            assert!(opf.contains(&format!(
+                "<item id=\"chapter-{0}\" href=\"chapter-{0}.xhtml\"",
+                i
+            )));
+            assert!(opf.contains(&format!("<itemref idref=\"chapter-{}\"/>", i)));
+        }
+
+        assert!(nav.contains("<a href=\"chapter-1.xhtml#heading-1\">One</a>"));
+        assert!(nav.contains("<a href=\"chapter-1.xhtml#heading-2\">Part</a>"));
+        assert!(nav.contains("<a href=\"chapter-2.xhtml\">Second</a>"));
+        assert!(file("OEBPS/chapter-1.xhtml").contains("<h2 id=\"heading-2\">Part</h2>"));
+        assert!(file("OEBPS/chapter-2.xhtml").contains("no headings"));
+    }
+}
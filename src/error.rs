@@ -0,0 +1,66 @@
+use std::fmt;
+use std::io;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Pdf(printpdf::Error),
+    Font(String),
+    Template(String),
+    Config(String),
+    Image(String),
+    Include(String),
+    // every document of a batch that failed, one per line with its path
+    Render(String),
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "io error: {}", err),
+            Error::Pdf(err) => write!(f, "pdf error: {}", err),
+            Error::Font(err) => write!(f, "font error: {}", err),
+            Error::Template(err) => write!(f, "template error: {}", err),
+            Error::Config(err) => write!(f, "config error: {}", err),
+            Error::Image(err) => write!(f, "image error: {}", err),
+            Error::Include(err) => write!(f, "include error: {}", err),
+            Error::Render(err) => write!(f, "{}", err),
+            Error::Unsupported(what) => write!(f, "unsupported: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<zip::result::ZipError> for Error {
+    fn from(err: zip::result::ZipError) -> Self {
+        Error::Io(err.into())
+    }
+}
+
+impl From<printpdf::Error> for Error {
+    fn from(err: printpdf::Error) -> Self {
+        Error::Pdf(err)
+    }
+}
+
+impl From<handlebars::TemplateError> for Error {
+    fn from(err: handlebars::TemplateError) -> Self {
+        Error::Template(err.to_string())
+    }
+}
+
+impl From<handlebars::RenderError> for Error {
+    fn from(err: handlebars::RenderError) -> Self {
+        Error::Template(err.to_string())
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rustybuzz::ttf_parser::{name_id, Face, Language};
+
+use crate::assets::FontFamily;
+use crate::error::{Error, Result};
+
+// a font file found on the system
+#[derive(Clone, Debug)]
+pub struct SystemFace {
+    pub path: PathBuf,
+    pub family: String,
+    // like "Bold Italic", as the font names it
+    pub style: String,
+    pub weight: u16,
+    // 1 (ultra condensed) to 9 (ultra expanded), 5 is normal
+    pub stretch: u16,
+    pub italic: bool,
+    pub monospace: bool,
+}
+
+// the fonts installed on the system, found by scanning the usual linux font directories,
+// collections (.ttc) are skipped since only the first face of a file can be embedded
+#[derive(Clone, Debug, Default)]
+pub struct SystemFonts {
+    pub faces: Vec<SystemFace>,
+}
+
+impl SystemFonts {
+    pub fn scan() -> SystemFonts {
+        SystemFonts::scan_dirs(&SystemFonts::dirs())
+    }
+
+    pub fn dirs() -> Vec<PathBuf> {
+        let mut dirs = vec![
+            PathBuf::from("/usr/share/fonts"),
+            PathBuf::from("/usr/local/share/fonts"),
+        ];
+
+        if let Some(data_dir) = dirs::data_dir() {
+            dirs.push(data_dir.join("fonts"));
+        }
+
+        if let Some(home_dir) = dirs::home_dir() {
+            dirs.push(home_dir.join(".fonts"));
+        }
+
+        dirs
+    }
+
+    // walks `dirs` recursively, files that can't be read or parsed are skipped
+    pub fn scan_dirs(dirs: &[PathBuf]) -> SystemFonts {
+        let mut faces = vec![];
+        let mut visited = HashSet::new();
+
+        for dir in dirs {
+            scan_dir(dir, &mut faces, &mut visited);
+        }
+
+        faces.sort_by(|a, b| {
+            (a.family.to_lowercase(), a.italic, a.weight, &a.path).cmp(&(
+                b.family.to_lowercase(),
+                b.italic,
+                b.weight,
+                &b.path,
+            ))
+        });
+
+        SystemFonts { faces }
+    }
+
+    // family names in alphabetical order, each with its faces
+    pub fn families(&self) -> Vec<(&str, Vec<&SystemFace>)> {
+        let mut families: Vec<(&str, Vec<&SystemFace>)> = vec![];
+
+        for face in &self.faces {
+            match families.last_mut() {
+                Some((family, faces)) if family.eq_ignore_ascii_case(&face.family) => {
+                    faces.push(face)
+                }
+                _ => families.push((&face.family, vec![face])),
+            }
+        }
+
+        families
+    }
+
+    // loads the faces of an installed family, the name is matched case insensitively,
+    // families without italics get them synthesized
+    pub fn family(&self, name: &str) -> Result<FontFamily> {
+        let faces: Vec<&SystemFace> = self
+            .faces
+            .iter()
+            .filter(|face| face.family.eq_ignore_ascii_case(name))
+            .collect();
+
+        // the face of the wanted slant closest to `weight` and normal width,
+        // bold faces have to be bold
+        let pick = |italic: bool, weight: u16| {
+            faces
+                .iter()
+                .filter(|face| face.italic == italic)
+                .filter(|face| weight < 600 || face.weight >= 600)
+                .min_by_key(|face| (face.weight.abs_diff(weight), face.stretch.abs_diff(5)))
+                .map(|face| fs::read(&face.path))
+                .transpose()
+        };
+
+        let regular = match pick(false, 400)? {
+            Some(regular) => regular,
+            // families that only come in italics
+            None => pick(true, 400)?
+                .ok_or_else(|| Error::Font(format!("no font family named \"{}\"", name)))?,
+        };
+        let bold = pick(false, 700)?;
+
+        Ok(FontFamily::new(
+            regular.clone(),
+            bold.unwrap_or(regular),
+            pick(true, 400)?,
+            pick(true, 700)?,
+        ))
+    }
+}
+
+// `visited` holds the canonical path of every directory scanned so far, symlinks
+// can lead back to one of them
+fn scan_dir(dir: &Path, faces: &mut Vec<SystemFace>, visited: &mut HashSet<PathBuf>) {
+    let Ok(canonical) = dir.canonicalize() else {
+        return;
+    };
+
+    if !visited.insert(canonical) {
+        return;
+    }
+
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+
+        if path.is_dir() {
+            scan_dir(&path, faces, visited);
+        } else if matches!(
+            path.extension()
+                .and_then(|extension| extension.to_str())
+                .map(|extension| extension.to_ascii_lowercase())
+                .as_deref(),
+            Some("ttf" | "otf")
+        ) {
+            if let Some(face) = read_face(path) {
+                faces.push(face);
+            }
+        }
+    }
+}
+
+fn read_face(path: PathBuf) -> Option<SystemFace> {
+    let data = fs::read(&path).ok()?;
+    let face = Face::parse(&data, 0).ok()?;
+
+    let name = |ids: [u16; 2]| {
+        ids.iter().find_map(|id| {
+            let mut names = face.names().into_iter().filter(|name| {
+                name.name_id == *id && name.is_unicode() && name.to_string().is_some()
+            });
+            let first = names.clone().next()?;
+
+            names
+                .find(|name| name.language() == Language::English_UnitedStates)
+                .unwrap_or(first)
+                .to_string()
+        })
+    };
+
+    Some(SystemFace {
+        family: name([name_id::TYPOGRAPHIC_FAMILY, name_id::FAMILY])?,
+        style: name([name_id::TYPOGRAPHIC_SUBFAMILY, name_id::SUBFAMILY])
+            .unwrap_or_else(|| "Regular".to_string()),
+        weight: face.weight().to_number(),
+        stretch: face.width().to_number(),
+        italic: face.is_italic() || face.is_oblique(),
+        monospace: face.is_monospaced(),
+        path,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[cfg(unix)]
+    #[test]
+    fn scanning_stops_at_symlink_loops() {
+        let dir = TempDir::new("fonts");
+        let font = dir.write(
+            "nested/Roboto.ttf",
+            fs::read("assets/fonts/Roboto-Regular.ttf").unwrap(),
+        );
+        std::os::unix::fs::symlink(&*dir, font.with_file_name("loop")).ok();
+
+        let fonts = SystemFonts::scan_dirs(&[dir.to_path_buf()]);
+
+        assert_eq!(fonts.faces.len(), 1);
+    }
+}
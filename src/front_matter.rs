@@ -0,0 +1,104 @@
+use printpdf::{Mm, Pt};
+use serde::Deserialize;
+
+use crate::css::parse_length;
+use crate::error::{Error, Result};
+use crate::render::{Direction, Hyphens, PageBreak, Style};
+
+// yaml metadata at the very top of a document, fenced by `---` lines, e.g.
+//
+// ---
+// title: Über Silbentrennung
+// author: Ada Muster
+// lang: de
+// dir: ltr
+// h1_break: right
+// columns: 2
+// column_gap: 8mm
+// asterisk_breaks: true
+// hyphenation: [Ur-instinkt, Ta-bel-le]
+// ---
+//
+// unknown keys are ignored, documents tend to carry metadata for other tools as well
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(default)]
+pub struct FrontMatter {
+    pub title: Option<String>,
+    // author, date and description only end up in the metadata of epub, docx, odt and tex files
+    pub author: Option<String>,
+    pub date: Option<String>,
+    pub description: Option<String>,
+    // a language tag like "en" or "de-CH"
+    pub lang: Option<String>,
+    // ltr, rtl or auto, decides the direction of paragraphs and the order of table
+    // columns, auto goes by the first strong character of each paragraph or table header
+    pub dir: Option<Direction>,
+    pub hyphens: Option<Hyphens>,
+    // auto, page or right, whether first level headings start a new page
+    pub h1_break: Option<PageBreak>,
+    pub columns: Option<usize>,
+    // a css length like "8mm" or "1.5em"
+    pub column_gap: Option<String>,
+    // draws a line between the columns, in the color of horizontal rules
+    pub column_rule: Option<bool>,
+    // `***` and `* * *` start a new page rather than drawing a rule
+    pub asterisk_breaks: Option<bool>,
+    // words with their allowed break points marked by `-`, take precedence over the patterns
+    pub hyphenation: Vec<String>,
+}
+
+impl FrontMatter {
+    // splits a document into its front matter and the markdown after it,
+    // documents without front matter get the defaults
+    pub fn split(document: &str) -> Result<(FrontMatter, &str)> {
+        let Some(rest) = document
+            .strip_prefix("---\n")
+            .or_else(|| document.strip_prefix("---\r\n"))
+        else {
+            return Ok((FrontMatter::default(), document));
+        };
+
+        let mut offset = 0;
+
+        for line in rest.split_inclusive('\n') {
+            if matches!(line.trim_end(), "---" | "...") {
+                let front_matter = match rest[..offset].trim() {
+                    "" => FrontMatter::default(),
+                    yaml => serde_yaml::from_str(yaml)
+                        .map_err(|err| Error::Config(format!("front matter: {}", err)))?,
+                };
+
+                return Ok((front_matter, &rest[offset + line.len()..]));
+            }
+
+            offset += line.len();
+        }
+
+        // an unterminated fence is a thematic break, not front matter
+        Ok((FrontMatter::default(), document))
+    }
+
+    // overrides the parts of `style` the front matter sets
+    pub fn apply(&self, style: &mut Style) -> Result<()> {
+        style.hyphens = self.hyphens.unwrap_or(style.hyphens);
+        style.direction = self.dir.unwrap_or(style.direction);
+        style.h1_break = self.h1_break.unwrap_or(style.h1_break);
+        style.columns = self.columns.unwrap_or(style.columns);
+        style.asterisk_breaks = self.asterisk_breaks.unwrap_or(style.asterisk_breaks);
+
+        if let Some(gap) = &self.column_gap {
+            let gap = parse_length(gap, style.font_size).ok_or_else(|| {
+                Error::Config(format!("front matter: invalid column_gap \"{}\"", gap))
+            })?;
+            style.column_gap = Mm::from(Pt(gap));
+        }
+
+        match self.column_rule {
+            Some(true) => style.column_rule = Some(style.rule_color.clone()),
+            Some(false) => style.column_rule = None,
+            None => {}
+        }
+
+        Ok(())
+    }
+}
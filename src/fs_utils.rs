@@ -1,23 +1,48 @@
-use dirs;
 use std::collections::HashMap;
-use std::env;
 use std::fs;
 use std::fs::read_to_string;
 use std::io;
-use std::path::PathBuf;
-use std::process;
+use std::path::{Path, PathBuf};
 
 pub fn write_files(files: &HashMap<PathBuf, Vec<u8>>) -> io::Result<()> {
     for (filename, data) in files {
-        match fs::write(&filename, data) {
-            Ok(_) => {}
-            Err(_) => (),
-        }
+        fs::write(filename, data)?;
     }
 
     Ok(())
 }
 
+// names the files rendered from `input` after `output`, a single file is written to
+// `output` as given, pages keep their number and extension, `doc-2.svg` becomes `out-2.svg`
+pub fn rename_outputs(
+    files: HashMap<PathBuf, Vec<u8>>,
+    input: &Path,
+    output: &Path,
+) -> HashMap<PathBuf, Vec<u8>> {
+    if files.len() == 1 {
+        return files
+            .into_values()
+            .map(|data| (output.to_path_buf(), data))
+            .collect();
+    }
+
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+    let output_stem = output.file_stem().unwrap_or_default().to_string_lossy();
+
+    files
+        .into_iter()
+        .map(|(path, data)| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let suffix = name.strip_prefix(stem.as_ref()).unwrap_or(&name);
+
+            (
+                output.with_file_name(format!("{}{}", output_stem, suffix)),
+                data,
+            )
+        })
+        .collect()
+}
+
 pub fn read_files(files: Vec<PathBuf>) -> Option<HashMap<PathBuf, String>> {
     let mut out_files = HashMap::new();
 
@@ -32,19 +57,3 @@ pub fn read_files(files: Vec<PathBuf>) -> Option<HashMap<PathBuf, String>> {
 
     Some(out_files)
 }
-
-pub fn try_create_config_dir() {
-    match env::consts::OS {
-        "linux" => {
-            let home_directory = dirs::home_dir().unwrap().join(".config/jamdr/");
-        }
-        "windows" => {}
-        "macos" => {}
-        _ => {
-            eprintln!("operating system not supported");
-            process::exit(1);
-        }
-    }
-}
-
-pub fn try_create_config_dir_linux() {}
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use hypher::Lang;
+
+use crate::render::Hyphens;
+
+const SOFT_HYPHEN: char = '\u{ad}';
+
+// a place inside a word where a line may end
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    // byte offset of the first character after the break
+    pub offset: usize,
+    // false after explicit hyphens, the word already has one
+    pub hyphen: bool,
+}
+
+// finds break points in words, from soft hyphens, the document's exception list
+// and liang's patterns, in that order of precedence
+#[derive(Clone, Debug)]
+pub struct Hyphenator {
+    hyphens: Hyphens,
+    lang: Option<Lang>,
+    // lowercased word -> offsets of its break points
+    exceptions: HashMap<String, Vec<usize>>,
+}
+
+impl Hyphenator {
+    // `lang` is a language tag like "en-GB", languages without patterns
+    // only break at soft hyphens and explicit hyphens
+    pub fn new(hyphens: Hyphens, lang: &str, exceptions: &[String]) -> Hyphenator {
+        let lang = lang
+            .get(..2)
+            .map(|code| code.to_ascii_lowercase())
+            .and_then(|code| Lang::from_iso(code.as_bytes().try_into().ok()?));
+
+        let exceptions = exceptions
+            .iter()
+            .map(|exception| {
+                let mut offsets = vec![];
+                let mut word = String::new();
+
+                for part in exception.split('-') {
+                    if !word.is_empty() {
+                        offsets.push(word.len());
+                    }
+
+                    word.push_str(&part.to_lowercase());
+                }
+
+                (word, offsets)
+            })
+            .collect();
+
+        Hyphenator {
+            hyphens,
+            lang,
+            exceptions,
+        }
+    }
+
+    // returns the word with its soft hyphens removed and the places it may be broken at
+    pub fn hyphenate(&self, word: &str) -> (String, Vec<Breakpoint>) {
+        if word.contains(SOFT_HYPHEN) {
+            let mut breakpoints = vec![];
+            let mut clean = String::with_capacity(word.len());
+
+            for part in word.split(SOFT_HYPHEN) {
+                if !clean.is_empty() && !part.is_empty() {
+                    breakpoints.push(Breakpoint {
+                        offset: clean.len(),
+                        hyphen: true,
+                    });
+                }
+
+                clean.push_str(part);
+            }
+
+            if self.hyphens == Hyphens::None {
+                breakpoints.clear();
+            }
+
+            return (clean, breakpoints);
+        }
+
+        if self.hyphens == Hyphens::None {
+            return (word.to_string(), vec![]);
+        }
+
+        // leading and trailing punctuation stays attached to the first and last syllable
+        let start = word.len() - word.trim_start_matches(|c: char| !c.is_alphabetic()).len();
+        let core = word.trim_matches(|c: char| !c.is_alphabetic());
+        let mut breakpoints = vec![];
+        let mut offset = start;
+
+        // compounds like "well-known" break after their hyphens, the parts are
+        // hyphenated on their own unless hyphenation is manual
+        for (i, part) in core.split('-').enumerate() {
+            if i > 0 && !part.is_empty() {
+                breakpoints.push(Breakpoint {
+                    offset,
+                    hyphen: false,
+                });
+            }
+
+            if self.hyphens == Hyphens::Auto {
+                breakpoints.extend(
+                    self.hyphenate_part(part)
+                        .into_iter()
+                        .map(|inner| Breakpoint {
+                            offset: offset + inner,
+                            hyphen: true,
+                        }),
+                );
+            }
+
+            offset += part.len() + 1;
+        }
+
+        (word.to_string(), breakpoints)
+    }
+
+    fn hyphenate_part(&self, part: &str) -> Vec<usize> {
+        if let Some(offsets) = self.exceptions.get(&part.to_lowercase()) {
+            // lowercasing can change byte lengths, only trust offsets that still fit
+            return offsets
+                .iter()
+                .copied()
+                .filter(|offset| part.is_char_boundary(*offset))
+                .collect();
+        }
+
+        let Some(lang) = self.lang else {
+            return vec![];
+        };
+
+        // numbers, urls and the like are left alone
+        if !part.chars().all(char::is_alphabetic) {
+            return vec![];
+        }
+
+        hypher::hyphenate(part, lang)
+            .scan(0, |offset, syllable| {
+                *offset += syllable.len();
+                Some(*offset)
+            })
+            .filter(|offset| *offset < part.len())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the word with a dash at every break point, `=` where it has a hyphen already
+    fn syllables(hyphenator: &Hyphenator, word: &str) -> String {
+        let (word, breakpoints) = hyphenator.hyphenate(word);
+        let mut out = word.clone();
+
+        for breakpoint in breakpoints.iter().rev() {
+            let mark = if breakpoint.hyphen { "-" } else { "=" };
+            out.insert_str(breakpoint.offset, mark);
+        }
+
+        out
+    }
+
+    #[test]
+    fn hyphenates_with_patterns() {
+        let hyphenator = Hyphenator::new(Hyphens::Auto, "en-US", &[]);
+
+        assert_eq!(syllables(&hyphenator, "hyphenation"), "hy-phen-ation");
+        assert_eq!(syllables(&hyphenator, "(hyphenation)."), "(hy-phen-ation).");
+    }
+
+    #[test]
+    fn exceptions_win_over_patterns() {
+        let exceptions = ["hyph-enation".to_string()];
+        let hyphenator = Hyphenator::new(Hyphens::Auto, "en", &exceptions);
+
+        assert_eq!(syllables(&hyphenator, "Hyphenation"), "Hyph-enation");
+    }
+
+    #[test]
+    fn compounds_break_after_their_hyphen() {
+        let hyphenator = Hyphenator::new(Hyphens::Auto, "en", &[]);
+
+        assert_eq!(syllables(&hyphenator, "well-known"), "well-=known");
+    }
+
+    #[test]
+    fn soft_hyphens_are_removed() {
+        let manual = Hyphenator::new(Hyphens::Manual, "en", &[]);
+        let none = Hyphenator::new(Hyphens::None, "en", &[]);
+
+        assert_eq!(syllables(&manual, "hy\u{ad}phen"), "hy-phen");
+        assert_eq!(syllables(&manual, "hyphenation"), "hyphenation");
+        assert_eq!(syllables(&manual, "well-known"), "well-=known");
+        assert_eq!(syllables(&none, "hy\u{ad}phen"), "hyphen");
+        assert_eq!(syllables(&none, "well-known"), "well-known");
+    }
+
+    #[test]
+    fn languages_without_patterns_are_left_alone() {
+        let hyphenator = Hyphenator::new(Hyphens::Auto, "xx", &[]);
+
+        assert_eq!(syllables(&hyphenator, "hyphenation"), "hyphenation");
+    }
+}
@@ -0,0 +1,522 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::front_matter::FrontMatter;
+use crate::util::slug;
+
+// other files pulled into a document before it is parsed, e.g.
+//
+// {{#include setup.md}}               another document, without its front matter
+// {{#include setup.md#linux}}         the section under the heading with that anchor
+// {{#include setup.md shift=1}}       with its headings a level deeper, or higher if negative
+// {{#include main.rs:10:20}}          lines 10 to 20, `:10` is just that line, `:10:` goes
+//                                     on to the end and `::20` starts at the first
+// ![[setup]] or ![[setup#Linux]]      a note embedded the way obsidian does it
+//
+// directives take a line of their own, paths are relative to the file they are in and
+// included documents can include others in turn
+//
+// anything but markdown goes into a code fence, unless the directive is in one already,
+// a backslash in front of `{{#include` keeps the directive as it is
+pub fn expand(document: &str, path: &Path) -> Result<String> {
+    let (_, markdown) = FrontMatter::split(document)?;
+    let front_matter = &document[..document.len() - markdown.len()];
+    let mut stack = vec![(canonical(path), path.to_path_buf())];
+
+    Ok(format!(
+        "{}{}",
+        front_matter,
+        expand_markdown(markdown, path, lines(front_matter) + 1, &mut stack)?
+    ))
+}
+
+// the path of every document being expanded, with the path it was included by,
+// from the outermost one in
+type Stack = Vec<(PathBuf, PathBuf)>;
+
+// `first` is the line of the file `markdown` starts at, errors point into the file
+fn expand_markdown(markdown: &str, path: &Path, first: usize, stack: &mut Stack) -> Result<String> {
+    let mut out = String::with_capacity(markdown.len());
+    let mut fence = Fence::default();
+
+    for (number, line) in markdown.split_inclusive('\n').enumerate() {
+        let in_code = fence.is_code(line);
+        let trimmed = line.trim();
+        let location = format!("{}:{}", path.display(), first + number);
+
+        if trimmed.starts_with("\\{{#include") {
+            out.push_str(&line.replacen("\\{{#include", "{{#include", 1));
+            continue;
+        }
+
+        let directive = Directive::parse(trimmed, in_code)
+            .map_err(|err| Error::Include(format!("{}: {}", location, err)))?;
+
+        match directive {
+            Some(directive) => out.push_str(&include(&directive, path, &location, in_code, stack)?),
+            None => out.push_str(line),
+        }
+    }
+
+    Ok(out)
+}
+
+struct Directive<'a> {
+    path: String,
+    anchor: Option<&'a str>,
+    // the first and last line, counting from 1, none goes on to the end
+    lines: Option<(usize, Option<usize>)>,
+    shift: i32,
+}
+
+impl<'a> Directive<'a> {
+    // obsidian embeds are left alone in code, they could be an example of themselves
+    fn parse(line: &'a str, in_code: bool) -> std::result::Result<Option<Directive<'a>>, String> {
+        if let Some(inner) = line
+            .strip_prefix("{{#include")
+            .and_then(|rest| rest.strip_suffix("}}"))
+        {
+            let mut words = inner.split_whitespace();
+            let Some(spec) = words.next() else {
+                return Err("include without a path".to_string());
+            };
+
+            let (spec, lines) = match spec.split_once(':') {
+                Some((spec, range)) => (spec, Some(line_range(range)?)),
+                None => (spec, None),
+            };
+            let (file, anchor) = match spec.split_once('#') {
+                Some((file, anchor)) => (file, Some(anchor)),
+                None => (spec, None),
+            };
+
+            let mut shift = 0;
+
+            for option in words {
+                match option.split_once('=') {
+                    Some(("shift", value)) => {
+                        shift = value
+                            .parse()
+                            .map_err(|_| format!("invalid heading shift \"{}\"", value))?
+                    }
+                    _ => return Err(format!("unknown include option \"{}\"", option)),
+                }
+            }
+
+            return Ok(Some(Directive {
+                path: file.to_string(),
+                anchor,
+                lines,
+                shift,
+            }));
+        }
+
+        let Some(inner) = line
+            .strip_prefix("![[")
+            .and_then(|rest| rest.strip_suffix("]]"))
+            .filter(|_| !in_code)
+        else {
+            return Ok(None);
+        };
+
+        // what comes after `|` is how obsidian labels the embed
+        let inner = inner.split('|').next().unwrap().trim();
+        let (note, anchor) = match inner.split_once('#') {
+            Some((note, anchor)) => (note, Some(anchor)),
+            None => (inner, None),
+        };
+
+        // embedded images and other files are for the renderers
+        let path = match Path::new(note).extension() {
+            None => format!("{}.md", note),
+            Some(_) if is_markdown(Path::new(note)) => note.to_string(),
+            Some(_) => return Ok(None),
+        };
+
+        Ok(Some(Directive {
+            path,
+            anchor,
+            lines: None,
+            shift: 0,
+        }))
+    }
+}
+
+fn line_range(range: &str) -> std::result::Result<(usize, Option<usize>), String> {
+    let number = |text: &str| match text.parse::<usize>() {
+        Ok(0) | Err(_) => Err(format!("invalid line range \"{}\"", range)),
+        Ok(number) => Ok(number),
+    };
+
+    let (first, last) = match range.split_once(':') {
+        None => (number(range)?, Some(number(range)?)),
+        Some(("", last)) => (1, Some(number(last)?)),
+        Some((first, "")) => (number(first)?, None),
+        Some((first, last)) => (number(first)?, Some(number(last)?)),
+    };
+
+    match last {
+        Some(last) if last < first => Err(format!("invalid line range \"{}\"", range)),
+        _ => Ok((first, last)),
+    }
+}
+
+fn include(
+    directive: &Directive,
+    path: &Path,
+    location: &str,
+    in_code: bool,
+    stack: &mut Stack,
+) -> Result<String> {
+    let target = path.parent().unwrap_or(Path::new("")).join(&directive.path);
+    let error = |message: String| Error::Include(format!("{}: {}", location, message));
+
+    let content = fs::read_to_string(&target)
+        .map_err(|err| error(format!("could not read {}: {}", target.display(), err)))?;
+    let markdown = is_markdown(&target);
+
+    // the line of the file `text` starts at
+    let mut first = 1;
+
+    let mut text = match markdown {
+        true => {
+            let (_, text) = FrontMatter::split(&content)
+                .map_err(|err| error(format!("{}: {}", target.display(), err)))?;
+            first += lines(&content[..content.len() - text.len()]);
+            text.to_string()
+        }
+        false => content.clone(),
+    };
+
+    if let Some(anchor) = directive.anchor {
+        if !markdown {
+            return Err(error(format!(
+                "{} is not markdown, it has no sections",
+                target.display()
+            )));
+        }
+
+        let (offset, found) = section(&text, anchor)
+            .ok_or_else(|| error(format!("{} has no heading #{}", target.display(), anchor)))?;
+        first += offset;
+        text = found;
+    }
+
+    if let Some((from, last)) = directive.lines {
+        let lines: Vec<&str> = text.split_inclusive('\n').collect();
+
+        if from > lines.len() {
+            return Err(error(format!(
+                "{} has no line {}, it ends at {}",
+                target.display(),
+                from,
+                lines.len()
+            )));
+        }
+
+        let last = last.unwrap_or(lines.len()).min(lines.len());
+        first += from - 1;
+        text = lines[from - 1..last].concat();
+    }
+
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+
+    // in code the document is shown as it is
+    if in_code {
+        return Ok(text);
+    }
+
+    if markdown {
+        let canonical = canonical(&target);
+
+        if let Some(start) = stack.iter().position(|(known, _)| *known == canonical) {
+            let cycle: Vec<String> = stack[start..]
+                .iter()
+                .map(|(_, path)| path.display().to_string())
+                .chain([target.display().to_string()])
+                .collect();
+
+            return Err(error(format!("include cycle {}", cycle.join(" -> "))));
+        }
+
+        stack.push((canonical, target.clone()));
+        let expanded = expand_markdown(&text, &target, first, stack)?;
+        stack.pop();
+
+        return Ok(shift_headings(&expanded, directive.shift));
+    }
+
+    let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat((longest + 1).max(3));
+
+    Ok(format!(
+        "{}{}\n{}{}\n",
+        fence,
+        language(&target),
+        text,
+        fence
+    ))
+}
+
+// the heading with that anchor or text, and everything up to the next heading
+// of the same or a higher level, with the number of lines before it
+fn section(markdown: &str, anchor: &str) -> Option<(usize, String)> {
+    let anchor = slug(anchor);
+    let mut fence = Fence::default();
+    let mut section: Option<(usize, String)> = None;
+    let mut offset = 0;
+
+    for (number, line) in markdown.split_inclusive('\n').enumerate() {
+        let heading = match fence.is_code(line) {
+            true => None,
+            false => atx_heading(line),
+        };
+
+        match (&mut section, heading) {
+            (Some((level, _)), Some((next, _))) if next <= *level => break,
+            (Some((_, text)), _) => text.push_str(line),
+            (None, Some((level, title))) if slug(title) == anchor => {
+                offset = number;
+                section = Some((level, line.to_string()))
+            }
+            (None, _) => {}
+        }
+    }
+
+    section.map(|(_, text)| (offset, text))
+}
+
+// moves every heading `shift` levels down, they stay between 1 and 6
+fn shift_headings(markdown: &str, shift: i32) -> String {
+    if shift == 0 {
+        return markdown.to_string();
+    }
+
+    let mut fence = Fence::default();
+    let mut out = String::with_capacity(markdown.len());
+
+    for line in markdown.split_inclusive('\n') {
+        match (fence.is_code(line), atx_heading(line)) {
+            (false, Some((level, _))) => {
+                let level = (level as i32 + shift).clamp(1, 6) as usize;
+                let rest = line.trim_start_matches(' ').trim_start_matches('#');
+                let indent = &line[..line.len() - line.trim_start_matches(' ').len()];
+
+                out.push_str(indent);
+                out.push_str(&"#".repeat(level));
+                out.push_str(rest);
+            }
+            _ => out.push_str(line),
+        }
+    }
+
+    out
+}
+
+// the level and text of a `#` heading
+fn atx_heading(line: &str) -> Option<(usize, &str)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    let trimmed = line.trim();
+    let text = trimmed.trim_start_matches('#');
+    let level = trimmed.len() - text.len();
+
+    if indent >= 4 || !(1..=6).contains(&level) {
+        return None;
+    }
+
+    if !text.is_empty() && !text.starts_with([' ', '\t']) {
+        return None;
+    }
+
+    Some((level, text.trim().trim_end_matches('#').trim_end()))
+}
+
+fn lines(text: &str) -> usize {
+    text.split_inclusive('\n').count()
+}
+
+fn is_markdown(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "md" || extension == "markdown")
+}
+
+// the info string of the code fence a source file goes into
+fn language(path: &Path) -> String {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "rs" => "rust",
+        "py" => "python",
+        "js" | "mjs" => "javascript",
+        "ts" => "typescript",
+        "sh" => "bash",
+        "yml" => "yaml",
+        "h" => "c",
+        "cc" | "hpp" => "cpp",
+        "txt" => "",
+        extension => extension,
+    }
+    .to_string()
+}
+
+// files that can't be resolved, like ones that don't exist, are told apart by path
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or(path.to_path_buf())
+}
+
+// follows fenced code blocks line by line, like page_break::mark
+#[derive(Default)]
+struct Fence {
+    open: Option<String>,
+}
+
+impl Fence {
+    // whether `line` is code, the fences included
+    fn is_code(&mut self, line: &str) -> bool {
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        let trimmed = line.trim();
+
+        if let Some(open) = &self.open {
+            if trimmed.starts_with(open.as_str())
+                && trimmed.trim_start_matches(&open[..1]).is_empty()
+            {
+                self.open = None;
+            }
+
+            return true;
+        }
+
+        if indent < 4 && (trimmed.starts_with("```") || trimmed.starts_with("~~~")) {
+            let marker = &trimmed[..1];
+            let length = trimmed.len() - trimmed.trim_start_matches(marker).len();
+            self.open = Some(trimmed[..length].to_string());
+            return true;
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    // writes the files to a directory of their own and expands the first, errors
+    // name the files relative to it
+    fn expand_files(name: &str, files: &[(&str, &str)]) -> Result<String> {
+        let dir = TempDir::new(&format!("include-{}", name));
+
+        for (file, content) in files {
+            dir.write(file, content);
+        }
+
+        let result = expand(files[0].1, &dir.join(files[0].0));
+
+        result.map_err(|err| match err {
+            Error::Include(message) => {
+                Error::Include(message.replace(&format!("{}/", dir.display()), ""))
+            }
+            err => err,
+        })
+    }
+
+    fn message(result: Result<String>) -> String {
+        match result {
+            Err(Error::Include(message)) => message,
+            result => panic!("expected an include error, got {:?}", result.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn cycles_are_reported() {
+        let result = expand_files(
+            "cycle",
+            &[("a.md", "{{#include b.md}}\n"), ("b.md", "# b\n\n![[a]]\n")],
+        );
+
+        assert!(message(result).ends_with("include cycle a.md -> b.md -> a.md"));
+    }
+
+    #[test]
+    fn line_ranges_are_cut_out() {
+        let source = "one\ntwo\nthree\nfour\n";
+        let expanded = expand_files(
+            "lines",
+            &[
+                (
+                    "doc.md",
+                    "{{#include code.rs:2:3}}\n{{#include code.rs::1}}\n{{#include code.rs:4:}}\n",
+                ),
+                ("code.rs", source),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            expanded,
+            "```rust\ntwo\nthree\n```\n```rust\none\n```\n```rust\nfour\n```\n"
+        );
+    }
+
+    #[test]
+    fn sections_end_at_the_next_heading_of_their_level() {
+        let setup = "---\ntitle: setup\n---\n# Setup\n\n## Linux\n\napt\n\n### Debian\n\ndeb\n\n## Mac\n\nbrew\n";
+        let expanded = expand_files(
+            "section",
+            &[
+                ("doc.md", "{{#include setup.md#linux shift=1}}\n"),
+                ("setup.md", setup),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(expanded, "### Linux\n\napt\n\n#### Debian\n\ndeb\n\n");
+    }
+
+    #[test]
+    fn errors_point_at_lines_of_the_original_file() {
+        let located = |name: &str, files: &[(&str, &str)]| {
+            let message = message(expand_files(name, files));
+            message.split(": ").next().unwrap().to_string()
+        };
+
+        // after the front matter of the document itself
+        assert_eq!(
+            located(
+                "front",
+                &[("doc.md", "---\ntitle: doc\n---\ntext\n{{#include}}\n")]
+            ),
+            "doc.md:5"
+        );
+
+        // in an included section, after its front matter
+        let notes = "---\ntitle: notes\n---\n# One\n\n# Two\n\n{{#include gone.md}}\n";
+        assert_eq!(
+            located(
+                "nested",
+                &[("doc.md", "![[notes#two]]\n"), ("notes.md", notes)]
+            ),
+            "notes.md:8"
+        );
+
+        // in a range of lines
+        let notes = "one\n\ntwo\n\n{{#include gone.md}}\n";
+        assert_eq!(
+            located(
+                "range",
+                &[
+                    ("doc.md", "{{#include notes.md:3:}}\n"),
+                    ("notes.md", notes)
+                ]
+            ),
+            "notes.md:5"
+        );
+    }
+}
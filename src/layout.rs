@@ -0,0 +1,2735 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use printpdf::{Color, Pt};
+use pulldown_cmark::{Alignment, Event, HeadingLevel, Tag};
+use unicode_bidi::{BidiInfo, Level};
+
+use crate::assets::FontData;
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::front_matter::FrontMatter;
+use crate::hyphenation::Hyphenator;
+use crate::linebreak::{self, Item, INFINITY};
+use crate::page_break;
+use crate::render::{extract, Direction, HeaderUnderline, PageBreak, Style, TextAlign};
+use crate::shaping::{self, ShapedRun};
+use crate::util;
+use crate::walk;
+
+// a document broken into pages of positioned text runs and lines, which the writers
+// paint without knowing anything about markdown
+//
+// positions are in points from the bottom left corner of the page, like in pdf
+pub struct Layout {
+    pub title: String,
+    pub width: Pt,
+    pub height: Pt,
+    // painted behind every page, none leaves the pages transparent
+    pub background: Option<Color>,
+    // every face the runs are drawn with
+    pub faces: Vec<Arc<[u8]>>,
+    pub pages: Vec<Page>,
+    // characters none of the fonts could draw
+    pub missing_glyphs: BTreeSet<char>,
+    // bookmarks in document order, only books have them
+    pub outline: Vec<OutlineItem>,
+    // the headings of a document by their anchor, for links to them, books link
+    // to their headings directly
+    pub destinations: Vec<Destination>,
+}
+
+// where the heading with the anchor `name` ended up
+pub struct Destination {
+    pub name: String,
+    pub page: usize,
+    pub y: Pt,
+}
+
+// an entry of the pdf outline, chapters are on level 0 and headings below them
+pub struct OutlineItem {
+    pub title: String,
+    pub level: usize,
+    pub page: usize,
+    pub y: Pt,
+}
+
+#[derive(Default)]
+pub struct Page {
+    pub elements: Vec<Element>,
+}
+
+pub enum Element {
+    Text(TextRun),
+    Line {
+        from: (Pt, Pt),
+        to: (Pt, Pt),
+        width: Pt,
+        color: Color,
+    },
+    // a clickable area, given by its bottom left and top right corners
+    Link {
+        from: (Pt, Pt),
+        to: (Pt, Pt),
+        url: String,
+    },
+    // a clickable area that jumps to `y` on another page of the document
+    PageLink {
+        from: (Pt, Pt),
+        to: (Pt, Pt),
+        page: usize,
+        y: Pt,
+    },
+    // a filled area below the text, given by its bottom left and top right corners
+    Rect {
+        from: (Pt, Pt),
+        to: (Pt, Pt),
+        color: Color,
+    },
+    // a picture stretched over the area between its bottom left and top right corners
+    Image {
+        from: (Pt, Pt),
+        to: (Pt, Pt),
+        image: Arc<Image>,
+    },
+}
+
+// a png file and its pixels, in rgba without premultiplied alpha
+pub struct Image {
+    pub data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    // png is the one format every writer can paint without another decoder
+    fn decode(data: Vec<u8>) -> Option<Image> {
+        let pixmap = tiny_skia::Pixmap::decode_png(&data).ok()?;
+        let pixels = pixmap
+            .pixels()
+            .iter()
+            .flat_map(|pixel| {
+                let color = pixel.demultiply();
+                [color.red(), color.green(), color.blue(), color.alpha()]
+            })
+            .collect();
+
+        Some(Image {
+            width: pixmap.width(),
+            height: pixmap.height(),
+            pixels,
+            data,
+        })
+    }
+}
+
+pub struct TextRun {
+    // start of the run on the baseline
+    pub x: Pt,
+    pub y: Pt,
+    pub run: ShapedRun,
+    // the index in Layout::faces of every face the run was shaped with,
+    // in the order ShapedGlyph::font counts them
+    pub faces: Vec<usize>,
+    pub color: Color,
+    // horizontal skew of synthesized italics, 0 for upright text
+    pub slant: f32,
+}
+
+// lays out a markdown document with the style, fonts and language of `config`, the title
+// is the file name, front matter overrides it and the language and hyphenation
+//
+// images are looked up next to the file
+pub fn layout_document(markdown: &str, path: &Path, config: &Config) -> Result<Layout> {
+    let (front_matter, markdown) = FrontMatter::split(markdown)?;
+
+    let mut style = config.style.clone();
+    front_matter.apply(&mut style)?;
+    let markdown = page_break::mark(markdown, style.asterisk_breaks);
+
+    let hyphenator = Hyphenator::new(
+        style.hyphens,
+        front_matter.lang.as_deref().unwrap_or(&config.lang),
+        &front_matter.hyphenation,
+    );
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let title = front_matter.title.as_deref().unwrap_or(&file_name);
+
+    let mut layouter = Layouter::new(&markdown, title, &config.assets.fonts, &style, hyphenator)?;
+    layouter.base = base_dir(path);
+    let layout = layouter.layout();
+    warn_missing_glyphs(&layout);
+
+    Ok(layout)
+}
+
+// lays out documents as the chapters of one book, each after a title page of its own,
+// behind a table of contents and with the pages numbered through
+//
+// the front matter of the first chapter styles the whole book, links to other chapters
+// and to headings jump to them
+pub fn layout_book(
+    chapters: &[(&Path, &str)],
+    title: Option<&str>,
+    config: &Config,
+) -> Result<Layout> {
+    let Some((_, first)) = chapters.first() else {
+        return Err(Error::Config(
+            "a book needs at least one chapter".to_string(),
+        ));
+    };
+
+    let (front_matter, _) = FrontMatter::split(first)?;
+    let mut style = config.style.clone();
+    front_matter.apply(&mut style)?;
+
+    let mut parts = vec![];
+
+    for (path, document) in chapters {
+        let (front_matter, markdown) = FrontMatter::split(document)?;
+        let asterisks = front_matter
+            .asterisk_breaks
+            .unwrap_or(style.asterisk_breaks);
+        let markdown = page_break::mark(markdown, asterisks);
+        let title = chapter_title(&front_matter, &markdown, path);
+
+        parts.push((front_matter, markdown, title));
+    }
+
+    let hyphenator = |front_matter: &FrontMatter| {
+        Hyphenator::new(
+            style.hyphens,
+            front_matter.lang.as_deref().unwrap_or(&config.lang),
+            &front_matter.hyphenation,
+        )
+    };
+    let title = title.unwrap_or(&parts[0].2);
+
+    let mut layouter = Layouter::new(
+        "",
+        title,
+        &config.assets.fonts,
+        &style,
+        hyphenator(&front_matter),
+    )?;
+    layouter.book = Some(BookState {
+        paths: chapters
+            .iter()
+            .map(|(path, _)| path.canonicalize().unwrap_or(path.to_path_buf()))
+            .collect(),
+        ..BookState::default()
+    });
+
+    for ((path, _), (front_matter, markdown, title)) in chapters.iter().zip(&parts) {
+        layouter.base = base_dir(path);
+        layouter.chapter(title, markdown, hyphenator(front_matter));
+    }
+
+    let layout = layouter.finish_book();
+    warn_missing_glyphs(&layout);
+
+    Ok(layout)
+}
+
+// the front matter title, or else the text of the first heading, or the file name
+fn chapter_title(front_matter: &FrontMatter, markdown: &str, path: &Path) -> String {
+    if let Some(title) = &front_matter.title {
+        return title.clone();
+    }
+
+    let heading: String = walk::parse(markdown)
+        .into_iter()
+        .skip_while(|event| !matches!(event, Event::Start(Tag::Heading(..))))
+        .take_while(|event| !matches!(event, Event::End(Tag::Heading(..))))
+        .filter_map(|event| match event {
+            Event::Text(text) | Event::Code(text) => Some(text.to_string()),
+            _ => None,
+        })
+        .collect();
+
+    match heading.trim() {
+        "" => path.file_stem().unwrap().to_string_lossy().into_owned(),
+        heading => heading.to_string(),
+    }
+}
+
+// the directory relative paths in the document at `path` start from
+fn base_dir(path: &Path) -> PathBuf {
+    path.parent().unwrap_or(Path::new("")).to_path_buf()
+}
+
+fn warn_missing_glyphs(layout: &Layout) {
+    if !layout.missing_glyphs.is_empty() {
+        let missing: Vec<String> = layout
+            .missing_glyphs
+            .iter()
+            .map(|c| format!("{} (U+{:04X})", c, *c as u32))
+            .collect();
+
+        eprintln!(
+            "warning: {}: no font has glyphs for {}, add a fallback font with --fallback-font",
+            layout.title,
+            missing.join(", ")
+        );
+    }
+}
+
+// walks the markdown events and places what they stand for on pages
+struct Layouter<'a> {
+    markdown: Vec<Event<'a>>,
+    position: usize,
+    title: String,
+    // it makes more sense to store page dimensions in millimeters,
+    // we however store the position in points, as it makes line height and certain
+    // formatting calculations easier
+    page_position: (Pt, Pt),
+    // list depth: if entry is none, list is bulleted, if entry is some, list is numbered
+    list_depth: Vec<Option<u64>>,
+    // how far blockquotes and nested lists move text in from the side lines start on
+    indent: Pt,
+    quotes: Vec<Quote>,
+    // footnote labels, numbered in the order they first come up
+    footnotes: HashMap<String, usize>,
+    // the directory images are looked up in
+    base: PathBuf,
+    pages: Vec<Page>,
+    // the column text goes into, and the baseline of the first line of the columns
+    // on this page, which starts below elements that span all of them
+    column: usize,
+    column_top: Pt,
+    spanning: bool,
+    font: Font,
+    hyphenator: Hyphenator,
+    // characters none of the fonts could draw
+    missing_glyphs: BTreeSet<char>,
+    // room taken by a list bullet on the right of a right to left item
+    right_inset: Pt,
+    // room the next paragraph keeps free below its last line, see page_breaks
+    keep_with_next: Pt,
+    style: Style,
+    // the anchors of the document or current chapter, with how often each came up
+    anchors: HashMap<String, usize>,
+    destinations: Vec<Destination>,
+    // set while laying out a book, see layout_book
+    book: Option<BookState>,
+}
+
+// what a book keeps track of across its chapters
+#[derive(Default)]
+struct BookState {
+    // canonical paths of the chapters, links to them jump into the book
+    paths: Vec<PathBuf>,
+    // the title and title page of every chapter so far, the last one is being laid out
+    titles: Vec<(String, usize)>,
+    headings: Vec<Heading>,
+    links: Vec<PendingLink>,
+}
+
+// a heading of a book and where it ended up
+struct Heading {
+    title: String,
+    level: usize,
+    anchor: String,
+    chapter: usize,
+    page: usize,
+    y: Pt,
+}
+
+// a link drawn as element `index` of `page`, pointing at an anchor of a chapter, the empty
+// anchor stands for the chapter itself
+struct PendingLink {
+    page: usize,
+    index: usize,
+    chapter: usize,
+    anchor: String,
+}
+
+// a blockquote being laid out, its bar is drawn one column at a time
+struct Quote {
+    // distance of the bar from the side of the column
+    inset: Pt,
+    // where the bar starts in the current column
+    top: Pt,
+}
+
+impl BookState {
+    // the chapter and anchor a link points at, if it stays inside the book
+    fn target(&self, url: &str) -> Option<(usize, String)> {
+        if util::is_absolute_url(url) {
+            return None;
+        }
+
+        let current = self.titles.len() - 1;
+        let (file, anchor) = url.split_once('#').unwrap_or((url, ""));
+        let chapter = match file {
+            "" => current,
+            file => {
+                let path = self.paths[current]
+                    .parent()?
+                    .join(file)
+                    .canonicalize()
+                    .ok()?;
+                self.paths.iter().position(|known| *known == path)?
+            }
+        };
+
+        Some((chapter, anchor.to_string()))
+    }
+}
+
+// columns have to leave some room for text between the gaps
+fn check_columns(style: &Style) -> Result<()> {
+    if style.columns == 0 {
+        return Err(Error::Config("columns has to be at least 1".to_string()));
+    }
+
+    let width = (style.width - style.horizontal_padding * 2.0).into_pt();
+    let gaps = style.column_gap.into_pt() * (style.columns - 1) as f32;
+
+    if width <= gaps {
+        return Err(Error::Config(format!(
+            "{} columns with a gap of {}mm leave no room for text",
+            style.columns, style.column_gap.0
+        )));
+    }
+
+    Ok(())
+}
+
+impl<'a> Layouter<'a> {
+    fn new(
+        markdown: &'a str,
+        title: &str,
+        fonts: &FontData,
+        style: &Style,
+        hyphenator: Hyphenator,
+    ) -> Result<Layouter<'a>> {
+        check_columns(style)?;
+
+        let mut layouter = Layouter {
+            markdown: walk::parse(markdown),
+            position: 0,
+            title: title.to_string(),
+            page_position: (Pt(0.0), Pt(0.0)),
+            list_depth: vec![],
+            indent: Pt(0.0),
+            quotes: vec![],
+            footnotes: HashMap::new(),
+            base: PathBuf::new(),
+            pages: vec![],
+            column: 0,
+            column_top: Pt(0.0),
+            spanning: false,
+            font: Font::new(fonts, style)?,
+            hyphenator,
+            missing_glyphs: BTreeSet::new(),
+            right_inset: Pt(0.0),
+            keep_with_next: Pt(0.0),
+            style: style.clone(),
+            anchors: HashMap::new(),
+            destinations: vec![],
+            book: None,
+        };
+        layouter.new_page();
+
+        Ok(layouter)
+    }
+
+    fn layout(mut self) -> Layout {
+        self.run();
+        self.draw_column_rules();
+        self.finish()
+    }
+
+    fn run(&mut self) {
+        while !self.is_at_end() {
+            self.render();
+        }
+    }
+
+    fn finish(self) -> Layout {
+        Layout {
+            title: self.title,
+            width: self.style.width.into_pt(),
+            height: self.style.height.into_pt(),
+            background: self.style.background_color,
+            faces: self.font.faces,
+            pages: self.pages,
+            missing_glyphs: self.missing_glyphs,
+            outline: vec![],
+            destinations: self.destinations,
+        }
+    }
+
+    // lays out the next chapter of a book on the pages after its title page
+    fn chapter(&mut self, title: &str, markdown: &'a str, hyphenator: Hyphenator) {
+        self.break_before(PageBreak::Page);
+        self.title_page(title);
+
+        let book = self.book.as_mut().unwrap();
+        book.titles.push((title.to_string(), self.pages.len() - 1));
+        self.anchors.clear();
+
+        self.new_page();
+        self.markdown = walk::parse(markdown);
+        self.position = 0;
+        self.hyphenator = hyphenator;
+        self.run();
+    }
+
+    // the chapter title, centered a third down the page
+    fn title_page(&mut self, title: &str) {
+        self.start_span();
+        self.page_position = (self.left(), self.top() - (self.top() - self.bottom()) / 3.0);
+
+        self.font.role = FontRole::Headings;
+        self.font.is_bold = true;
+        self.font.current_size = self.font.heading_sizes[0] * 1.5;
+
+        let text_align = std::mem::replace(&mut self.style.text_align, TextAlign::Center);
+        self.render_spans(vec![Span {
+            text: title.to_string(),
+            variant: self.font.variant(),
+            strikethrough: false,
+            size: self.font.current_size,
+            link: None,
+        }]);
+        self.style.text_align = text_align;
+
+        self.line_break();
+        self.end_span();
+    }
+
+    // resolves the links between chapters and puts the table of contents in front,
+    // every page but the title pages gets its number
+    fn finish_book(mut self) -> Layout {
+        self.draw_column_rules();
+
+        let book = self.book.take().unwrap();
+        self.resolve_links(&book);
+
+        let mut outline = vec![];
+
+        for (chapter, (title, page)) in book.titles.iter().enumerate() {
+            outline.push(OutlineItem {
+                title: title.clone(),
+                level: 0,
+                page: *page,
+                y: self.style.height.into_pt(),
+            });
+
+            let headings = book.headings.iter().filter(|h| h.chapter == chapter);
+
+            // the first heading tends to be the title again
+            for (i, heading) in headings.enumerate() {
+                if i == 0 && heading.title == *title {
+                    continue;
+                }
+
+                outline.push(OutlineItem {
+                    title: heading.title.clone(),
+                    level: heading.level,
+                    page: heading.page,
+                    y: heading.y,
+                });
+            }
+        }
+
+        // the number of pages the contents take doesn't depend on the page numbers in it
+        let contents: Vec<&OutlineItem> = outline.iter().filter(|item| item.level <= 2).collect();
+        let offset = self.table_of_contents(&contents, 0).len();
+        let contents = self.table_of_contents(&contents, offset);
+
+        for page in &mut self.pages {
+            for element in &mut page.elements {
+                if let Element::PageLink { page, .. } = element {
+                    *page += offset;
+                }
+            }
+        }
+
+        for item in &mut outline {
+            item.page += offset;
+        }
+
+        self.pages.splice(0..0, contents);
+
+        let title_pages: Vec<usize> = book.titles.iter().map(|(_, page)| page + offset).collect();
+        self.number_pages(&title_pages);
+
+        let mut layout = self.finish();
+        layout.outline = outline;
+        layout
+    }
+
+    // links into the book jump to the chapter or heading they point at, the others
+    // stay links to their url
+    fn resolve_links(&mut self, book: &BookState) {
+        for link in &book.links {
+            let destination = match link.anchor.as_str() {
+                "" => Some((book.titles[link.chapter].1, self.style.height.into_pt())),
+                anchor => book
+                    .headings
+                    .iter()
+                    .find(|heading| heading.chapter == link.chapter && heading.anchor == anchor)
+                    .map(|heading| (heading.page, heading.y)),
+            };
+
+            let Some((page, y)) = destination else {
+                continue;
+            };
+
+            let element = &mut self.pages[link.page].elements[link.index];
+
+            if let Element::Link { from, to, .. } = element {
+                let (from, to) = (*from, *to);
+                *element = Element::PageLink { from, to, page, y };
+            }
+        }
+    }
+
+    // the table of contents on pages of their own, the pages of the entries move
+    // `offset` pages back once it is put in front of them
+    fn table_of_contents(&mut self, entries: &[&OutlineItem], offset: usize) -> Vec<Page> {
+        let pages = std::mem::take(&mut self.pages);
+        self.new_page();
+        self.spanning = true;
+
+        self.font.role = FontRole::Headings;
+        self.font.is_bold = true;
+        self.font.current_size = self.font.heading_sizes[0];
+        self.render_spans(vec![Span {
+            text: "Contents".to_string(),
+            variant: self.font.variant(),
+            strikethrough: false,
+            size: self.font.current_size,
+            link: None,
+        }]);
+
+        if self.style.underline_headings == HeaderUnderline::FullPage {
+            self.draw_line(
+                self.left(),
+                self.right(),
+                LineLocation::Underline,
+                self.style.rule_color.clone(),
+            );
+        }
+
+        self.line_break();
+
+        for entry in entries {
+            self.contents_entry(entry, offset);
+        }
+
+        self.spanning = false;
+        std::mem::replace(&mut self.pages, pages)
+    }
+
+    // one line with the title on the left and the page number on the right, titles
+    // that don't fit are cut short
+    fn contents_entry(&mut self, entry: &OutlineItem, offset: usize) {
+        let size = self.font.regular_size;
+        let height = Pt(size * self.font.line_height_scale);
+
+        // chapters get some room above them
+        if entry.level == 0 && self.page_position.1 < self.top() {
+            self.next_line(height * 0.5);
+        }
+
+        if self.page_position.1 < self.bottom() {
+            self.new_page();
+        }
+
+        let variant = Variant {
+            role: FontRole::Body,
+            bold: entry.level == 0,
+            italic: false,
+        };
+        let page = entry.page + offset;
+        let number = self
+            .font
+            .shape_variant(variant, size, &(page + 1).to_string(), false);
+
+        let left = self.left() + Pt(size * 1.5 * entry.level as f32);
+        let right = self.right();
+        let number_x = right - number.width();
+
+        let rtl = self.is_rtl(&entry.title);
+        let mut title = entry.title.clone();
+        let mut run = self.font.shape_variant(variant, size, &title, rtl);
+
+        while run.width() > number_x - left - Pt(size) && title.pop().is_some() {
+            let shortened = format!("{}…", title.trim_end());
+            run = self.font.shape_variant(variant, size, &shortened, rtl);
+        }
+
+        let y = self.page_position.1;
+        self.push_run(run, variant, left, None);
+        self.push_run(number, variant, number_x, None);
+        self.push(Element::PageLink {
+            from: (left, y - Pt(size * 0.25)),
+            to: (right, y + Pt(size)),
+            page,
+            y: entry.y,
+        });
+
+        self.next_line(height);
+    }
+
+    // numbers at the bottom of every page, counting through the whole document
+    fn number_pages(&mut self, skipped: &[usize]) {
+        let variant = Variant {
+            role: FontRole::Body,
+            bold: false,
+            italic: false,
+        };
+        let size = self.font.regular_size;
+        let faces = self.font.run_faces(variant);
+        // in the middle of the margin
+        let y = self.bottom() / 2.0 - Pt(size * 0.25);
+        let width = self.style.width.into_pt();
+
+        for (i, page) in self.pages.iter_mut().enumerate() {
+            if skipped.contains(&i) {
+                continue;
+            }
+
+            let run = self
+                .font
+                .shape_variant(variant, size, &(i + 1).to_string(), false);
+
+            page.elements.push(Element::Text(TextRun {
+                x: (width - run.width()) / 2.0,
+                y,
+                run,
+                faces: faces.clone(),
+                color: self.style.text_color.clone(),
+                slant: 0.0,
+            }));
+        }
+    }
+
+    // where a heading ended up, for links to it and the outline of a book
+    fn record_heading(&mut self, level: HeadingLevel, title: String) {
+        let page = self.pages.len() - 1;
+        let y = self.page_position.1 + Pt(self.font.current_size);
+        let anchor = self.anchor(&title);
+
+        let Some(book) = &mut self.book else {
+            self.destinations.push(Destination {
+                name: anchor,
+                page,
+                y,
+            });
+            return;
+        };
+
+        book.headings.push(Heading {
+            title: title.trim().to_string(),
+            level: level as usize,
+            anchor,
+            chapter: book.titles.len() - 1,
+            page,
+            y,
+        });
+    }
+
+    // like github, repeated anchors get numbered
+    fn anchor(&mut self, title: &str) -> String {
+        let anchor = util::slug(title);
+
+        let count = self.anchors.entry(anchor.clone()).or_insert(0);
+        *count += 1;
+
+        match *count {
+            1 => anchor,
+            count => format!("{}-{}", anchor, count - 1),
+        }
+    }
+
+    // the cursor starts at the top left of the new page
+    fn new_page(&mut self) {
+        if !self.pages.is_empty() {
+            self.draw_quote_bars();
+            self.draw_column_rules();
+        }
+
+        self.pages.push(Page::default());
+        self.column = 0;
+        self.column_top = self.top();
+        self.page_position = (self.left(), self.top());
+        self.continue_quote_bars();
+    }
+
+    fn push(&mut self, element: Element) {
+        self.pages.last_mut().unwrap().elements.push(element);
+    }
+
+    // baselines of text lines stay between these
+    fn top(&self) -> Pt {
+        (self.style.height - self.style.vertical_padding).into_pt()
+    }
+
+    fn bottom(&self) -> Pt {
+        self.style.vertical_padding.into_pt()
+    }
+
+    // the number of columns text currently flows through
+    fn column_count(&self) -> usize {
+        match self.spanning {
+            true => 1,
+            false => self.style.columns,
+        }
+    }
+
+    // the left and right edge of the current column, or of the text area
+    // while spanning all of them, indented on the side lines start on
+    fn left(&self) -> Pt {
+        let start = self.column_edges(self.column).0;
+
+        match self.style.direction {
+            Direction::Rtl => start,
+            _ => start + self.indent,
+        }
+    }
+
+    fn right(&self) -> Pt {
+        let (start, width) = self.column_edges(self.column);
+
+        match self.style.direction {
+            Direction::Rtl => start + width - self.indent,
+            _ => start + width,
+        }
+    }
+
+    // how far a line of body text reaches above and below its baseline, blocks are
+    // shaded and ruled along these
+    fn line_extent(&self) -> (Pt, Pt) {
+        let height = self.font.regular_size * self.font.line_height_scale;
+        (Pt(height * 0.75), Pt(height * 0.25))
+    }
+
+    fn line_top(&self, baseline: Pt) -> Pt {
+        baseline + self.line_extent().0
+    }
+
+    fn line_bottom(&self, baseline: Pt) -> Pt {
+        baseline - self.line_extent().1
+    }
+
+    fn column_edges(&self, column: usize) -> (Pt, Pt) {
+        let start = self.style.horizontal_padding.into_pt();
+        let width = (self.style.width - self.style.horizontal_padding * 2.0).into_pt();
+        let columns = self.column_count();
+        let gap = self.style.column_gap.into_pt();
+        let column_width = (width - gap * (columns - 1) as f32) / columns as f32;
+
+        (start + (column_width + gap) * column as f32, column_width)
+    }
+
+    // continues at the top of the next column, or on the next page after the last one
+    fn next_column(&mut self) {
+        if self.column + 1 >= self.column_count() {
+            self.new_page();
+            return;
+        }
+
+        self.draw_quote_bars();
+        self.column += 1;
+        self.page_position = (self.left(), self.column_top);
+        self.continue_quote_bars();
+    }
+
+    // the bars of the open blockquotes, down to the bottom of the column
+    fn draw_quote_bars(&mut self) {
+        let bottom = self.line_bottom(self.bottom());
+        let bars: Vec<Element> = self
+            .quotes
+            .iter()
+            .map(|quote| self.quote_bar(quote, bottom))
+            .collect();
+
+        for bar in bars {
+            self.push(bar);
+        }
+    }
+
+    // open blockquotes go on at the top of the new column
+    fn continue_quote_bars(&mut self) {
+        let top = self.line_top(self.column_top);
+
+        for quote in &mut self.quotes {
+            quote.top = top;
+        }
+    }
+
+    fn quote_bar(&self, quote: &Quote, bottom: Pt) -> Element {
+        let (start, width) = self.column_edges(self.column);
+        let x = match self.style.direction {
+            Direction::Rtl => start + width - quote.inset,
+            _ => start + quote.inset,
+        };
+
+        Element::Line {
+            from: (x, quote.top),
+            to: (x, bottom),
+            width: Pt(QUOTE_BAR),
+            color: self.style.rule_color.clone(),
+        }
+    }
+
+    // lays out what follows over the full width, below the columns used so far,
+    // or on a new page if they went past the first one
+    fn start_span(&mut self) {
+        if self.column_count() == 1 {
+            return;
+        }
+
+        if self.column > 0 {
+            self.new_page();
+        }
+
+        self.spanning = true;
+    }
+
+    // columns start again below the spanning element
+    fn end_span(&mut self) {
+        if !self.spanning {
+            return;
+        }
+
+        self.spanning = false;
+        self.column = 0;
+        self.column_top = self.page_position.1;
+    }
+
+    // rules between the columns of the current page, the columns before the current
+    // one are filled down to the bottom
+    fn draw_column_rules(&mut self) {
+        let Some(color) = self.style.column_rule.clone() else {
+            return;
+        };
+
+        let gap = self.style.column_gap.into_pt();
+        let top = self.column_top + Pt(self.font.regular_size);
+
+        for column in 0..self.column {
+            let (start, width) = self.column_edges(column);
+            let x = start + width + gap * 0.5;
+
+            self.push(Element::Line {
+                from: (x, top),
+                to: (x, self.bottom()),
+                width: Pt(1.0),
+                color: color.clone(),
+            });
+        }
+    }
+
+    // moves on to the next column unless there is room for `height` below the current
+    // line, or it is the first line of the column already
+    fn keep_together(&mut self, height: Pt) {
+        let y = self.page_position.1;
+
+        if y < self.column_top && y - height < self.bottom() {
+            self.next_column();
+        }
+    }
+
+    // keep_together for blocks that could be split, when the style asks for it and the
+    // block fits on a page at all
+    fn keep_block_together(&mut self, height: Pt) {
+        if self.style.keep_together && height <= self.top() - self.bottom() {
+            self.keep_together(height);
+        }
+    }
+
+    fn render(&mut self) {
+        if util::is_page_break(&self.markdown[self.position..]) {
+            self.position += 3;
+            self.new_page();
+            return;
+        }
+
+        match self.peek() {
+            Event::Start(tag) if is_inline(tag) => self.render_inline(),
+            Event::Start(_) => self.handle_start(),
+            Event::End(_) => self.handle_end(),
+            Event::Text(_)
+            | Event::Code(_)
+            | Event::SoftBreak
+            | Event::HardBreak
+            | Event::FootnoteReference(_)
+            | Event::TaskListMarker(_) => self.render_inline(),
+            // raw html can't be laid out, it is left out
+            Event::Html(_) => {
+                self.consume();
+            }
+            Event::Rule => {
+                self.horizontal_rule();
+                self.consume();
+            }
+        }
+    }
+
+    // a paragraph of just the page break marker, see page_break::mark
+    // a forced break before a heading, nothing happens at the top of a page
+    // that is already the right one
+    fn break_before(&mut self, page_break: PageBreak) {
+        let at_top = self.page_position.1 >= self.top();
+
+        match page_break {
+            PageBreak::Auto => {}
+            PageBreak::Page if at_top => {}
+            PageBreak::Page => self.new_page(),
+            PageBreak::Right => {
+                if !at_top {
+                    self.new_page();
+                }
+
+                // odd pages are on the right
+                if self.pages.len().is_multiple_of(2) {
+                    self.new_page();
+                }
+            }
+        }
+    }
+
+    fn handle_start(&mut self) {
+        let tag = extract!(self.consume().clone(), Event::Start);
+
+        match tag {
+            Tag::Paragraph => {
+                self.font.current_size = self.font.regular_size;
+            }
+            Tag::Heading(heading_level, _, _) => {
+                if heading_level == HeadingLevel::H1 {
+                    self.break_before(self.style.h1_break);
+
+                    if self.style.h1_span {
+                        self.start_span();
+                    }
+                }
+
+                self.font.role = FontRole::Headings;
+                self.font.is_bold = true;
+                self.font.current_size = self.font.heading_sizes[match heading_level {
+                    HeadingLevel::H1 => 0,
+                    HeadingLevel::H2 => 1,
+                    HeadingLevel::H3 => 2,
+                    HeadingLevel::H4 => 3,
+                    HeadingLevel::H5 => 4,
+                    HeadingLevel::H6 => 5,
+                }];
+
+                // room for the heading's line advance and the first lines of the next block
+                if self.style.keep_with_next {
+                    let body_lines = self.style.orphans.max(1) - 1;
+                    self.keep_with_next = Pt(self.font.line_height_scale
+                        * (self.font.current_size + self.font.regular_size * body_lines as f32));
+                }
+
+                let title = self.upcoming_text();
+                self.render();
+                self.record_heading(heading_level, title);
+
+                match self.style.underline_headings {
+                    HeaderUnderline::FullPage => self.draw_line(
+                        self.left(),
+                        self.right(),
+                        LineLocation::Underline,
+                        self.style.rule_color.clone(),
+                    ),
+                    HeaderUnderline::None => {}
+                };
+            }
+            Tag::BlockQuote => {
+                self.end_line();
+                self.quotes.push(Quote {
+                    inset: self.indent + Pt(QUOTE_BAR),
+                    top: self.line_top(self.page_position.1),
+                });
+                self.indent += Pt(self.font.regular_size * QUOTE_INDENT);
+                self.page_position.0 = self.left();
+            }
+            Tag::CodeBlock(_) => self.render_code_block(),
+            Tag::List(list) => {
+                // nested lists start on a line of their own, further in
+                if !self.list_depth.is_empty() {
+                    self.end_line();
+                    self.indent += Pt(self.font.regular_size * LIST_INDENT);
+                    self.page_position.0 = self.left();
+                }
+
+                self.list_depth.push(list);
+            }
+            Tag::Item => {
+                let number = self.list_depth.pop().unwrap();
+
+                let number_str = match number {
+                    Some(number) => number.to_string(),
+                    None => "•".to_string(),
+                };
+
+                let text = format!("  {}  ", number_str);
+
+                // the bullet can't be left behind, so there has to be room for the
+                // first lines of the item
+                let lines = self.style.orphans.max(1) - 1;
+                self.keep_together(Pt(self.font.current_size
+                    * self.font.line_height_scale
+                    * lines as f32));
+
+                let run = self.font.shape(&text);
+
+                // right to left items have their bullet on the right
+                if self.is_rtl(&self.upcoming_text()) {
+                    let x = self.right() - run.width();
+                    self.right_inset = run.width();
+                    self.push_run(run, self.font.variant(), x, None);
+                } else {
+                    self.push_run(run, self.font.variant(), self.page_position.0, None);
+                }
+
+                self.list_depth.push(number.map(|x| x + 1));
+            }
+            // notes are set where they are defined, usually at the end, behind their number
+            Tag::FootnoteDefinition(label) => {
+                self.end_line();
+
+                let number = self.footnote_number(&label);
+                let run = self.font.shape(&format!("{}. ", number));
+                self.push_run(run, self.font.variant(), self.left(), None);
+            }
+            Tag::Table(alignments) => self.render_table(alignments),
+            Tag::TableHead | Tag::TableRow | Tag::TableCell => {
+                unreachable!("table rows are handled by render_table")
+            }
+            Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(_, _, _) => {
+                unreachable!("inline tags are handled by render_inline")
+            }
+            Tag::Image(_, url, _) => self.render_image(&url),
+        }
+    }
+
+    fn handle_end(&mut self) {
+        let tag = extract!(self.consume().clone(), Event::End);
+
+        match tag {
+            Tag::Paragraph => self.line_break(),
+            Tag::Heading(_, _, _) => {
+                self.line_break();
+                self.end_span();
+            }
+            Tag::BlockQuote => {
+                let quote = self.quotes.pop().unwrap();
+                let bottom = self.line_top(self.page_position.1);
+
+                if bottom < quote.top {
+                    let bar = self.quote_bar(&quote, bottom);
+                    self.push(bar);
+                }
+
+                self.indent -= Pt(self.font.regular_size * QUOTE_INDENT);
+                self.page_position.0 = self.left();
+            }
+            Tag::List(_) => {
+                self.list_depth.pop();
+
+                if self.list_depth.is_empty() {
+                    self.line_break();
+                } else {
+                    self.indent -= Pt(self.font.regular_size * LIST_INDENT);
+                    self.page_position.0 = self.left();
+                }
+            }
+            // an item that ends with a nested list has ended its line already
+            Tag::Item => {
+                if !matches!(self.markdown[self.position - 2], Event::End(Tag::List(_))) {
+                    self.line_break();
+                }
+            }
+            // the paragraphs of the note end their lines
+            Tag::FootnoteDefinition(_) => {}
+            Tag::CodeBlock(_) | Tag::Table(_) | Tag::Image(_, _, _) => {
+                unreachable!("blocks are laid out with their contents")
+            }
+            Tag::TableHead | Tag::TableRow | Tag::TableCell => {
+                unreachable!("table rows are handled by render_table")
+            }
+            Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(_, _, _) => {
+                unreachable!("inline tags are handled by render_inline")
+            }
+        }
+    }
+
+    // lays out everything up to the next block level event as one paragraph
+    fn render_inline(&mut self) {
+        let spans = self.collect_spans();
+        self.render_spans(spans);
+    }
+
+    // the first line starts at the current position
+    //
+    // lines are broken in logical order and reordered for display with the
+    // bidi levels of their words
+    fn render_spans(&mut self, spans: Vec<Span>) {
+        let paragraph = self.paragraph(spans);
+
+        let left = self.left();
+        let mut right = self.right();
+        let mut start = self.page_position.0;
+        // continuation lines of list items hang below the text, not the bullet
+        let mut indent = if self.list_depth.is_empty() {
+            left
+        } else {
+            start
+        };
+
+        if paragraph.rtl {
+            right -= self.right_inset;
+            start = left;
+            indent = left;
+        }
+
+        let lines = linebreak::break_lines(&paragraph.items, |line| match line {
+            0 => (right - start).0,
+            _ => (right - indent).0,
+        });
+
+        // distance of every line from the one above it
+        let advances: Vec<Pt> = lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| match i {
+                0 => Pt(0.0),
+                _ => {
+                    let size = paragraph.contents[line.start..line.end]
+                        .iter()
+                        .filter_map(|content| match content {
+                            Inline::Word { span, .. } => Some(paragraph.spans[*span].size),
+                            _ => None,
+                        })
+                        .fold(self.font.current_size, f32::max);
+
+                    Pt(size * self.font.line_height_scale)
+                }
+            })
+            .collect();
+
+        // something, like a list bullet, is on the first line already
+        let breakable = self.page_position.0 == left && self.right_inset.0 == 0.0;
+        let reserve = std::mem::replace(&mut self.keep_with_next, Pt(0.0));
+        let breaks = self.page_breaks(&advances, reserve, breakable);
+
+        for (i, line) in lines.iter().enumerate() {
+            if breaks.contains(&i) {
+                self.next_column();
+            } else if i > 0 {
+                self.next_line(advances[i]);
+            }
+
+            // columns are the same width, so lines keep their place in the next one
+            let shift = self.left() - left;
+            let x = shift + if i == 0 { start } else { indent };
+
+            self.draw_inline_line(&paragraph, line, x, right + shift - x);
+        }
+    }
+
+    // the spans shaped and ready to be broken into lines
+    fn paragraph(&self, spans: Vec<Span>) -> Paragraph {
+        // hard breaks would start new bidi paragraphs
+        let text: String = spans
+            .iter()
+            .map(|span| span.text.replace('\n', " "))
+            .collect();
+        let rtl = self.is_rtl(&text);
+        let base_level = match rtl {
+            true => Level::rtl(),
+            false => Level::ltr(),
+        };
+        let levels = BidiInfo::new(&text, Some(base_level)).levels;
+        let (items, contents) = self.build_items(&spans, &levels);
+
+        Paragraph {
+            spans,
+            items,
+            contents,
+            rtl,
+        }
+    }
+
+    // the lines a paragraph continues in the next column or on a new page with, given
+    // the `advances` of its lines from the current position
+    //
+    // columns don't end with fewer than `orphans` lines of it or start with fewer than
+    // `widows`, unless the paragraph has the whole column, and `reserve` is kept free
+    // below the last line, which moves a heading to the column of the block after it
+    fn page_breaks(&self, advances: &[Pt], reserve: Pt, breakable: bool) -> Vec<usize> {
+        let (orphans, widows) = (self.style.orphans.max(1), self.style.widows.max(1));
+        let lines = advances.len();
+        let bottom = self.bottom();
+
+        let mut breaks = vec![];
+        let mut start = 0;
+        let mut y = self.page_position.1;
+        let mut at_top = y >= self.column_top;
+        let mut column = self.column;
+
+        loop {
+            let mut fit = 0;
+            let mut last = y;
+
+            for advance in &advances[start..] {
+                if fit > 0 {
+                    last -= *advance;
+                }
+
+                if last < bottom {
+                    break;
+                }
+
+                fit += 1;
+            }
+
+            let fits_all = start + fit == lines;
+            let mut end = match fits_all {
+                true if at_top || last - reserve >= bottom => break,
+                _ if lines - (start + fit) < widows => lines.saturating_sub(widows),
+                _ => start + fit,
+            }
+            .max(start);
+
+            if end - start < orphans {
+                end = start;
+            }
+
+            // nothing can move to the next page, the lines that fit stay
+            if end == start && (at_top || (start == 0 && !breakable)) {
+                end = start + fit.max(1);
+
+                if fits_all || end >= lines {
+                    break;
+                }
+            }
+
+            breaks.push(end);
+            start = end;
+            at_top = true;
+            column += 1;
+            y = self.column_top;
+
+            if column >= self.column_count() {
+                column = 0;
+                y = self.top();
+            }
+        }
+
+        breaks
+    }
+
+    // the paragraph direction of `text`, unless the document sets one
+    fn is_rtl(&self, text: &str) -> bool {
+        match self.style.direction {
+            Direction::Ltr => false,
+            Direction::Rtl => true,
+            Direction::Auto => BidiInfo::new(text, None)
+                .paragraphs
+                .first()
+                .is_some_and(|paragraph| paragraph.level.is_rtl()),
+        }
+    }
+
+    // the text of the next paragraph, without consuming it
+    fn upcoming_text(&self) -> String {
+        let mut text = String::new();
+
+        for event in &self.markdown[self.position..] {
+            match event {
+                Event::Text(content) | Event::Code(content) => text.push_str(content),
+                Event::SoftBreak | Event::HardBreak => text.push(' '),
+                Event::Start(tag) | Event::End(tag) if is_inline(tag) => {}
+                Event::Start(Tag::Paragraph) => {}
+                _ => break,
+            }
+        }
+
+        text
+    }
+
+    // consumes inline events, tracking the formatting they switch on and off
+    fn collect_spans(&mut self) -> Vec<Span> {
+        let mut spans = vec![];
+        let mut link = None;
+
+        while !self.is_at_end() {
+            let code = matches!(self.peek(), Event::Code(_));
+            let text = match self.peek().clone() {
+                Event::Text(text) | Event::Code(text) => text.to_string(),
+                Event::SoftBreak => " ".to_string(),
+                Event::HardBreak => "\n".to_string(),
+                Event::Start(Tag::Emphasis) => {
+                    self.font.is_italic = true;
+                    String::new()
+                }
+                Event::End(Tag::Emphasis) => {
+                    self.font.is_italic = false;
+                    String::new()
+                }
+                Event::Start(Tag::Strong) => {
+                    self.font.is_bold = true;
+                    String::new()
+                }
+                Event::End(Tag::Strong) => {
+                    self.font.is_bold = false;
+                    String::new()
+                }
+                Event::Start(Tag::Strikethrough) => {
+                    self.font.is_strikethrough = true;
+                    String::new()
+                }
+                Event::End(Tag::Strikethrough) => {
+                    self.font.is_strikethrough = false;
+                    String::new()
+                }
+                Event::Start(Tag::Link(_, url, _)) => {
+                    link = Some(url.to_string());
+                    String::new()
+                }
+                Event::End(Tag::Link(_, _, _)) => {
+                    link = None;
+                    String::new()
+                }
+                Event::FootnoteReference(label) => format!("[{}]", self.footnote_number(&label)),
+                // no break space, so the box stays in one piece
+                Event::TaskListMarker(true) => "[x] ".to_string(),
+                Event::TaskListMarker(false) => "[\u{a0}] ".to_string(),
+                Event::Html(_) => String::new(),
+                _ => break,
+            };
+
+            self.consume();
+
+            if !text.is_empty() {
+                let mut variant = self.font.variant();
+
+                if code {
+                    variant.role = FontRole::Monospace;
+                }
+
+                spans.push(Span {
+                    text,
+                    variant,
+                    strikethrough: self.font.is_strikethrough,
+                    size: self.font.current_size,
+                    link: link.clone(),
+                });
+            }
+        }
+
+        spans
+    }
+
+    // words become boxes, spaces become glue and hard breaks forced penalties,
+    // `contents` holds what to draw for every item, `levels` are the bidi levels
+    // of the bytes of the spans' text
+    fn build_items(&self, spans: &[Span], levels: &[Level]) -> (Vec<Item>, Vec<Inline>) {
+        let mut items = vec![];
+        let mut contents = vec![];
+
+        let end_line = |items: &mut Vec<Item>, contents: &mut Vec<Inline>| {
+            if let Some(Item::Glue { .. }) = items.last() {
+                items.pop();
+                contents.pop();
+            }
+
+            items.push(Item::Glue {
+                width: 0.0,
+                stretch: INFINITY,
+                shrink: 0.0,
+            });
+            items.push(Item::Penalty {
+                width: 0.0,
+                cost: -INFINITY,
+                flagged: false,
+            });
+            contents.extend([Inline::Nothing, Inline::Nothing]);
+        };
+
+        let mut offset = 0;
+
+        for (index, span) in spans.iter().enumerate() {
+            for piece in span.text.split_inclusive([' ', '\n']) {
+                let word = piece.trim_end_matches([' ', '\n']);
+                let level = levels[offset];
+
+                if !word.is_empty() {
+                    // a space in front of punctuation must not end up at the end of a line
+                    if is_closing_punctuation(word)
+                        && matches!(items.last(), Some(Item::Glue { .. }))
+                    {
+                        let glue = items.len() - 1;
+
+                        items.insert(
+                            glue,
+                            Item::Penalty {
+                                width: 0.0,
+                                cost: INFINITY,
+                                flagged: false,
+                            },
+                        );
+                        contents.insert(glue, Inline::Nothing);
+                    }
+
+                    self.push_word(&mut items, &mut contents, span, index, word, level);
+                }
+
+                match &piece[word.len()..] {
+                    " " if !matches!(items.last(), None | Some(Item::Glue { .. })) => {
+                        let level = levels[offset + word.len()];
+                        let run =
+                            self.font
+                                .shape_variant(span.variant, span.size, " ", level.is_rtl());
+                        let width = run.width().0;
+
+                        items.push(Item::Glue {
+                            width,
+                            stretch: width / 2.0,
+                            shrink: width / 3.0,
+                        });
+                        contents.push(Inline::Space {
+                            run,
+                            span: index,
+                            level,
+                        });
+                    }
+                    "\n" => end_line(&mut items, &mut contents),
+                    _ => {}
+                }
+
+                offset += piece.len();
+            }
+        }
+
+        end_line(&mut items, &mut contents);
+        (items, contents)
+    }
+
+    // the word is shaped as a whole and cut at its hyphenation points afterwards,
+    // so kerning and ligatures survive unless the line is actually broken there
+    fn push_word(
+        &self,
+        items: &mut Vec<Item>,
+        contents: &mut Vec<Inline>,
+        span: &Span,
+        index: usize,
+        word: &str,
+        level: Level,
+    ) {
+        let (word, breakpoints) = self.hyphenator.hyphenate(word);
+        let mut rest = self
+            .font
+            .shape_variant(span.variant, span.size, &word, level.is_rtl());
+        let mut consumed = 0;
+
+        for breakpoint in breakpoints {
+            let Some((head, tail)) = rest.split(breakpoint.offset - consumed) else {
+                continue;
+            };
+
+            items.push(Item::Box {
+                width: head.width().0,
+            });
+            contents.push(Inline::Word {
+                run: head,
+                span: index,
+                level,
+            });
+
+            if breakpoint.hyphen {
+                let run = self.font.shape_variant(span.variant, span.size, "-", false);
+
+                items.push(Item::Penalty {
+                    width: run.width().0,
+                    cost: HYPHEN_PENALTY,
+                    flagged: true,
+                });
+                contents.push(Inline::Hyphen {
+                    run,
+                    span: index,
+                    level,
+                });
+            } else {
+                items.push(Item::Penalty {
+                    width: 0.0,
+                    cost: HYPHEN_PENALTY,
+                    flagged: true,
+                });
+                contents.push(Inline::Nothing);
+            }
+
+            consumed = breakpoint.offset;
+            rest = tail;
+        }
+
+        items.push(Item::Box {
+            width: rest.width().0,
+        });
+        contents.push(Inline::Word {
+            run: rest,
+            span: index,
+            level,
+        });
+    }
+
+    fn draw_inline_line(
+        &mut self,
+        paragraph: &Paragraph,
+        line: &linebreak::Line,
+        x: Pt,
+        available: Pt,
+    ) {
+        let Paragraph {
+            spans,
+            items,
+            contents,
+            rtl,
+        } = paragraph;
+
+        // ragged lines keep their natural spacing, unless they have to shrink to fit
+        let ratio = match self.style.text_align {
+            TextAlign::Justify => line.ratio,
+            _ => line.ratio.min(0.0),
+        };
+
+        let width_of = |item: &Item| match *item {
+            Item::Glue { width, shrink, .. } if ratio < 0.0 => width + ratio * shrink,
+            Item::Glue { width, stretch, .. } => width + ratio * stretch,
+            item => item.width(),
+        };
+
+        // hyphens are only drawn at the end of a line
+        let drawn = |index: &usize| match contents[*index] {
+            Inline::Nothing => false,
+            Inline::Hyphen { .. } => *index == line.end,
+            Inline::Space { .. } => *index != line.end,
+            Inline::Word { .. } => true,
+        };
+        let logical: Vec<usize> = (line.start..=line.end).filter(drawn).collect();
+        let natural_width: f32 = logical.iter().map(|index| width_of(&items[*index])).sum();
+
+        // right to left paragraphs start on the right, so their last line does as well
+        let offset = match (self.style.text_align, *rtl) {
+            (TextAlign::Center, _) => (available.0 - natural_width) / 2.0,
+            (TextAlign::Left | TextAlign::Justify, false) => 0.0,
+            (TextAlign::Right, _) | (_, true) => available.0 - natural_width,
+        }
+        .max(0.0);
+
+        let levels: Vec<Level> = logical
+            .iter()
+            .map(|index| contents[*index].level())
+            .collect();
+        let visual = BidiInfo::reorder_visual(&levels)
+            .into_iter()
+            .map(|i| logical[i]);
+
+        let mut cursor = x + Pt(offset);
+        let mut links: Vec<(String, Pt, Pt)> = vec![];
+
+        for index in visual {
+            let (run, span, width) = match &contents[index] {
+                Inline::Word { run, span, .. } | Inline::Hyphen { run, span, .. } => {
+                    (run, *span, run.width())
+                }
+                Inline::Space { run, span, .. } => (run, *span, Pt(width_of(&items[index]))),
+                Inline::Nothing => continue,
+            };
+            let style = &spans[span];
+            let color = style.link.as_ref().map(|_| self.style.link_color.clone());
+
+            self.push_run(run.clone(), style.variant, cursor, color);
+
+            if style.strikethrough {
+                let color = self.style.text_color.clone();
+                self.draw_line(cursor, cursor + width, LineLocation::Strikethrough, color);
+            }
+
+            if let Some(url) = &style.link {
+                match links.last_mut() {
+                    Some((last, _, end)) if last == url && *end == cursor => *end = cursor + width,
+                    _ => links.push((url.clone(), cursor, cursor + width)),
+                }
+            }
+
+            cursor += width;
+        }
+
+        for (url, start, end) in links {
+            let size = self.font.current_size;
+            let target = self.book.as_ref().and_then(|book| book.target(&url));
+
+            if let Some((chapter, anchor)) = target {
+                let page = self.pages.len() - 1;
+                let index = self.pages[page].elements.len();
+
+                self.book.as_mut().unwrap().links.push(PendingLink {
+                    page,
+                    index,
+                    chapter,
+                    anchor,
+                });
+            }
+
+            self.push(Element::Link {
+                from: (start, self.page_position.1 - Pt(size * 0.25)),
+                to: (end, self.page_position.1 + Pt(size)),
+                url,
+            });
+        }
+    }
+
+    // places `run` at `x` on the current line, in the text color unless a `color` is given
+    fn push_run(&mut self, run: ShapedRun, variant: Variant, x: Pt, color: Option<Color>) {
+        self.missing_glyphs.extend(run.missing());
+        self.page_position.0 = x + run.width();
+
+        let slant = match self.font.is_synthesized(variant) {
+            true => SYNTHETIC_SLANT,
+            false => 0.0,
+        };
+
+        self.push(Element::Text(TextRun {
+            x,
+            y: self.page_position.1,
+            faces: self.font.run_faces(variant),
+            run,
+            color: color.unwrap_or(self.style.text_color.clone()),
+            slant,
+        }));
+    }
+
+    // the lines of a code block as they are, in the monospace font on a shaded background,
+    // lines too wide for the column are cut and go on below
+    fn render_code_block(&mut self) {
+        let mut code = String::new();
+
+        while let Event::Text(text) = self.consume() {
+            code.push_str(text);
+        }
+
+        self.end_line();
+
+        let variant = Variant {
+            role: FontRole::Monospace,
+            bold: false,
+            italic: false,
+        };
+        let size = self.font.regular_size;
+        let height = Pt(size * self.font.line_height_scale);
+        let padding = Pt(size * 0.5);
+        let width = self.right() - self.left() - padding * 2.0;
+
+        let lines: Vec<ShapedRun> = code
+            .strip_suffix('\n')
+            .unwrap_or(&code)
+            .split('\n')
+            .flat_map(|line| {
+                let run =
+                    self.font
+                        .shape_variant(variant, size, &line.replace('\t', "    "), false);
+                wrap_run(run, width)
+            })
+            .collect();
+
+        self.keep_block_together(height * lines.len().saturating_sub(1) as f32);
+
+        let mut lines = lines.into_iter().peekable();
+
+        while lines.peek().is_some() {
+            if self.page_position.1 < self.bottom() {
+                self.next_column();
+            }
+
+            // the lines that fit into the rest of the column, at least one
+            let room = ((self.page_position.1 - self.bottom()).0 / height.0) as usize + 1;
+            let piece: Vec<ShapedRun> = lines.by_ref().take(room).collect();
+            let last = self.page_position.1 - height * (piece.len() - 1) as f32;
+
+            self.push(Element::Rect {
+                from: (self.left(), self.line_bottom(last)),
+                to: (self.right(), self.line_top(self.page_position.1)),
+                color: self.style.code_background.clone(),
+            });
+
+            for (i, run) in piece.into_iter().enumerate() {
+                if i > 0 {
+                    self.next_line(height);
+                }
+
+                self.push_run(run, variant, self.left() + padding, None);
+            }
+
+            if lines.peek().is_some() {
+                self.next_column();
+            }
+        }
+
+        self.line_break();
+    }
+
+    // an image on a line of its own, scaled down to fit the column, images that can't
+    // be read show their alt text instead
+    fn render_image(&mut self, url: &str) {
+        let mut alt = String::new();
+
+        loop {
+            match self.consume() {
+                Event::End(Tag::Image(_, _, _)) => break,
+                Event::Text(text) | Event::Code(text) => alt.push_str(text),
+                _ => {}
+            }
+        }
+
+        let Some(image) = self.load_image(url) else {
+            eprintln!(
+                "warning: {}: could not load {}, only png files are supported, the alt text is shown instead",
+                self.title, url
+            );
+
+            self.render_spans(vec![Span {
+                text: match alt.trim() {
+                    "" => url.to_string(),
+                    alt => alt.to_string(),
+                },
+                variant: Variant {
+                    italic: true,
+                    ..self.font.variant()
+                },
+                strikethrough: false,
+                size: self.font.current_size,
+                link: None,
+            }]);
+            return;
+        };
+
+        self.end_line();
+
+        // 96 pixels to the inch, the size browsers show images at
+        let natural = (
+            Pt(image.width as f32 * 0.75),
+            Pt(image.height as f32 * 0.75),
+        );
+        let room = (
+            self.right() - self.left(),
+            self.line_top(self.top()) - self.line_bottom(self.bottom()),
+        );
+        let scale = (room.0 / natural.0).min(room.1 / natural.1).min(1.0);
+        let (width, height) = (natural.0 * scale, natural.1 * scale);
+
+        // images can't be split, they go to the next column whole
+        let at_top = self.page_position.1 >= self.column_top;
+
+        if !at_top && self.line_top(self.page_position.1) - height < self.line_bottom(self.bottom())
+        {
+            self.next_column();
+        }
+
+        let top = self.line_top(self.page_position.1);
+        let x = match self.style.direction {
+            Direction::Rtl => self.right() - width,
+            _ => self.left(),
+        };
+
+        self.push(Element::Image {
+            from: (x, top - height),
+            to: (x + width, top),
+            image,
+        });
+
+        // the next line starts right below the image
+        self.page_position.1 = top - height - self.line_extent().0;
+        self.page_position.0 = self.left();
+    }
+
+    fn load_image(&self, url: &str) -> Option<Arc<Image>> {
+        if url.contains("://") {
+            return None;
+        }
+
+        let data = fs::read(self.base.join(url)).ok()?;
+        Image::decode(data).map(Arc::new)
+    }
+
+    // a table row by row between rules, every cell a paragraph of its own, columns get
+    // the width of their content when there is room and share it out when there isn't
+    //
+    // tables too wide for a text column span all of them, rows that don't fit go on in
+    // the next column, behind a copy of the header
+    fn render_table(&mut self, alignments: Vec<Alignment>) {
+        self.end_line();
+
+        let rows = self.collect_rows();
+        let size = self.font.regular_size;
+        let height = Pt(size * self.font.line_height_scale);
+        let padding = Pt(size * 0.5);
+        self.font.current_size = size;
+
+        let mut natural = vec![0.0; alignments.len()];
+        let mut minimum = vec![0.0; alignments.len()];
+
+        for row in &rows {
+            for (i, cell) in row.cells.iter().enumerate().take(alignments.len()) {
+                let (line, word) =
+                    cell.items
+                        .iter()
+                        .fold((0.0, 0.0), |(line, word), item| match item {
+                            Item::Box { width } => (line + width, f32::max(word, *width)),
+                            Item::Glue { width, .. } => (line + width, word),
+                            Item::Penalty { .. } => (line, word),
+                        });
+
+                natural[i] = f32::max(natural[i], line + padding.0 * 2.0);
+                minimum[i] = f32::max(minimum[i], word + padding.0 * 2.0);
+            }
+        }
+
+        if natural.iter().sum::<f32>() > (self.right() - self.left()).0 {
+            self.start_span();
+        }
+
+        let widths: Vec<Pt> = column_widths(&natural, &minimum, (self.right() - self.left()).0)
+            .into_iter()
+            .map(Pt)
+            .collect();
+        let width = widths.iter().fold(Pt(0.0), |total, width| total + *width);
+        let mut offsets: Vec<Pt> = widths
+            .iter()
+            .scan(Pt(0.0), |offset, width| {
+                let start = *offset;
+                *offset += *width;
+                Some(start)
+            })
+            .collect();
+
+        // right to left tables start on the right with their first column
+        let header: String = rows
+            .first()
+            .into_iter()
+            .flat_map(|row| &row.cells)
+            .flat_map(|cell| &cell.spans)
+            .map(|span| span.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let rtl = self.is_rtl(&header);
+
+        if rtl {
+            for (offset, column) in offsets.iter_mut().zip(&widths) {
+                *offset = width - *offset - *column;
+            }
+        }
+
+        let columns = Columns {
+            offsets,
+            width,
+            rtl,
+            alignments: alignments
+                .iter()
+                .map(|alignment| match alignment {
+                    Alignment::None | Alignment::Left => TextAlign::Left,
+                    Alignment::Center => TextAlign::Center,
+                    Alignment::Right => TextAlign::Right,
+                })
+                .collect(),
+            widths,
+            padding,
+        };
+
+        let lines: Vec<Vec<Vec<linebreak::Line>>> = rows
+            .iter()
+            .map(|row| {
+                row.cells
+                    .iter()
+                    .zip(&columns.widths)
+                    .map(|(cell, width)| {
+                        linebreak::break_lines(&cell.items, |_| (*width - padding * 2.0).0)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let count: usize = lines
+            .iter()
+            .map(|row| row.iter().map(Vec::len).max().unwrap_or(1))
+            .sum();
+        self.keep_block_together(height * count.saturating_sub(1) as f32);
+
+        let header = rows
+            .first()
+            .filter(|row| row.head)
+            .map(|row| (row, &lines[0]));
+        self.draw_table_rule(&columns, self.line_top(self.page_position.1));
+
+        for (index, (row, lines)) in rows.iter().zip(&lines).enumerate() {
+            let count = lines.iter().map(Vec::len).max().unwrap_or(1);
+            let last = self.page_position.1 - height * (count - 1) as f32;
+
+            if self.page_position.1 < self.column_top && last < self.bottom() {
+                self.next_column();
+                self.draw_table_rule(&columns, self.line_top(self.page_position.1));
+
+                if let Some((header, header_lines)) = header.filter(|_| index > 0) {
+                    self.draw_row(header, header_lines, &columns);
+                }
+            }
+
+            self.draw_row(row, lines, &columns);
+        }
+
+        self.end_span();
+        self.font.clear_typography();
+    }
+
+    // the cells of a table as paragraphs, row by row, the header is set in bold
+    fn collect_rows(&mut self) -> Vec<Row> {
+        let mut rows: Vec<Row> = vec![];
+
+        loop {
+            match self.consume().clone() {
+                Event::Start(Tag::TableHead) => rows.push(Row {
+                    head: true,
+                    cells: vec![],
+                }),
+                Event::Start(Tag::TableRow) => rows.push(Row {
+                    head: false,
+                    cells: vec![],
+                }),
+                Event::Start(Tag::TableCell) => {
+                    self.font.is_bold = rows.last().unwrap().head;
+                    let mut spans = vec![];
+
+                    // images in cells are left out, their alt text stays
+                    loop {
+                        spans.extend(self.collect_spans());
+
+                        if matches!(self.consume(), Event::End(Tag::TableCell)) {
+                            break;
+                        }
+                    }
+
+                    self.font.is_bold = false;
+                    let cell = self.paragraph(spans);
+                    rows.last_mut().unwrap().cells.push(cell);
+                }
+                Event::End(Tag::Table(_)) => break,
+                _ => {}
+            }
+        }
+
+        rows
+    }
+
+    // the cells of a row from the current line down, with a rule below them
+    fn draw_row(&mut self, row: &Row, lines: &[Vec<linebreak::Line>], columns: &Columns) {
+        let baseline = self.page_position.1;
+        let height = Pt(self.font.regular_size * self.font.line_height_scale);
+        let start = self.table_start(columns);
+
+        for (i, (cell, lines)) in row.cells.iter().zip(lines).enumerate() {
+            let x = start + columns.offsets[i] + columns.padding;
+            let width = columns.widths[i] - columns.padding * 2.0;
+            let text_align = std::mem::replace(&mut self.style.text_align, columns.alignments[i]);
+
+            for (j, line) in lines.iter().enumerate() {
+                self.page_position.1 = baseline - height * j as f32;
+                self.draw_inline_line(cell, line, x, width);
+            }
+
+            self.style.text_align = text_align;
+        }
+
+        let count = lines.iter().map(Vec::len).max().unwrap_or(1);
+        self.page_position.1 = baseline - height * (count - 1) as f32;
+        self.draw_table_rule(columns, self.line_bottom(self.page_position.1));
+        self.next_line(height);
+    }
+
+    fn table_start(&self, columns: &Columns) -> Pt {
+        match columns.rtl {
+            true => self.right() - columns.width,
+            false => self.left(),
+        }
+    }
+
+    fn draw_table_rule(&mut self, columns: &Columns, y: Pt) {
+        let start = self.table_start(columns);
+
+        self.push(Element::Line {
+            from: (start, y),
+            to: (start + columns.width, y),
+            width: Pt(1.0),
+            color: self.style.rule_color.clone(),
+        });
+    }
+
+    fn footnote_number(&mut self, label: &str) -> usize {
+        let next = self.footnotes.len() + 1;
+        *self.footnotes.entry(label.to_string()).or_insert(next)
+    }
+
+    fn draw_line(&mut self, start: Pt, end: Pt, location: LineLocation, color: Color) {
+        let offset = match location {
+            LineLocation::Underline => {
+                Pt(-self.font.line_height_scale * self.font.current_size * 0.25)
+            }
+            LineLocation::Strikethrough => {
+                Pt(self.font.line_height_scale * self.font.current_size * 0.2)
+            }
+        };
+        let y = self.page_position.1 + offset;
+
+        self.push(Element::Line {
+            from: (start, y),
+            to: (end, y),
+            width: Pt(1.0),
+            color,
+        });
+    }
+
+    // ends the current line if something is on it already
+    fn end_line(&mut self) {
+        if self.page_position.0 != self.left() {
+            self.line_break();
+        }
+    }
+
+    fn line_break(&mut self) {
+        self.next_line(Pt(self.font.line_height_scale * self.font.current_size));
+        self.font.clear_typography();
+        self.right_inset = Pt(0.0);
+    }
+
+    // moves down by `height` and back to the left margin, keeps the formatting
+    fn next_line(&mut self, height: Pt) {
+        self.page_position.1 -= height;
+        self.page_position.0 = self.left();
+    }
+
+    fn horizontal_rule(&mut self) {
+        self.keep_together(Pt(0.0));
+
+        let color = self.style.rule_color.clone();
+        self.draw_line(
+            self.left(),
+            self.right(),
+            LineLocation::Strikethrough,
+            color,
+        );
+        self.line_break();
+    }
+
+    fn consume(&mut self) -> &Event<'a> {
+        let event = self.markdown.get(self.position).unwrap();
+        self.position += 1;
+        event
+    }
+
+    fn peek(&self) -> &Event<'a> {
+        self.markdown.get(self.position).unwrap()
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.position >= self.markdown.len()
+    }
+}
+
+// a piece of inline text with one formatting
+struct Span {
+    text: String,
+    variant: Variant,
+    strikethrough: bool,
+    size: f32,
+    link: Option<String>,
+}
+
+// a paragraph ready for line breaking, `contents` has what to draw for each of `items`
+struct Paragraph {
+    spans: Vec<Span>,
+    items: Vec<Item>,
+    contents: Vec<Inline>,
+    rtl: bool,
+}
+
+// a row of a table, every cell a paragraph
+struct Row {
+    head: bool,
+    cells: Vec<Paragraph>,
+}
+
+// where the columns of a table go, offsets are from the start of the table
+struct Columns {
+    offsets: Vec<Pt>,
+    widths: Vec<Pt>,
+    // of the whole table
+    width: Pt,
+    // the table sits on the right of the column
+    rtl: bool,
+    alignments: Vec<TextAlign>,
+    // between the text and the edges of the cells
+    padding: Pt,
+}
+
+// the widths of the columns of a table, from the width of their content on one line
+// and of their widest word
+//
+// columns get their natural width when the table fits, otherwise every column gets its
+// widest word and the room left is shared out by how much more each one would take
+fn column_widths(natural: &[f32], minimum: &[f32], available: f32) -> Vec<f32> {
+    let total: f32 = natural.iter().sum();
+
+    if total <= available {
+        return natural.to_vec();
+    }
+
+    let least: f32 = minimum.iter().sum();
+
+    if least >= available {
+        return minimum
+            .iter()
+            .map(|width| width * available / least)
+            .collect();
+    }
+
+    let share = (available - least) / (total - least);
+
+    natural
+        .iter()
+        .zip(minimum)
+        .map(|(natural, minimum)| minimum + (natural - minimum) * share)
+        .collect()
+}
+
+// what gets drawn for an item of the line breaker, with the bidi level it is displayed at
+enum Inline {
+    Word {
+        run: ShapedRun,
+        span: usize,
+        level: Level,
+    },
+    Space {
+        run: ShapedRun,
+        span: usize,
+        level: Level,
+    },
+    // only drawn when the line is broken at it
+    Hyphen {
+        run: ShapedRun,
+        span: usize,
+        level: Level,
+    },
+    Nothing,
+}
+
+impl Inline {
+    fn level(&self) -> Level {
+        match self {
+            Inline::Word { level, .. }
+            | Inline::Space { level, .. }
+            | Inline::Hyphen { level, .. } => *level,
+            Inline::Nothing => Level::ltr(),
+        }
+    }
+}
+
+// the cost of breaking a line inside a word
+const HYPHEN_PENALTY: f32 = 50.0;
+
+// how far blockquotes and nested lists are indented, in multiples of the font size
+const QUOTE_INDENT: f32 = 1.5;
+const LIST_INDENT: f32 = 1.5;
+
+// width of the bar on the side of blockquotes
+const QUOTE_BAR: f32 = 3.0;
+
+// cuts a left to right run into pieces no wider than `width`, at least a cluster each
+fn wrap_run(mut run: ShapedRun, width: Pt) -> Vec<ShapedRun> {
+    let mut pieces = vec![];
+
+    while run.width() > width {
+        let limit = width.0 * 1000.0 / run.size;
+        let mut advance = 0;
+
+        // the offsets the run can be cut at with the part in front still fitting
+        let fitting: Vec<usize> = run
+            .glyphs
+            .iter()
+            .filter_map(|glyph| {
+                let fits = advance as f32 <= limit;
+                advance += glyph.advance;
+                (fits && glyph.cluster > 0).then_some(glyph.cluster)
+            })
+            .collect();
+
+        let split = fitting
+            .iter()
+            .rev()
+            .find_map(|offset| run.split(*offset))
+            .or_else(|| {
+                run.glyphs
+                    .iter()
+                    .filter(|glyph| glyph.cluster > 0)
+                    .find_map(|glyph| run.split(glyph.cluster))
+            });
+
+        let Some((head, tail)) = split else {
+            break;
+        };
+
+        pieces.push(head);
+        run = tail;
+    }
+
+    pieces.push(run);
+    pieces
+}
+
+fn is_inline(tag: &Tag<'_>) -> bool {
+    matches!(
+        tag,
+        Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(_, _, _)
+    )
+}
+
+fn is_closing_punctuation(word: &str) -> bool {
+    word.chars().all(|c| {
+        matches!(
+            c,
+            '.' | ',' | ';' | ':' | '!' | '?' | ')' | ']' | '}' | '»' | '…'
+        )
+    })
+}
+
+enum LineLocation {
+    Underline,
+    Strikethrough,
+}
+
+// which family a piece of text is set in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FontRole {
+    Body,
+    Headings,
+    Monospace,
+}
+
+// a face of one of the families
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Variant {
+    pub role: FontRole,
+    pub bold: bool,
+    pub italic: bool,
+}
+
+// slant of synthesized italics, about 12 degrees
+const SYNTHETIC_SLANT: f32 = 0.21;
+
+// the faces of the document and the typographic state
+pub struct Font {
+    // every distinct face of the families, followed by the fallbacks
+    faces: Vec<Arc<[u8]>>,
+    // index of the face in `faces`, and whether it has to be slanted
+    variants: HashMap<Variant, (usize, bool)>,
+    // index of the first fallback in `faces`
+    fallbacks: usize,
+    role: FontRole,
+    is_bold: bool,
+    is_italic: bool,
+    is_strikethrough: bool,
+    current_size: f32,
+    regular_size: f32,
+    heading_sizes: [f32; 6],
+    line_height_scale: f32,
+}
+
+impl Font {
+    pub fn new(fonts: &FontData, style: &Style) -> Result<Self> {
+        let mut faces: Vec<Arc<[u8]>> = vec![];
+        let mut variants = HashMap::new();
+
+        for (role, family) in [
+            (FontRole::Body, &fonts.body),
+            (
+                FontRole::Headings,
+                fonts.headings.as_ref().unwrap_or(&fonts.body),
+            ),
+            (
+                FontRole::Monospace,
+                fonts.monospace.as_ref().unwrap_or(&fonts.body),
+            ),
+        ] {
+            if family.faces().any(|face| !shaping::is_valid_face(face)) {
+                return Err(Error::Font("unable to parse font file".to_string()));
+            }
+
+            for (bold, italic) in [(false, false), (true, false), (false, true), (true, true)] {
+                let (face, synthesized) = family.face(bold, italic);
+                let index = match faces.iter().position(|known| Arc::ptr_eq(known, face)) {
+                    Some(index) => index,
+                    None => {
+                        faces.push(face.clone());
+                        faces.len() - 1
+                    }
+                };
+
+                variants.insert(Variant { role, bold, italic }, (index, synthesized));
+            }
+        }
+
+        if fonts
+            .fallbacks
+            .iter()
+            .any(|face| !shaping::is_valid_face(face))
+        {
+            return Err(Error::Font("unable to parse font file".to_string()));
+        }
+
+        let fallbacks = faces.len();
+        faces.extend(fonts.fallbacks.iter().cloned());
+
+        Ok(Font {
+            faces,
+            variants,
+            fallbacks,
+            role: FontRole::Body,
+            is_bold: false,
+            is_italic: false,
+            is_strikethrough: false,
+            current_size: style.font_size,
+            regular_size: style.font_size,
+            heading_sizes: style.heading_sizes,
+            line_height_scale: style.line_height,
+        })
+    }
+
+    // the face the current formatting selects
+    pub fn variant(&self) -> Variant {
+        Variant {
+            role: self.role,
+            bold: self.is_bold,
+            italic: self.is_italic,
+        }
+    }
+
+    // true when the family has no face for the variant's slant and the upright face
+    // has to be slanted instead
+    pub fn is_synthesized(&self, variant: Variant) -> bool {
+        self.variants[&variant].1
+    }
+
+    // shapes `text` left to right with the current face and size
+    pub fn shape(&self, text: &str) -> ShapedRun {
+        self.shape_variant(self.variant(), self.current_size, text, false)
+    }
+
+    pub fn shape_variant(&self, variant: Variant, size: f32, text: &str, rtl: bool) -> ShapedRun {
+        let faces: Vec<&[u8]> = self
+            .run_faces(variant)
+            .into_iter()
+            .map(|index| &self.faces[index][..])
+            .collect();
+
+        shaping::shape(&faces, text, size, rtl)
+    }
+
+    // the faces a run of `variant` is shaped with, the variant's face and the fallbacks
+    pub fn run_faces(&self, variant: Variant) -> Vec<usize> {
+        let (index, _) = self.variants[&variant];
+        [index]
+            .into_iter()
+            .chain(self.fallbacks..self.faces.len())
+            .collect()
+    }
+
+    // reset internal formatting
+    fn clear_typography(&mut self) {
+        self.role = FontRole::Body;
+        self.is_bold = false;
+        self.is_italic = false;
+        self.is_strikethrough = false;
+        self.current_size = self.regular_size;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempDir};
+
+    fn layout(markdown: &str) -> Layout {
+        layout_with(markdown, Style::default())
+    }
+
+    fn layout_with(markdown: &str, style: Style) -> Layout {
+        let config = testing::styled(style);
+        layout_document(markdown, Path::new("test.md"), &config).unwrap()
+    }
+
+    // the page and the start of the first run drawing `word`
+    fn find(layout: &Layout, word: &str) -> (usize, Pt, Pt) {
+        layout
+            .pages
+            .iter()
+            .enumerate()
+            .find_map(|(i, page)| {
+                page.elements.iter().find_map(|element| match element {
+                    Element::Text(text) if text.run.text == word => Some((i, text.x, text.y)),
+                    _ => None,
+                })
+            })
+            .unwrap_or_else(|| panic!("{} is not drawn", word))
+    }
+
+    #[test]
+    fn forced_breaks_start_a_new_page() {
+        let layout = layout("one\n\n\\pagebreak\n\ntwo\n");
+
+        assert_eq!(layout.pages.len(), 2);
+        assert_eq!(find(&layout, "one").0, 0);
+        assert_eq!(find(&layout, "two").0, 1);
+    }
+
+    #[test]
+    fn text_flows_onto_the_next_page() {
+        let markdown = "paragraph\n\n".repeat(60) + "last\n";
+        let layout = layout(&markdown);
+
+        assert!(layout.pages.len() > 1);
+        assert_eq!(find(&layout, "last").0, layout.pages.len() - 1);
+    }
+
+    #[test]
+    fn books_outline_their_chapters_and_headings() {
+        let config = testing::config();
+        let chapters = [
+            (Path::new("one.md"), "# One\n\ntext\n\n## Part\n\ntext\n"),
+            (Path::new("two.md"), "# Two\n\ntext\n"),
+        ];
+        let layout = layout_book(&chapters, Some("Book"), &config).unwrap();
+        let outline: Vec<_> = layout
+            .outline
+            .iter()
+            .map(|item| (item.title.as_str(), item.level))
+            .collect();
+
+        assert_eq!(outline, [("One", 0), ("Part", 2), ("Two", 0)]);
+    }
+
+    #[test]
+    fn links_between_chapters_jump_inside_the_book() {
+        let dir = TempDir::new("book-links");
+        let one = dir.write(
+            "one.md",
+            "# One\n\n[next](two.md) [part](two.md#part) [back](#one) [web](https://example.com)\n",
+        );
+        let two = dir.write(
+            "two.md",
+            "# Two\n\ntext\n\n\\pagebreak\n\n## Part\n\nmore\n",
+        );
+        let chapters = [
+            (one.as_path(), &*fs::read_to_string(&one).unwrap()),
+            (two.as_path(), &*fs::read_to_string(&two).unwrap()),
+        ];
+        let layout = layout_book(&chapters, None, &testing::config()).unwrap();
+
+        let outlined = |title: &str| {
+            let item = layout
+                .outline
+                .iter()
+                .find(|item| item.title == title)
+                .unwrap();
+            (item.page, item.y)
+        };
+        // the table of contents in front links to the chapters as well
+        let chapters = &layout.pages[outlined("One").0..];
+        let jumps: Vec<_> = chapters
+            .iter()
+            .flat_map(|page| &page.elements)
+            .filter_map(|element| match element {
+                Element::PageLink { page, y, .. } => Some((*page, *y)),
+                _ => None,
+            })
+            .collect();
+        let urls: Vec<_> = chapters
+            .iter()
+            .flat_map(|page| &page.elements)
+            .filter_map(|element| match element {
+                Element::Link { url, .. } => Some(url.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        // chapters are jumped to at the top of their title page, the first heading of
+        // a chapter is on the page after it
+        assert_eq!(
+            jumps,
+            [
+                outlined("Two"),
+                outlined("Part"),
+                (outlined("One").0 + 1, jumps[2].1),
+            ]
+        );
+        assert_eq!(urls, ["https://example.com"]);
+    }
+
+    #[test]
+    fn nested_lists_are_indented() {
+        let layout = layout("- top\n    - sub\n- next\n");
+        let (_, top, _) = find(&layout, "top");
+        let (_, sub, _) = find(&layout, "sub");
+        let (_, next, _) = find(&layout, "next");
+
+        assert!(sub > top);
+        assert_eq!(next, top);
+    }
+
+    #[test]
+    fn quotes_code_tables_and_footnotes_are_laid_out() {
+        let layout = layout(concat!(
+            "> quoted\n\n",
+            "```\ncode\n```\n\n",
+            "| a | b |\n|---|---|\n| cell | 2 |\n\n",
+            "note[^1] <span>html</span>\n\n",
+            "- [x] done\n\n",
+            "[^1]: foot\n",
+        ));
+
+        assert!(find(&layout, "quoted").1 > find(&layout, "note").1);
+        assert!(find(&layout, "code").1 > find(&layout, "note").1);
+        find(&layout, "cell");
+        find(&layout, "foot");
+        find(&layout, "[1]");
+        find(&layout, "done");
+    }
+
+    #[test]
+    fn images_are_found_next_to_the_document() {
+        let dir = TempDir::new("images");
+        let png = tiny_skia::Pixmap::new(40, 20)
+            .unwrap()
+            .encode_png()
+            .unwrap();
+        dir.write("dot.png", png);
+
+        let markdown = "![dot](dot.png)\n\n![gone](gone.png)\n";
+        let layout = layout_document(markdown, &dir.join("doc.md"), &testing::config()).unwrap();
+
+        let sizes: Vec<_> = layout.pages[0]
+            .elements
+            .iter()
+            .filter_map(|element| match element {
+                Element::Image { from, to, .. } => Some((to.0 - from.0, to.1 - from.1)),
+                _ => None,
+            })
+            .collect();
+
+        // 96 pixels to the inch
+        assert_eq!(sizes, [(Pt(30.0), Pt(15.0))]);
+        find(&layout, "gone");
+    }
+
+    #[test]
+    fn wide_tables_span_all_columns() {
+        let layout = layout(concat!(
+            "---\ncolumns: 2\n---\n\n",
+            "left\n\n",
+            "| one | two | six | ten |\n|---|---|---|---|\n",
+            "| a long cell that does | not fit into the | first of the two | columns of the page |\n\n",
+            "end\n",
+        ));
+        let (_, left, top) = find(&layout, "left");
+        let (_, end, bottom) = find(&layout, "end");
+        let widest_rule = layout.pages[0]
+            .elements
+            .iter()
+            .filter_map(|element| match element {
+                Element::Line { from, to, .. } => Some((to.0 - from.0).0),
+                _ => None,
+            })
+            .fold(0.0, f32::max);
+
+        // the table is ruled across the middle of the page, the columns go on below it
+        assert!(widest_rule > (layout.width * 0.5).0);
+        assert_eq!(end, left);
+        assert!(bottom < top);
+    }
+
+    #[test]
+    fn zero_columns_are_rejected() {
+        let config = testing::config();
+        let result = layout_document("---\ncolumns: 0\n---\ntext\n", Path::new("a.md"), &config);
+
+        assert!(matches!(result, Err(Error::Config(_))));
+    }
+
+    #[test]
+    fn gaps_wider_than_the_page_are_rejected() {
+        let config = testing::config();
+        let markdown = "---\ncolumns: 2\ncolumn_gap: 500mm\n---\ntext\n";
+        let result = layout_document(markdown, Path::new("a.md"), &config);
+
+        assert!(matches!(result, Err(Error::Config(_))));
+    }
+
+    #[test]
+    fn code_blocks_move_to_the_next_page_whole() {
+        let code: String = (1..=12).map(|i| format!("{}\n", i)).collect();
+        let markdown = format!("{}```\n{}```\n", "text\n\n".repeat(40), code);
+
+        let kept = layout(&markdown);
+        assert_eq!(find(&kept, "1").0, 1);
+        assert_eq!(find(&kept, "12").0, 1);
+
+        let style = Style {
+            keep_together: false,
+            ..Style::default()
+        };
+        let split = layout_with(&markdown, style);
+        assert_eq!(find(&split, "1").0, 0);
+        assert_eq!(find(&split, "12").0, 1);
+    }
+
+    #[test]
+    fn right_to_left_tables_start_on_the_right() {
+        let markdown = "| one | two |\n|---|---|\n| 1 | 2 |\n";
+        let (_, one, _) = find(&layout(markdown), "one");
+        let (_, two, _) = find(&layout(markdown), "two");
+        assert!(one < two);
+
+        let style = Style {
+            direction: Direction::Rtl,
+            ..Style::default()
+        };
+        let rtl = layout_with(markdown, style);
+        let (_, one, _) = find(&rtl, "one");
+        let (_, two, _) = find(&rtl, "two");
+        assert!(one > two);
+        assert!(one.0 > (rtl.width * 0.5).0);
+    }
+
+    #[test]
+    fn table_columns_shrink_towards_their_minimum() {
+        assert_eq!(
+            column_widths(&[10.0, 20.0], &[5.0, 5.0], 40.0),
+            [10.0, 20.0]
+        );
+        assert_eq!(column_widths(&[10.0, 30.0], &[5.0, 5.0], 25.0), [7.5, 17.5]);
+        assert_eq!(
+            column_widths(&[10.0, 30.0], &[10.0, 30.0], 20.0),
+            [5.0, 15.0]
+        );
+    }
+}
@@ -0,0 +1,73 @@
+use std::path::Path;
+
+mod assets;
+mod book;
+mod config;
+mod css;
+mod docx;
+mod epub;
+mod error;
+mod fonts;
+mod front_matter;
+mod hyphenation;
+mod include;
+mod layout;
+mod linebreak;
+mod odt;
+mod page_break;
+mod pdf;
+mod raster;
+mod render;
+mod shaping;
+mod subset;
+mod svg;
+#[cfg(test)]
+mod testing;
+mod tex;
+mod text;
+mod theme;
+mod util;
+mod walk;
+
+pub use assets::{Assets, FontData, FontFamily};
+pub use book::Book;
+pub use config::{BackendType, Config, ConfigBuilder};
+pub use css::{CssMode, CustomCss};
+pub use docx::Docx;
+pub use epub::Epub;
+pub use error::{Error, Result};
+pub use fonts::{SystemFace, SystemFonts};
+pub use include::expand as expand_includes;
+pub use odt::Odt;
+pub use printpdf::{Color, Mm, Rgb};
+pub use raster::{PngWriter, Raster};
+pub use render::{
+    Backend, Chromium, Direction, HeaderUnderline, Html, Hyphens, Inhouse, PageBreak, Style,
+    TextAlign,
+};
+pub use svg::SvgWriter;
+pub use tex::Tex;
+pub use text::PlainText;
+pub use theme::Theme;
+
+// renders a single markdown document in-process with the backend chosen in `config`
+pub fn render_markdown(markdown: &str, config: &Config) -> Result<Vec<u8>> {
+    match config.backend {
+        BackendType::Inhouse => Inhouse::render_document(markdown, Path::new("document"), config),
+        BackendType::Chromium => Chromium::render_document(markdown, Path::new("document"), config),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_renders_a_pdf() {
+        let config = testing::config();
+        let pdf = render_markdown("# Title\n\nSome text.\n", &config).unwrap();
+
+        assert_eq!(config.backend, BackendType::Inhouse);
+        assert!(pdf.starts_with(b"%PDF"));
+    }
+}
@@ -0,0 +1,476 @@
+// optimal fit line breaking, as described by knuth and plass in
+// "breaking paragraphs into lines" (1981)
+//
+// a paragraph is a list of boxes (things that get drawn), glue (stretchable space)
+// and penalties (possible breakpoints with a cost), widths are in points
+
+// penalties at or above this can never be broken at, at or below its negative always are
+pub const INFINITY: f32 = 10000.0;
+
+// added to the badness of every line, prefers fewer lines
+const LINE_PENALTY: f32 = 10.0;
+// two consecutive lines ending on a flagged penalty, e.g. two hyphens in a row
+const FLAGGED_DEMERITS: f32 = 3000.0;
+// a tight line next to a loose one
+const FITNESS_DEMERITS: f32 = 3000.0;
+
+// how far spaces may stretch, as a multiple of their stretchability, the later
+// passes only run when no solution was found with the earlier ones
+const TOLERANCES: [f32; 3] = [1.0, 2.0, 10.0];
+
+#[derive(Clone, Copy, Debug)]
+pub enum Item {
+    Box {
+        width: f32,
+    },
+    Glue {
+        width: f32,
+        stretch: f32,
+        shrink: f32,
+    },
+    Penalty {
+        width: f32,
+        cost: f32,
+        flagged: bool,
+    },
+}
+
+impl Item {
+    pub fn width(&self) -> f32 {
+        match *self {
+            Item::Box { width } | Item::Glue { width, .. } | Item::Penalty { width, .. } => width,
+        }
+    }
+
+    fn is_forced_break(&self) -> bool {
+        matches!(*self, Item::Penalty { cost, .. } if cost <= -INFINITY)
+    }
+
+    fn is_flagged(&self) -> bool {
+        matches!(*self, Item::Penalty { flagged: true, .. })
+    }
+}
+
+// a line is made of the items in start..end, `end` is the breakpoint itself:
+// broken glue is dropped, the width of a broken penalty (a hyphen) is part of the line
+#[derive(Clone, Copy, Debug)]
+pub struct Line {
+    pub start: usize,
+    pub end: usize,
+    // how much the glue on the line has to stretch (positive) or shrink (negative)
+    // to fill the line, relative to its stretchability
+    pub ratio: f32,
+}
+
+// `line_width` returns the available width for the nth line of the paragraph,
+// the items have to end with a forced break
+pub fn break_lines(items: &[Item], line_width: impl Fn(usize) -> f32) -> Vec<Line> {
+    // when even the loosest pass fails, a single word is wider than the line,
+    // fall back to filling lines first come first served and let that word stick out
+    let breaks = TOLERANCES
+        .iter()
+        .find_map(|tolerance| optimal_breaks(items, &line_width, *tolerance))
+        .unwrap_or_else(|| first_fit_breaks(items, &line_width));
+
+    let mut start = 0;
+
+    breaks
+        .iter()
+        .enumerate()
+        .map(|(line, end)| {
+            let totals = Totals::between(items, start, *end);
+            let line = Line {
+                start,
+                end: *end,
+                ratio: adjustment_ratio(totals, penalty_width(items, *end), line_width(line)),
+            };
+
+            start = next_line_start(items, *end);
+            line
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+struct Totals {
+    width: f32,
+    stretch: f32,
+    shrink: f32,
+}
+
+impl Totals {
+    fn add(&mut self, item: &Item) {
+        match *item {
+            Item::Box { width } => self.width += width,
+            Item::Glue {
+                width,
+                stretch,
+                shrink,
+            } => {
+                self.width += width;
+                self.stretch += stretch;
+                self.shrink += shrink;
+            }
+            Item::Penalty { .. } => {}
+        }
+    }
+
+    fn since(&self, earlier: &Totals) -> Totals {
+        Totals {
+            width: self.width - earlier.width,
+            stretch: self.stretch - earlier.stretch,
+            shrink: self.shrink - earlier.shrink,
+        }
+    }
+
+    fn between(items: &[Item], start: usize, end: usize) -> Totals {
+        let mut totals = Totals::default();
+        items[start..end].iter().for_each(|item| totals.add(item));
+        totals
+    }
+}
+
+struct Node {
+    position: usize,
+    line: usize,
+    fitness: usize,
+    // totals from the start of the paragraph to the first item of the next line
+    totals: Totals,
+    demerits: f32,
+    previous: Option<usize>,
+}
+
+fn optimal_breaks(
+    items: &[Item],
+    line_width: impl Fn(usize) -> f32,
+    tolerance: f32,
+) -> Option<Vec<usize>> {
+    let mut nodes = vec![Node {
+        position: 0,
+        line: 0,
+        fitness: 1,
+        totals: Totals::default(),
+        demerits: 0.0,
+        previous: None,
+    }];
+    let mut active = vec![0];
+    let mut sum = Totals::default();
+
+    for (b, item) in items.iter().enumerate() {
+        if is_legal_break(items, b) {
+            let (cost, flagged) = match *item {
+                Item::Penalty { cost, flagged, .. } => (cost, flagged),
+                _ => (0.0, false),
+            };
+
+            // the best way to get to this breakpoint, per fitness class
+            let mut candidates: [Option<(f32, usize)>; 4] = [None; 4];
+            let mut i = 0;
+
+            while i < active.len() {
+                let node = &nodes[active[i]];
+                let ratio = adjustment_ratio(
+                    sum.since(&node.totals),
+                    penalty_width(items, b),
+                    line_width(node.line),
+                );
+
+                if (-1.0..=tolerance).contains(&ratio) {
+                    let badness = 100.0 * ratio.abs().powi(3);
+                    let mut demerits = if cost >= 0.0 {
+                        (LINE_PENALTY + badness + cost).powi(2)
+                    } else if cost > -INFINITY {
+                        (LINE_PENALTY + badness).powi(2) - cost.powi(2)
+                    } else {
+                        (LINE_PENALTY + badness).powi(2)
+                    };
+
+                    if flagged && node.previous.is_some() && items[node.position].is_flagged() {
+                        demerits += FLAGGED_DEMERITS;
+                    }
+
+                    let fitness = fitness_class(ratio);
+
+                    if fitness.abs_diff(node.fitness) > 1 {
+                        demerits += FITNESS_DEMERITS;
+                    }
+
+                    demerits += node.demerits;
+
+                    if candidates[fitness].is_none_or(|(best, _)| demerits < best) {
+                        candidates[fitness] = Some((demerits, active[i]));
+                    }
+                }
+
+                // lines starting at this node can only get longer from here on
+                if ratio < -1.0 || item.is_forced_break() {
+                    active.remove(i);
+                } else {
+                    i += 1;
+                }
+            }
+
+            let mut totals = sum;
+
+            for (offset, item) in items[b..].iter().enumerate() {
+                if matches!(item, Item::Box { .. }) || (offset > 0 && item.is_forced_break()) {
+                    break;
+                }
+
+                totals.add(item);
+            }
+
+            for (fitness, candidate) in candidates.iter().enumerate() {
+                if let Some((demerits, previous)) = candidate {
+                    active.push(nodes.len());
+                    nodes.push(Node {
+                        position: b,
+                        line: nodes[*previous].line + 1,
+                        fitness,
+                        totals,
+                        demerits: *demerits,
+                        previous: Some(*previous),
+                    });
+                }
+            }
+        }
+
+        sum.add(item);
+    }
+
+    let last = items.len().checked_sub(1)?;
+    let mut best = active
+        .iter()
+        .map(|index| &nodes[*index])
+        .filter(|node| node.position == last && node.previous.is_some())
+        .min_by(|a, b| a.demerits.total_cmp(&b.demerits))?;
+
+    let mut breaks = vec![best.position];
+
+    while let Some(previous) = best.previous {
+        best = &nodes[previous];
+
+        if best.previous.is_some() {
+            breaks.push(best.position);
+        }
+    }
+
+    breaks.reverse();
+    Some(breaks)
+}
+
+fn first_fit_breaks(items: &[Item], line_width: impl Fn(usize) -> f32) -> Vec<usize> {
+    let mut breaks = vec![];
+    let mut start = 0;
+    let mut last_fit = None;
+    let mut b = 0;
+
+    while b < items.len() {
+        if is_legal_break(items, b) {
+            let totals = Totals::between(items, start, b);
+            let fits =
+                totals.width + penalty_width(items, b) - totals.shrink <= line_width(breaks.len());
+
+            let end = match (fits, last_fit) {
+                (false, Some(fit)) => Some(fit),
+                (false, None) => Some(b),
+                (true, _) if items[b].is_forced_break() => Some(b),
+                (true, _) => None,
+            };
+
+            match end {
+                Some(end) => {
+                    breaks.push(end);
+                    start = next_line_start(items, end);
+                    last_fit = None;
+                    b = start;
+                    continue;
+                }
+                None => last_fit = Some(b),
+            }
+        }
+
+        b += 1;
+    }
+
+    breaks
+}
+
+// glue is only a breakpoint right after a box, so spaces never pile up
+fn is_legal_break(items: &[Item], b: usize) -> bool {
+    match items[b] {
+        Item::Box { .. } => false,
+        Item::Glue { .. } => b > 0 && matches!(items[b - 1], Item::Box { .. }),
+        Item::Penalty { cost, .. } => cost < INFINITY,
+    }
+}
+
+// glue and penalties after a break are discarded up to the next box or forced break
+fn next_line_start(items: &[Item], b: usize) -> usize {
+    items[b + 1..]
+        .iter()
+        .position(|item| matches!(item, Item::Box { .. }) || item.is_forced_break())
+        .map_or(items.len(), |offset| b + 1 + offset)
+}
+
+fn penalty_width(items: &[Item], b: usize) -> f32 {
+    match items[b] {
+        Item::Penalty { width, .. } => width,
+        _ => 0.0,
+    }
+}
+
+fn adjustment_ratio(totals: Totals, penalty_width: f32, available: f32) -> f32 {
+    let width = totals.width + penalty_width;
+
+    if width < available {
+        if totals.stretch > 0.0 {
+            (available - width) / totals.stretch
+        } else {
+            INFINITY
+        }
+    } else if width > available {
+        if totals.shrink > 0.0 {
+            (available - width) / totals.shrink
+        } else {
+            -INFINITY
+        }
+    } else {
+        0.0
+    }
+}
+
+// tight, decent, loose, very loose
+fn fitness_class(ratio: f32) -> usize {
+    if ratio < -0.5 {
+        0
+    } else if ratio <= 0.5 {
+        1
+    } else if ratio <= 1.0 {
+        2
+    } else {
+        3
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn space() -> Item {
+        Item::Glue {
+            width: 10.0,
+            stretch: 5.0,
+            shrink: 3.0,
+        }
+    }
+
+    // words of the given widths separated by spaces, ended the way paragraphs are
+    fn paragraph(words: &[f32]) -> Vec<Item> {
+        let mut items = vec![];
+
+        for (i, width) in words.iter().enumerate() {
+            if i > 0 {
+                items.push(space());
+            }
+
+            items.push(Item::Box { width: *width });
+        }
+
+        finish(&mut items);
+        items
+    }
+
+    fn finish(items: &mut Vec<Item>) {
+        items.extend([
+            Item::Penalty {
+                width: 0.0,
+                cost: INFINITY,
+                flagged: false,
+            },
+            Item::Glue {
+                width: 0.0,
+                stretch: INFINITY,
+                shrink: 0.0,
+            },
+            Item::Penalty {
+                width: 0.0,
+                cost: -INFINITY,
+                flagged: true,
+            },
+        ]);
+    }
+
+    fn ends(lines: &[Line]) -> Vec<usize> {
+        lines.iter().map(|line| line.end).collect()
+    }
+
+    #[test]
+    fn fills_lines_that_fit_exactly() {
+        let items = paragraph(&[20.0; 8]);
+        let lines = break_lines(&items, |_| 110.0);
+
+        // four words and three spaces make 110, the space after them is dropped
+        assert_eq!(ends(&lines), vec![7, 17]);
+        assert_eq!(lines[0].ratio, 0.0);
+        assert_eq!(lines[1].start, 8);
+    }
+
+    #[test]
+    fn breaks_at_a_hyphen_when_the_word_does_not_fit() {
+        let mut items = vec![
+            Item::Box { width: 50.0 },
+            space(),
+            Item::Box { width: 30.0 },
+            Item::Penalty {
+                width: 5.0,
+                cost: 50.0,
+                flagged: true,
+            },
+            Item::Box { width: 30.0 },
+        ];
+        finish(&mut items);
+
+        let lines = break_lines(&items, |_| 100.0);
+
+        assert_eq!(ends(&lines), vec![3, 7]);
+        assert_eq!(lines[0].ratio, 1.0);
+    }
+
+    #[test]
+    fn forced_breaks_end_a_line() {
+        let mut items = paragraph(&[20.0, 20.0]);
+        items.insert(
+            1,
+            Item::Penalty {
+                width: 0.0,
+                cost: -INFINITY,
+                flagged: false,
+            },
+        );
+
+        let lines = break_lines(&items, |_| 110.0);
+
+        // the space after the break is dropped
+        assert_eq!(ends(&lines), vec![1, 6]);
+        assert_eq!(lines[1].start, 3);
+    }
+
+    #[test]
+    fn narrower_lines_get_fewer_words() {
+        let items = paragraph(&[20.0; 8]);
+        let lines = break_lines(&items, |line| if line == 0 { 80.0 } else { 110.0 });
+
+        assert_eq!(ends(&lines)[0], 5);
+    }
+
+    #[test]
+    fn overfull_words_stick_out_of_their_line() {
+        let items = paragraph(&[200.0, 20.0, 20.0]);
+        let lines = break_lines(&items, |_| 100.0);
+
+        assert_eq!(ends(&lines), vec![1, 7]);
+        assert!(lines[0].ratio < 0.0);
+        assert!(lines[1].ratio >= 0.0);
+    }
+}
@@ -1,28 +1,232 @@
 mod cli;
-mod config;
 mod fs_utils;
-mod render;
+
+use std::collections::HashMap;
+use std::io::IsTerminal;
+use std::path::Path;
 
 use clap::Parser;
-use cli::BackendType;
-use render::Backend;
-use render::Chromium;
-use std::fs::read_to_string;
+use jamdr::{
+    expand_includes, Assets, Backend, BackendType, Book, Chromium, Config, ConfigBuilder, CssMode,
+    CustomCss, Docx, Epub, FontFamily, Html, Inhouse, Odt, PlainText, PngWriter, Raster, SvgWriter,
+    SystemFonts, Tex, Theme,
+};
+use terminal_size::Width;
+
+use crate::cli::{Arguments, Commands, OutputType};
 
-use crate::cli::Arguments;
-use crate::render::Inhouse;
+type SetFont = fn(ConfigBuilder, FontFamily) -> ConfigBuilder;
 
 fn main() {
     let args = Arguments::parse().validate_args();
-    println!("{:#?}", args);
 
-    let files = fs_utils::read_files(args.file_paths).unwrap();
-    let css = read_to_string("assets/styles/light.css").unwrap();
-    let template = read_to_string("assets/templates/template.html").unwrap();
+    if let Some(Commands::Fonts {}) = args.command {
+        list_fonts();
+        return;
+    }
+
+    if let Some(Commands::View { file }) = &args.command {
+        view(file);
+        return;
+    }
+
+    let book = match args.book_manifest() {
+        Some(path) => match Book::load(path) {
+            Ok(book) => Some(book),
+            Err(err) => {
+                eprintln!("could not load book {}: {}", path.display(), err);
+                std::process::exit(1);
+            }
+        },
+        None if args.merge => Some(Book::from_files(args.file_paths.clone())),
+        None => None,
+    };
+
+    let paths = match &book {
+        Some(book) => book.chapters.clone(),
+        None => args.file_paths.clone(),
+    };
+    let files = fs_utils::read_files(paths).unwrap();
+
+    // every output sees the documents with their includes expanded
+    let files = match files
+        .into_iter()
+        .map(|(path, content)| Ok((path.clone(), expand_includes(&content, &path)?)))
+        .collect::<jamdr::Result<HashMap<_, _>>>()
+    {
+        Ok(files) => files,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    // overrides and themes are looked up next to the documents, not where jamdr runs
+    let project_root = args
+        .file_paths
+        .first()
+        .map_or_else(|| ".".into(), Assets::project_root);
+
+    let theme = match Theme::resolve(&args.theme, &project_root) {
+        Ok(theme) => theme,
+        Err(err) => {
+            eprintln!("could not load theme \"{}\": {}", args.theme, err);
+            std::process::exit(1);
+        }
+    };
+
+    let assets = match Assets::discover(&project_root) {
+        Ok(assets) => assets,
+        Err(err) => {
+            eprintln!("could not load assets: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let mut config = Config::builder()
+        .backend(args.backend)
+        .assets(assets)
+        .theme(theme)
+        .lang(&args.lang);
+
+    if let Some(path) = &args.custom_css {
+        let mode = match args.replace_css {
+            true => CssMode::Replace,
+            false => CssMode::Append,
+        };
+
+        match CustomCss::load(path, mode) {
+            Ok(custom_css) => config = config.custom_css(custom_css),
+            Err(err) => {
+                eprintln!("could not load style sheet {}: {}", path.display(), err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    for path in &args.fallback_fonts {
+        match std::fs::read(path) {
+            Ok(data) => config = config.fallback_font(data),
+            Err(err) => {
+                eprintln!("could not load fallback font {}: {}", path.display(), err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // the system is only scanned when a font is given by name
+    let mut system_fonts = None;
+
+    let roles: [(_, _, SetFont); 3] = [
+        (&args.body_font, "body", ConfigBuilder::body_font),
+        (&args.heading_font, "heading", ConfigBuilder::heading_font),
+        (
+            &args.monospace_font,
+            "monospace",
+            ConfigBuilder::monospace_font,
+        ),
+    ];
+
+    for (spec, role, set_font) in roles {
+        let Some(spec) = spec else {
+            continue;
+        };
+
+        let family = if Path::new(spec).is_file() {
+            FontFamily::load(spec)
+        } else {
+            system_fonts
+                .get_or_insert_with(SystemFonts::scan)
+                .family(spec)
+        };
+
+        let family = match family {
+            Ok(family) => family,
+            Err(err) => {
+                eprintln!("could not load {} font \"{}\": {}", role, spec, err);
+                std::process::exit(1);
+            }
+        };
 
-    let rendered_files = match args.backend {
-        BackendType::Inhouse => Inhouse::render_files(&files, template, css),
-        BackendType::Chromium => Chromium::render_files(&files, template, css),
+        config = set_font(config, family);
+    }
+
+    let config = match config.build() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("invalid configuration: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let rendered_files = match (args.output_type, args.backend) {
+        // books are always laid out inhouse, named after the manifest or the first chapter
+        _ if book.is_some() => {
+            let book = book.as_ref().unwrap();
+            let chapters: Vec<_> = book
+                .chapters
+                .iter()
+                .map(|path| (path.as_path(), files[path].as_str()))
+                .collect();
+            let output = args
+                .output_path
+                .clone()
+                .unwrap_or_else(|| args.file_paths[0].with_extension("pdf"));
+
+            Inhouse::render_book(&chapters, book.title.as_deref(), &config)
+                .map(|pdf| HashMap::from([(output, pdf)]))
+        }
+        (Some(OutputType::Svg), _) => SvgWriter::render_files(&files, &config),
+        (Some(OutputType::Png), _) => {
+            let raster = match args.thumbnail {
+                true => Raster::Thumbnail,
+                false => Raster::Pages { dpi: args.dpi },
+            };
+
+            PngWriter::render_files(&files, &config, raster)
+        }
+        // chapters in the order the files were given, the book is named after the first
+        (Some(OutputType::Epub), _) => {
+            let documents: Vec<_> = args
+                .file_paths
+                .iter()
+                .map(|path| (path.as_path(), files[path].as_str()))
+                .collect();
+            let output = args
+                .output_path
+                .clone()
+                .or_else(|| {
+                    args.file_paths
+                        .first()
+                        .map(|path| path.with_extension("epub"))
+                })
+                .unwrap_or_else(|| "book.epub".into());
+
+            Epub::render_book(&documents, &config).map(|book| HashMap::from([(output, book)]))
+        }
+        (Some(OutputType::Docx), _) => Docx::render_files(&files, &config),
+        (Some(OutputType::Odt), _) => Odt::render_files(&files, &config),
+        (Some(OutputType::Tex), _) => Tex::render_files(&files, &config),
+        (Some(OutputType::Txt), _) => PlainText::render_files(&files, args.wrap),
+        (Some(OutputType::Html), _) => Html::render_files(&files, &config),
+        (_, BackendType::Inhouse) => Inhouse::render_files(&files, &config),
+        (_, BackendType::Chromium) => Chromium::render_files(&files, &config),
+    };
+
+    let rendered_files = match rendered_files {
+        Ok(rendered_files) => rendered_files,
+        Err(err) => {
+            eprintln!("error rendering one or more file(s):\n{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    // books and epubs are named above, otherwise -o names the output of the one document
+    let rendered_files = match &args.output_path {
+        Some(output) if book.is_none() && args.output_type != Some(OutputType::Epub) => {
+            fs_utils::rename_outputs(rendered_files, &args.file_paths[0], output)
+        }
+        _ => rendered_files,
     };
 
     match fs_utils::write_files(&rendered_files) {
@@ -30,3 +234,56 @@ fn main() {
         Err(_) => println!("error writing one or more file(s)"),
     }
 }
+
+fn list_fonts() {
+    let system_fonts = SystemFonts::scan();
+
+    for (family, faces) in system_fonts.families() {
+        let monospace = match faces.iter().all(|face| face.monospace) {
+            true => " (monospace)",
+            false => "",
+        };
+
+        println!("{}{}", family, monospace);
+
+        for face in faces {
+            println!("    {:<20} {}", face.style, face.path.display());
+        }
+    }
+}
+
+fn view(path: &Path) {
+    let markdown = match std::fs::read_to_string(path) {
+        Ok(markdown) => markdown,
+        Err(err) => {
+            eprintln!("could not read {}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+    };
+
+    let markdown = match expand_includes(&markdown, path) {
+        Ok(markdown) => markdown,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let width = terminal_size::terminal_size()
+        .map(|(Width(width), _)| width as usize)
+        .unwrap_or(80);
+
+    // piped output stays plain text
+    let text = match std::io::stdout().is_terminal() {
+        true => PlainText::render_ansi(&markdown, width),
+        false => PlainText::render_document(&markdown, width),
+    };
+
+    match text {
+        Ok(text) => print!("{}", text),
+        Err(err) => {
+            eprintln!("error rendering {}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+    }
+}
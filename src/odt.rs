@@ -0,0 +1,1077 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use pulldown_cmark::{Alignment, Tag};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::assets::FontFamily;
+use crate::config::Config;
+use crate::error::Result;
+use crate::front_matter::FrontMatter;
+use crate::page_break;
+use crate::render::{
+    render_each, Backend, Direction, HeaderUnderline, Hyphens, PageBreak, Style, TextAlign,
+};
+use crate::theme::hex_color;
+use crate::util::{escape, image_info};
+use crate::walk::{self, Walker};
+
+// writes documents as opendocument text, walking the same markdown events as the
+// inhouse layout does, sizes and spacing follow the style so the pages come out close
+// to the pdf, the fonts of the config are embedded
+pub struct Odt {}
+
+impl Backend for Odt {
+    // relative image paths start next to the document
+    fn render_document(markdown: &str, path: &Path, config: &Config) -> Result<Vec<u8>> {
+        let (front_matter, markdown) = FrontMatter::split(markdown)?;
+        let mut style = config.style.clone();
+        front_matter.apply(&mut style)?;
+
+        let markdown = page_break::mark(markdown, style.asterisk_breaks);
+        let events = walk::parse(&markdown);
+
+        let base = path.parent().unwrap_or(Path::new(""));
+        let mut writer = OdtWriter::new(base, &style);
+        walk::walk(&events, &mut writer);
+        writer.finish();
+
+        let lang = front_matter.lang.as_deref().unwrap_or(&config.lang);
+        writer.package(&front_matter, lang, config)
+    }
+
+    fn render_files(
+        files: &HashMap<PathBuf, String>,
+        config: &Config,
+    ) -> Result<HashMap<PathBuf, Vec<u8>>> {
+        render_each(files, |path, content| {
+            let odt = Odt::render_document(content, path, config)?;
+
+            Ok(vec![(path.with_extension("odt"), odt)])
+        })
+    }
+}
+
+struct TableState {
+    alignments: Vec<Alignment>,
+    cell: usize,
+    head: bool,
+}
+
+// a file stored in the package next to the xml parts
+struct Resource {
+    path: String,
+    media_type: &'static str,
+    data: Arc<[u8]>,
+}
+
+// how far every list level is indented
+const LIST_INDENT_MM: f32 = 6.0;
+
+struct OdtWriter<'a> {
+    base: &'a Path,
+    style: &'a Style,
+
+    body: String,
+    // notes sit in the text where they are referenced, but markdown defines them at
+    // the end, references leave a placeholder that is filled in once all are known
+    footnote_ids: HashMap<String, usize>,
+    footnotes: HashMap<usize, String>,
+    footnote: Option<usize>,
+    pictures: Vec<Resource>,
+    tables: usize,
+
+    // the start of the ordered list whose first item comes next
+    list_start: Option<u64>,
+    list_depth: usize,
+    quote_depth: usize,
+
+    // the element of the open paragraph, `text:p` or `text:h`
+    paragraph: Option<&'static str>,
+    // whether each open link became a hyperlink, links to anchors stay plain text
+    links: Vec<bool>,
+    code_block: Option<String>,
+    table: Option<TableState>,
+    // the url and alt text of the image being read
+    image: Option<(String, String)>,
+}
+
+impl<'a> OdtWriter<'a> {
+    fn new(base: &'a Path, style: &'a Style) -> Self {
+        OdtWriter {
+            base,
+            style,
+            body: String::new(),
+            footnote_ids: HashMap::new(),
+            footnotes: HashMap::new(),
+            footnote: None,
+            pictures: vec![],
+            tables: 0,
+            list_start: None,
+            list_depth: 0,
+            quote_depth: 0,
+            paragraph: None,
+            links: vec![],
+            code_block: None,
+            table: None,
+            image: None,
+        }
+    }
+
+    fn finish(&mut self) {
+        self.body = self.fill_notes();
+    }
+
+    // where paragraphs go, the body or the note being written
+    fn out(&mut self) -> &mut String {
+        match self.footnote {
+            Some(id) => self.footnotes.get_mut(&id).unwrap(),
+            None => &mut self.body,
+        }
+    }
+
+    fn open_paragraph(&mut self, element: &'static str, style: &str, attributes: &str) {
+        self.close_paragraph();
+        self.paragraph = Some(element);
+
+        let paragraph = format!("<{} text:style-name=\"{}\"{}>", element, style, attributes);
+        self.out().push_str(&paragraph);
+    }
+
+    fn close_paragraph(&mut self) {
+        if let Some(element) = self.paragraph.take() {
+            self.out().push_str(&format!("</{}>", element));
+        }
+    }
+
+    // tight list items have their text without a paragraph around it
+    fn ensure_paragraph(&mut self) {
+        if self.paragraph.is_some() {
+            return;
+        }
+
+        let style = match (self.footnote, self.list_depth) {
+            (Some(_), _) => "Footnote",
+            (None, 0) => "Standard",
+            _ => "List_20_Contents",
+        };
+        self.open_paragraph("text:p", style, "");
+    }
+
+    fn open_span(&mut self, style: &str) {
+        self.ensure_paragraph();
+        self.out()
+            .push_str(&format!("<text:span text:style-name=\"{}\">", style));
+    }
+
+    // images that can't be read are replaced by their alt text
+    fn image(&mut self, url: &str, alt: &str) {
+        let data = match url.contains("://") {
+            true => None,
+            false => fs::read(self.base.join(url)).ok(),
+        };
+
+        let Some((data, extension, (width, height))) = data.and_then(|data| {
+            let (extension, size) = image_info(&data)?;
+            Some((data, extension, size))
+        }) else {
+            eprintln!("warning: could not embed image {}", url);
+            self.text(alt);
+            return;
+        };
+
+        let number = self.pictures.len() + 1;
+        let path = format!("Pictures/image{}.{}", number, extension);
+        self.pictures.push(Resource {
+            path: path.clone(),
+            media_type: match extension {
+                "png" => "image/png",
+                "gif" => "image/gif",
+                _ => "image/jpeg",
+            },
+            data: data.into(),
+        });
+
+        // pixels at 96 dpi, shrunk to the width of the text
+        let max_width = self.text_width();
+        let (mut width, mut height) = (width as f32 * 25.4 / 96.0, height as f32 * 25.4 / 96.0);
+
+        if width > max_width {
+            height *= max_width / width;
+            width = max_width;
+        }
+
+        let frame = format!(
+            concat!(
+                "<draw:frame draw:name=\"Image{}\" text:anchor-type=\"as-char\" svg:width=\"{:.2}mm\" svg:height=\"{:.2}mm\">",
+                "<draw:image xlink:href=\"{}\" xlink:type=\"simple\" xlink:show=\"embed\" xlink:actuate=\"onLoad\"/>",
+                "<svg:desc>{}</svg:desc></draw:frame>"
+            ),
+            number,
+            width,
+            height,
+            path,
+            escape(alt)
+        );
+
+        self.ensure_paragraph();
+        self.out().push_str(&frame);
+    }
+
+    // notes are numbered in the order they are first mentioned
+    fn footnote_id(&mut self, label: &str) -> usize {
+        let next = self.footnote_ids.len() + 1;
+        *self.footnote_ids.entry(label.to_string()).or_insert(next)
+    }
+
+    // every reference gets its own copy of the note, notes that are never defined
+    // come out empty
+    fn fill_notes(&self) -> String {
+        let mut body = self.body.clone();
+        let mut count = 0;
+
+        let mut ids: Vec<usize> = self.footnote_ids.values().copied().collect();
+        ids.sort_unstable();
+
+        for id in ids {
+            let marker = placeholder(id);
+            let content = match self.footnotes.get(&id) {
+                Some(content) if !content.is_empty() => content.as_str(),
+                _ => "<text:p text:style-name=\"Footnote\"/>",
+            };
+
+            while let Some(at) = body.find(&marker) {
+                count += 1;
+
+                let note = format!(
+                    concat!(
+                        "<text:note text:id=\"ftn{}\" text:note-class=\"footnote\">",
+                        "<text:note-citation>{}</text:note-citation>",
+                        "<text:note-body>{}</text:note-body></text:note>"
+                    ),
+                    count, id, content
+                );
+                body.replace_range(at..at + marker.len(), &note);
+            }
+        }
+
+        body
+    }
+
+    // between the horizontal margins, in millimeters
+    fn text_width(&self) -> f32 {
+        (self.style.width - self.style.horizontal_padding * 2.0).0
+    }
+
+    fn package(&self, front_matter: &FrontMatter, lang: &str, config: &Config) -> Result<Vec<u8>> {
+        let mut resources: Vec<&Resource> = self.pictures.iter().collect();
+        let fonts = fonts(config);
+        resources.extend(fonts.iter().flat_map(|font| &font.files));
+
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        // the mimetype has to come first and uncompressed, readers sniff it
+        zip.start_file("mimetype", stored)?;
+        zip.write_all(MIMETYPE.as_bytes())?;
+
+        let parts = [
+            ("META-INF/manifest.xml", manifest(&resources)),
+            ("meta.xml", meta(front_matter, lang)),
+            ("settings.xml", SETTINGS.to_string()),
+            ("styles.xml", styles(self.style, lang, &fonts)),
+            ("content.xml", self.content()),
+        ];
+
+        for (name, content) in parts {
+            zip.start_file(name, deflated)?;
+            zip.write_all(content.as_bytes())?;
+        }
+
+        for resource in resources {
+            zip.start_file(&resource.path, deflated)?;
+            zip.write_all(&resource.data)?;
+        }
+
+        Ok(zip.finish()?.into_inner())
+    }
+
+    // the styles that only the text uses, page breaks and tables
+    fn content(&self) -> String {
+        let mut automatic = format!(
+            concat!(
+                "<style:style style:name=\"PageBreak\" style:family=\"paragraph\" style:parent-style-name=\"Standard\">",
+                "<style:paragraph-properties fo:break-after=\"page\"/></style:style>",
+                "<style:style style:name=\"Table\" style:family=\"table\">",
+                "<style:table-properties style:width=\"{:.2}mm\" table:align=\"margins\" fo:margin-bottom=\"{:.2}pt\"/></style:style>",
+                "<style:style style:name=\"TableCell\" style:family=\"table-cell\">",
+                "<style:table-cell-properties fo:border=\"0.5pt solid #{}\" fo:padding=\"1mm\"/></style:style>"
+            ),
+            self.text_width(),
+            self.style.font_size * self.style.line_height,
+            hex_color(&self.style.rule_color)
+        );
+
+        for base in ["Table_20_Contents", "Table_20_Heading"] {
+            for alignment in ["center", "right"] {
+                let text_align = match alignment {
+                    "right" => "end",
+                    _ => alignment,
+                };
+
+                automatic.push_str(&format!(
+                    concat!(
+                        "<style:style style:name=\"{0}_{1}\" style:family=\"paragraph\" style:parent-style-name=\"{0}\">",
+                        "<style:paragraph-properties fo:text-align=\"{2}\"/></style:style>"
+                    ),
+                    base, alignment, text_align
+                ));
+            }
+        }
+
+        format!(
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                "<office:document-content {} office:version=\"1.3\">",
+                "<office:automatic-styles>{}</office:automatic-styles>",
+                "<office:body><office:text>{}</office:text></office:body></office:document-content>"
+            ),
+            NAMESPACES, automatic, self.body
+        )
+    }
+}
+
+impl<'a> Walker<'a> for OdtWriter<'a> {
+    fn start(&mut self, tag: Tag<'a>) {
+        match tag {
+            Tag::Paragraph => {
+                let style = match (self.footnote, self.quote_depth) {
+                    (Some(_), _) => "Footnote",
+                    (None, 0) => "Text_20_body",
+                    _ => "Quotations",
+                };
+                self.open_paragraph("text:p", style, "");
+            }
+            Tag::Heading(level, _, _) => {
+                self.close_paragraph();
+
+                let level = level as usize;
+                self.open_paragraph(
+                    "text:h",
+                    &format!("Heading_20_{}", level),
+                    &format!(" text:outline-level=\"{}\"", level),
+                );
+            }
+            Tag::BlockQuote => {
+                self.close_paragraph();
+                self.quote_depth += 1;
+            }
+            Tag::CodeBlock(_) => {
+                self.close_paragraph();
+                self.code_block = Some(String::new());
+            }
+            Tag::List(start) => {
+                self.close_paragraph();
+
+                let style = match start {
+                    Some(_) => "Numbering_20_123",
+                    None => "List_20_Bullet",
+                };
+                self.out()
+                    .push_str(&format!("<text:list text:style-name=\"{}\">", style));
+                self.list_start = start;
+                self.list_depth += 1;
+            }
+            Tag::Item => {
+                self.close_paragraph();
+
+                let item = match self.list_start.take() {
+                    Some(start) if start != 1 => {
+                        format!("<text:list-item text:start-value=\"{}\">", start)
+                    }
+                    _ => "<text:list-item>".to_string(),
+                };
+                self.out().push_str(&item);
+            }
+            Tag::FootnoteDefinition(label) => {
+                self.close_paragraph();
+
+                let id = self.footnote_id(&label);
+                self.footnotes.insert(id, String::new());
+                self.footnote = Some(id);
+            }
+            Tag::Table(alignments) => {
+                self.close_paragraph();
+                self.tables += 1;
+
+                let table = format!(
+                    "<table:table table:name=\"Table{}\" table:style-name=\"Table\"><table:table-column table:number-columns-repeated=\"{}\"/>",
+                    self.tables,
+                    alignments.len().max(1)
+                );
+                self.out().push_str(&table);
+                self.table = Some(TableState {
+                    alignments,
+                    cell: 0,
+                    head: false,
+                });
+            }
+            Tag::TableHead => {
+                self.out()
+                    .push_str("<table:table-header-rows><table:table-row>");
+                let table = self.table.as_mut().unwrap();
+                table.head = true;
+                table.cell = 0;
+            }
+            Tag::TableRow => {
+                self.out().push_str("<table:table-row>");
+                self.table.as_mut().unwrap().cell = 0;
+            }
+            Tag::TableCell => {
+                let table = self.table.as_ref().unwrap();
+                let base = match table.head {
+                    true => "Table_20_Heading",
+                    false => "Table_20_Contents",
+                };
+                let style = match table.alignments.get(table.cell) {
+                    Some(Alignment::Center) => format!("{}_center", base),
+                    Some(Alignment::Right) => format!("{}_right", base),
+                    _ => base.to_string(),
+                };
+
+                self.out().push_str(
+                    "<table:table-cell table:style-name=\"TableCell\" office:value-type=\"string\">",
+                );
+                self.open_paragraph("text:p", &style, "");
+            }
+            Tag::Emphasis => self.open_span("Emphasis"),
+            Tag::Strong => self.open_span("Strong_20_Emphasis"),
+            Tag::Strikethrough => self.open_span("Strikethrough"),
+            Tag::Link(_, url, _) => {
+                self.ensure_paragraph();
+
+                let hyperlink = !url.starts_with('#');
+
+                if hyperlink {
+                    let link = format!(
+                        "<text:a xlink:type=\"simple\" xlink:href=\"{}\" text:style-name=\"Internet_20_link\">",
+                        escape(&url)
+                    );
+                    self.out().push_str(&link);
+                }
+
+                self.links.push(hyperlink);
+            }
+            Tag::Image(_, url, _) => self.image = Some((url.to_string(), String::new())),
+        }
+    }
+
+    fn end(&mut self, tag: Tag<'a>) {
+        match tag {
+            Tag::Paragraph | Tag::Heading(..) => self.close_paragraph(),
+            Tag::BlockQuote => {
+                self.close_paragraph();
+                self.quote_depth -= 1;
+            }
+            Tag::CodeBlock(_) => {
+                let code = self.code_block.take().unwrap();
+                let lines: Vec<String> = code
+                    .trim_end_matches('\n')
+                    .split('\n')
+                    .map(preserve_spaces)
+                    .collect();
+
+                let paragraph = format!(
+                    "<text:p text:style-name=\"Preformatted_20_Text\">{}</text:p>",
+                    lines.join("<text:line-break/>")
+                );
+                self.out().push_str(&paragraph);
+            }
+            Tag::List(_) => {
+                self.close_paragraph();
+                self.out().push_str("</text:list>");
+                self.list_depth -= 1;
+            }
+            Tag::Item => {
+                self.close_paragraph();
+                self.out().push_str("</text:list-item>");
+            }
+            Tag::FootnoteDefinition(_) => {
+                self.close_paragraph();
+                self.footnote = None;
+            }
+            Tag::Table(_) => {
+                self.out().push_str("</table:table>");
+                self.table = None;
+            }
+            Tag::TableHead => {
+                self.out()
+                    .push_str("</table:table-row></table:table-header-rows>");
+                self.table.as_mut().unwrap().head = false;
+            }
+            Tag::TableRow => self.out().push_str("</table:table-row>"),
+            Tag::TableCell => {
+                self.close_paragraph();
+                self.out().push_str("</table:table-cell>");
+                self.table.as_mut().unwrap().cell += 1;
+            }
+            Tag::Emphasis | Tag::Strong | Tag::Strikethrough => self.out().push_str("</text:span>"),
+            Tag::Link(..) => {
+                if self.links.pop() == Some(true) {
+                    self.out().push_str("</text:a>");
+                }
+            }
+            Tag::Image(..) => {
+                let (url, alt) = self.image.take().unwrap();
+                self.image(&url, &alt);
+            }
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if let Some(code) = &mut self.code_block {
+            code.push_str(text);
+        } else if let Some((_, alt)) = &mut self.image {
+            alt.push_str(text);
+        } else {
+            self.ensure_paragraph();
+            self.out().push_str(&escape(text));
+        }
+    }
+
+    fn code(&mut self, code: &str) {
+        self.ensure_paragraph();
+        let span = format!(
+            "<text:span text:style-name=\"Source_20_Text\">{}</text:span>",
+            escape(code)
+        );
+        self.out().push_str(&span);
+    }
+
+    // notes can't hold notes, references inside them are dropped
+    fn footnote_reference(&mut self, label: &str) {
+        let id = self.footnote_id(label);
+
+        if self.footnote.is_none() {
+            self.ensure_paragraph();
+            self.body.push_str(&placeholder(id));
+        }
+    }
+
+    fn soft_break(&mut self) {
+        self.text(" ");
+    }
+
+    fn hard_break(&mut self) {
+        self.ensure_paragraph();
+        self.out().push_str("<text:line-break/>");
+    }
+
+    fn rule(&mut self) {
+        self.close_paragraph();
+        self.out()
+            .push_str("<text:p text:style-name=\"Horizontal_20_Line\"/>");
+    }
+
+    fn task_list_marker(&mut self, checked: bool) {
+        match checked {
+            true => self.text("\u{2612} "),
+            false => self.text("\u{2610} "),
+        }
+    }
+
+    fn page_break(&mut self) {
+        self.out()
+            .push_str("<text:p text:style-name=\"PageBreak\"/>");
+    }
+}
+
+const MIMETYPE: &str = "application/vnd.oasis.opendocument.text";
+
+const NAMESPACES: &str = concat!(
+    "xmlns:office=\"urn:oasis:names:tc:opendocument:xmlns:office:1.0\" ",
+    "xmlns:style=\"urn:oasis:names:tc:opendocument:xmlns:style:1.0\" ",
+    "xmlns:text=\"urn:oasis:names:tc:opendocument:xmlns:text:1.0\" ",
+    "xmlns:table=\"urn:oasis:names:tc:opendocument:xmlns:table:1.0\" ",
+    "xmlns:draw=\"urn:oasis:names:tc:opendocument:xmlns:drawing:1.0\" ",
+    "xmlns:fo=\"urn:oasis:names:tc:opendocument:xmlns:xsl-fo-compatible:1.0\" ",
+    "xmlns:svg=\"urn:oasis:names:tc:opendocument:xmlns:svg-compatible:1.0\" ",
+    "xmlns:xlink=\"http://www.w3.org/1999/xlink\""
+);
+
+// libreoffice only loads the embedded fonts when told to
+const SETTINGS: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+    "<office:document-settings xmlns:office=\"urn:oasis:names:tc:opendocument:xmlns:office:1.0\" ",
+    "xmlns:config=\"urn:oasis:names:tc:opendocument:xmlns:config:1.0\" office:version=\"1.3\">",
+    "<office:settings><config:config-item-set config:name=\"ooo:configuration-settings\">",
+    "<config:config-item config:name=\"EmbedFonts\" config:type=\"boolean\">true</config:config-item>",
+    "</config:config-item-set></office:settings></office:document-settings>"
+);
+
+// a font face declaration, with the files of the family when it is embedded
+struct FontFace {
+    name: &'static str,
+    family: String,
+    monospace: bool,
+    files: Vec<Resource>,
+}
+
+// one face declaration per role, roles without their own family use the body fonts
+// except for code, which falls back to a monospace font the reader has
+fn fonts(config: &Config) -> Vec<FontFace> {
+    let fonts = &config.assets.fonts;
+    let roles: [(&'static str, Option<&FontFamily>); 3] = [
+        ("Body", Some(&fonts.body)),
+        (
+            "Headings",
+            Some(fonts.headings.as_ref().unwrap_or(&fonts.body)),
+        ),
+        ("Monospace", fonts.monospace.as_ref()),
+    ];
+
+    let mut embedded: Vec<Arc<[u8]>> = vec![];
+
+    roles
+        .into_iter()
+        .map(|(name, family)| {
+            let Some(family) = family else {
+                return FontFace {
+                    name,
+                    family: "Courier New".to_string(),
+                    monospace: true,
+                    files: vec![],
+                };
+            };
+
+            let mut files = vec![];
+
+            for face in family.faces() {
+                if embedded.iter().any(|known| Arc::ptr_eq(known, face)) {
+                    continue;
+                }
+
+                embedded.push(face.clone());
+
+                let extension = match face.starts_with(b"OTTO") {
+                    true => "otf",
+                    false => "ttf",
+                };
+                files.push(Resource {
+                    path: format!("Fonts/font-{}.{}", embedded.len(), extension),
+                    media_type: "application/x-font-ttf",
+                    data: face.clone(),
+                });
+            }
+
+            FontFace {
+                name,
+                family: family.family_name().unwrap_or("Roboto".to_string()),
+                monospace: name == "Monospace",
+                files,
+            }
+        })
+        .collect()
+}
+
+fn manifest(resources: &[&Resource]) -> String {
+    let mut manifest = format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<manifest:manifest xmlns:manifest=\"urn:oasis:names:tc:opendocument:xmlns:manifest:1.0\" manifest:version=\"1.3\">",
+            "<manifest:file-entry manifest:full-path=\"/\" manifest:version=\"1.3\" manifest:media-type=\"{}\"/>"
+        ),
+        MIMETYPE
+    );
+
+    for part in ["content.xml", "styles.xml", "meta.xml", "settings.xml"] {
+        manifest.push_str(&format!(
+            "<manifest:file-entry manifest:full-path=\"{}\" manifest:media-type=\"text/xml\"/>",
+            part
+        ));
+    }
+
+    for resource in resources {
+        manifest.push_str(&format!(
+            "<manifest:file-entry manifest:full-path=\"{}\" manifest:media-type=\"{}\"/>",
+            resource.path, resource.media_type
+        ));
+    }
+
+    manifest.push_str("</manifest:manifest>");
+    manifest
+}
+
+fn meta(front_matter: &FrontMatter, lang: &str) -> String {
+    let mut meta = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<office:document-meta xmlns:office=\"urn:oasis:names:tc:opendocument:xmlns:office:1.0\" ",
+        "xmlns:meta=\"urn:oasis:names:tc:opendocument:xmlns:meta:1.0\" ",
+        "xmlns:dc=\"http://purl.org/dc/elements/1.1/\" office:version=\"1.3\">",
+        "<office:meta><meta:generator>jamdr</meta:generator>"
+    ));
+
+    let properties = [
+        ("dc:title", front_matter.title.as_deref()),
+        ("meta:initial-creator", front_matter.author.as_deref()),
+        ("dc:creator", front_matter.author.as_deref()),
+        ("dc:description", front_matter.description.as_deref()),
+        ("dc:language", Some(lang)),
+    ];
+
+    for (element, value) in properties {
+        if let Some(value) = value {
+            meta.push_str(&format!("<{0}>{1}</{0}>", element, escape(value)));
+        }
+    }
+
+    meta.push_str("</office:meta></office:document-meta>");
+    meta
+}
+
+// the named styles follow the inhouse layout, a line of the font size times the line
+// height, and one empty line after paragraphs
+fn styles(style: &Style, lang: &str, fonts: &[FontFace]) -> String {
+    let line = |size: f32| format!("{:.2}pt", size * style.line_height);
+    let rule = format!("0.75pt solid #{}", hex_color(&style.rule_color));
+    let (language, country) = match lang.split_once('-') {
+        Some((language, country)) => (language, Some(country)),
+        None => (lang, None),
+    };
+
+    let mut declarations = String::new();
+
+    for font in fonts {
+        let pitch = match font.monospace {
+            true => " style:font-family-generic=\"modern\" style:font-pitch=\"fixed\"",
+            false => " style:font-pitch=\"variable\"",
+        };
+        let sources: String = font
+            .files
+            .iter()
+            .map(|file| {
+                format!(
+                    "<svg:font-face-uri xlink:href=\"{}\" xlink:type=\"simple\"><svg:font-face-format svg:string=\"truetype\"/></svg:font-face-uri>",
+                    file.path
+                )
+            })
+            .collect();
+
+        declarations.push_str(&format!(
+            "<style:font-face style:name=\"{}\" svg:font-family=\"'{}'\"{}>",
+            font.name,
+            escape(&font.family),
+            pitch
+        ));
+
+        if !sources.is_empty() {
+            declarations.push_str(&format!(
+                "<svg:font-face-src>{}</svg:font-face-src>",
+                sources
+            ));
+        }
+
+        declarations.push_str("</style:font-face>");
+    }
+
+    let mut styles = format!(
+        concat!(
+            "<style:default-style style:family=\"paragraph\">",
+            "<style:paragraph-properties style:writing-mode=\"{}\"/>",
+            "<style:text-properties style:font-name=\"Body\" style:font-name-complex=\"Body\" fo:font-size=\"{}pt\" ",
+            "fo:color=\"#{}\" fo:language=\"{}\"{} fo:hyphenate=\"{}\"/></style:default-style>",
+            "<style:style style:name=\"Standard\" style:family=\"paragraph\" style:class=\"text\">",
+            "<style:paragraph-properties fo:line-height=\"{}\" fo:text-align=\"{}\"/></style:style>",
+            "<style:style style:name=\"Text_20_body\" style:display-name=\"Text body\" style:family=\"paragraph\" ",
+            "style:parent-style-name=\"Standard\" style:class=\"text\">",
+            "<style:paragraph-properties fo:margin-top=\"0pt\" fo:margin-bottom=\"{}\"/></style:style>",
+            "<style:style style:name=\"Heading\" style:family=\"paragraph\" style:parent-style-name=\"Standard\" ",
+            "style:next-style-name=\"Text_20_body\" style:class=\"text\">",
+            "<style:paragraph-properties fo:text-align=\"start\"{}{}/>",
+            "<style:text-properties style:font-name=\"Headings\" style:font-name-complex=\"Headings\" fo:font-weight=\"bold\" ",
+            "style:font-weight-complex=\"bold\"/></style:style>"
+        ),
+        match style.direction {
+            Direction::Rtl => "rl-tb",
+            _ => "page",
+        },
+        style.font_size,
+        hex_color(&style.text_color),
+        escape(language),
+        country.map_or(String::new(), |country| format!(
+            " fo:country=\"{}\"",
+            escape(country)
+        )),
+        style.hyphens == Hyphens::Auto,
+        line(style.font_size),
+        match style.text_align {
+            TextAlign::Left => "start",
+            TextAlign::Justify => "justify",
+            TextAlign::Center => "center",
+            TextAlign::Right => "end",
+        },
+        line(style.font_size),
+        match style.keep_with_next {
+            true => " fo:keep-with-next=\"always\"",
+            false => "",
+        },
+        match style.underline_headings {
+            HeaderUnderline::FullPage => format!(
+                " fo:border-bottom=\"{}\" fo:padding-bottom=\"{:.2}pt\"",
+                rule,
+                style.font_size * 0.25
+            ),
+            HeaderUnderline::None => String::new(),
+        }
+    );
+
+    for (i, size) in style.heading_sizes.iter().enumerate() {
+        let level = i + 1;
+        let page_break = match (level, style.h1_break) {
+            (1, PageBreak::Page | PageBreak::Right) => " fo:break-before=\"page\"",
+            _ => "",
+        };
+
+        styles.push_str(&format!(
+            concat!(
+                "<style:style style:name=\"Heading_20_{0}\" style:display-name=\"Heading {0}\" style:family=\"paragraph\" ",
+                "style:parent-style-name=\"Heading\" style:next-style-name=\"Text_20_body\" ",
+                "style:default-outline-level=\"{0}\" style:class=\"text\">",
+                "<style:paragraph-properties fo:line-height=\"{1}\"{2}/>",
+                "<style:text-properties fo:font-size=\"{3}pt\" style:font-size-complex=\"{3}pt\"/></style:style>"
+            ),
+            level,
+            line(*size),
+            page_break,
+            size
+        ));
+    }
+
+    styles.push_str(&format!(
+        concat!(
+            "<style:style style:name=\"Quotations\" style:family=\"paragraph\" style:parent-style-name=\"Text_20_body\" style:class=\"html\">",
+            "<style:paragraph-properties fo:margin-left=\"10mm\" fo:border-left=\"{0}\" fo:padding-left=\"3mm\"/></style:style>",
+            "<style:style style:name=\"Preformatted_20_Text\" style:display-name=\"Preformatted Text\" style:family=\"paragraph\" ",
+            "style:parent-style-name=\"Standard\" style:class=\"html\">",
+            "<style:paragraph-properties fo:text-align=\"start\" fo:margin-bottom=\"{1}\" fo:background-color=\"#F6F8FA\"/>",
+            "<style:text-properties style:font-name=\"Monospace\" style:font-name-complex=\"Monospace\"/></style:style>",
+            "<style:style style:name=\"List_20_Contents\" style:display-name=\"List Contents\" style:family=\"paragraph\" ",
+            "style:parent-style-name=\"Standard\" style:class=\"list\"/>",
+            "<style:style style:name=\"Table_20_Contents\" style:display-name=\"Table Contents\" style:family=\"paragraph\" ",
+            "style:parent-style-name=\"Standard\" style:class=\"extra\"><style:paragraph-properties fo:text-align=\"start\"/></style:style>",
+            "<style:style style:name=\"Table_20_Heading\" style:display-name=\"Table Heading\" style:family=\"paragraph\" ",
+            "style:parent-style-name=\"Table_20_Contents\" style:class=\"extra\">",
+            "<style:text-properties fo:font-weight=\"bold\" style:font-weight-complex=\"bold\"/></style:style>",
+            "<style:style style:name=\"Footnote\" style:family=\"paragraph\" style:parent-style-name=\"Standard\" style:class=\"extra\">",
+            "<style:paragraph-properties fo:line-height=\"{2}\"/>",
+            "<style:text-properties fo:font-size=\"{3}pt\" style:font-size-complex=\"{3}pt\"/></style:style>",
+            "<style:style style:name=\"Horizontal_20_Line\" style:display-name=\"Horizontal Line\" style:family=\"paragraph\" ",
+            "style:parent-style-name=\"Standard\" style:class=\"html\">",
+            "<style:paragraph-properties fo:margin-bottom=\"{1}\" fo:border-bottom=\"{0}\"/>",
+            "<style:text-properties fo:font-size=\"1pt\"/></style:style>",
+            "<style:style style:name=\"Emphasis\" style:family=\"text\">",
+            "<style:text-properties fo:font-style=\"italic\" style:font-style-complex=\"italic\"/></style:style>",
+            "<style:style style:name=\"Strong_20_Emphasis\" style:display-name=\"Strong Emphasis\" style:family=\"text\">",
+            "<style:text-properties fo:font-weight=\"bold\" style:font-weight-complex=\"bold\"/></style:style>",
+            "<style:style style:name=\"Strikethrough\" style:family=\"text\">",
+            "<style:text-properties style:text-line-through-style=\"solid\" style:text-line-through-type=\"single\"/></style:style>",
+            "<style:style style:name=\"Source_20_Text\" style:display-name=\"Source Text\" style:family=\"text\">",
+            "<style:text-properties style:font-name=\"Monospace\" style:font-name-complex=\"Monospace\"/></style:style>",
+            "<style:style style:name=\"Internet_20_link\" style:display-name=\"Internet link\" style:family=\"text\">",
+            "<style:text-properties fo:color=\"#{4}\" style:text-underline-style=\"solid\" style:text-underline-width=\"auto\" ",
+            "style:text-underline-color=\"font-color\"/></style:style>"
+        ),
+        rule,
+        line(style.font_size),
+        line(style.font_size * 0.8),
+        style.font_size * 0.8,
+        hex_color(&style.link_color)
+    ));
+
+    for (name, display_name, numbered) in [
+        ("List_20_Bullet", "List Bullet", false),
+        ("Numbering_20_123", "Numbering 123", true),
+    ] {
+        styles.push_str(&format!(
+            "<text:list-style style:name=\"{}\" style:display-name=\"{}\">",
+            name, display_name
+        ));
+
+        for level in 1..=10 {
+            let indent = LIST_INDENT_MM * level as f32;
+            let properties = format!(
+                concat!(
+                    "<style:list-level-properties text:list-level-position-and-space-mode=\"label-alignment\">",
+                    "<style:list-level-label-alignment text:label-followed-by=\"listtab\" ",
+                    "text:list-tab-stop-position=\"{0:.2}mm\" fo:text-indent=\"-{1:.2}mm\" fo:margin-left=\"{0:.2}mm\"/>",
+                    "</style:list-level-properties>"
+                ),
+                indent, LIST_INDENT_MM
+            );
+
+            styles.push_str(&match numbered {
+                true => format!(
+                    "<text:list-level-style-number text:level=\"{}\" style:num-suffix=\".\" style:num-format=\"1\">{}</text:list-level-style-number>",
+                    level, properties
+                ),
+                false => format!(
+                    "<text:list-level-style-bullet text:level=\"{}\" text:bullet-char=\"{}\">{}</text:list-level-style-bullet>",
+                    level,
+                    ["\u{2022}", "\u{25e6}", "\u{25aa}"][(level - 1) % 3],
+                    properties
+                ),
+            });
+        }
+
+        styles.push_str("</text:list-style>");
+    }
+
+    let background = match &style.background_color {
+        Some(color) => format!(" fo:background-color=\"#{}\"", hex_color(color)),
+        None => String::new(),
+    };
+    let columns = match style.columns {
+        1 => String::new(),
+        columns => format!(
+            "<style:columns fo:column-count=\"{}\" fo:column-gap=\"{:.2}mm\">{}</style:columns>",
+            columns,
+            style.column_gap.0,
+            match &style.column_rule {
+                Some(color) => format!(
+                    "<style:column-sep style:width=\"0.75pt\" style:color=\"#{}\" style:height=\"100%\"/>",
+                    hex_color(color)
+                ),
+                None => String::new(),
+            }
+        ),
+    };
+
+    format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<office:document-styles {} office:version=\"1.3\">",
+            "<office:font-face-decls>{}</office:font-face-decls>",
+            "<office:styles>{}</office:styles>",
+            "<office:automatic-styles><style:page-layout style:name=\"Page\">",
+            "<style:page-layout-properties fo:page-width=\"{:.2}mm\" fo:page-height=\"{:.2}mm\" ",
+            "fo:margin-top=\"{v:.2}mm\" fo:margin-bottom=\"{v:.2}mm\" fo:margin-left=\"{h:.2}mm\" fo:margin-right=\"{h:.2}mm\"{}>",
+            "{}</style:page-layout-properties></style:page-layout></office:automatic-styles>",
+            "<office:master-styles><style:master-page style:name=\"Standard\" style:page-layout-name=\"Page\"/></office:master-styles>",
+            "</office:document-styles>"
+        ),
+        NAMESPACES,
+        declarations,
+        styles,
+        style.width.0,
+        style.height.0,
+        background,
+        columns,
+        v = style.vertical_padding.0,
+        h = style.horizontal_padding.0,
+    )
+}
+
+fn placeholder(id: usize) -> String {
+    format!("<!--note {}-->", id)
+}
+
+// opendocument collapses runs of spaces like html does, code keeps them as spacers
+fn preserve_spaces(line: &str) -> String {
+    let mut preserved = String::new();
+    let mut spaces = 0;
+
+    for character in line.chars().chain(['\n']) {
+        if character == ' ' {
+            spaces += 1;
+            continue;
+        }
+
+        match (spaces, preserved.is_empty()) {
+            (0, _) => {}
+            (1, false) => preserved.push(' '),
+            (count, true) => preserved.push_str(&format!("<text:s text:c=\"{}\"/>", count)),
+            (count, false) => preserved.push_str(&format!(" <text:s text:c=\"{}\"/>", count - 1)),
+        }
+
+        spaces = 0;
+
+        match character {
+            '\t' => preserved.push_str("<text:tab/>"),
+            '\n' => {}
+            _ => preserved.push_str(&escape(&character.to_string())),
+        }
+    }
+
+    preserved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::io::Read;
+    use zip::ZipArchive;
+
+    fn content_xml(markdown: &str) -> String {
+        let odt = Odt::render_document(markdown, Path::new("doc.md"), &testing::config()).unwrap();
+        let mut archive = ZipArchive::new(Cursor::new(odt)).unwrap();
+        let mut content = String::new();
+        archive
+            .by_name("content.xml")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+
+        content
+    }
+
+    #[test]
+    fn headings_lists_and_tables_are_written() {
+        let content = content_xml(concat!(
+            "# Title\n\n## Sub\n\n",
+            "- one\n- two\n\n",
+            "3. three\n4. four\n\n",
+            "| head | more |\n|---|---|\n| cell | last |\n",
+        ));
+        let cell = "<table:table-cell table:style-name=\"TableCell\" office:value-type=\"string\">";
+        let start = content.find("<office:text>").unwrap();
+        let end = content.find("</office:text>").unwrap();
+
+        assert_eq!(
+            &content[start + "<office:text>".len()..end],
+            [
+                "<text:h text:style-name=\"Heading_20_1\" text:outline-level=\"1\">Title</text:h>",
+                "<text:h text:style-name=\"Heading_20_2\" text:outline-level=\"2\">Sub</text:h>",
+                "<text:list text:style-name=\"List_20_Bullet\">",
+                "<text:list-item><text:p text:style-name=\"List_20_Contents\">one</text:p></text:list-item>",
+                "<text:list-item><text:p text:style-name=\"List_20_Contents\">two</text:p></text:list-item>",
+                "</text:list>",
+                "<text:list text:style-name=\"Numbering_20_123\">",
+                "<text:list-item text:start-value=\"3\"><text:p text:style-name=\"List_20_Contents\">three</text:p></text:list-item>",
+                "<text:list-item><text:p text:style-name=\"List_20_Contents\">four</text:p></text:list-item>",
+                "</text:list>",
+                "<table:table table:name=\"Table1\" table:style-name=\"Table\">",
+                "<table:table-column table:number-columns-repeated=\"2\"/>",
+                "<table:table-header-rows><table:table-row>",
+                cell,
+                "<text:p text:style-name=\"Table_20_Heading\">head</text:p></table:table-cell>",
+                cell,
+                "<text:p text:style-name=\"Table_20_Heading\">more</text:p></table:table-cell>",
+                "</table:table-row></table:table-header-rows>",
+                "<table:table-row>",
+                cell,
+                "<text:p text:style-name=\"Table_20_Contents\">cell</text:p></table:table-cell>",
+                cell,
+                "<text:p text:style-name=\"Table_20_Contents\">last</text:p></table:table-cell>",
+                "</table:table-row>",
+                "</table:table>",
+            ]
+            .concat()
+        );
+    }
+}
@@ -0,0 +1,119 @@
+// forced page breaks can be written as a `\pagebreak` line or a div like
+// `<div style="page-break-after: always"></div>`, thematic breaks made of asterisks
+// (`***`, `* * *`) are page breaks too in documents that set `asterisk_breaks`, dashes
+// and underscores always stay horizontal rules
+//
+// all of them are rewritten to a `\pagebreak` paragraph before parsing, so both backends
+// only have to look for that
+
+// the text of the paragraph a page break becomes
+pub const MARKER: &str = "\\pagebreak";
+
+// what the marker paragraph turns into on the html path
+pub const HTML: &str = "<div class=\"page-break\"></div>";
+
+// rewrites every page break in `markdown` to a marker paragraph, `asterisks` says whether
+// thematic breaks of asterisks count, fenced and indented code is left alone
+pub fn mark(markdown: &str, asterisks: bool) -> String {
+    let mut out = String::with_capacity(markdown.len());
+    let mut fence: Option<&str> = None;
+    let mut closing_div = false;
+
+    for line in markdown.split_inclusive('\n') {
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        let trimmed = line.trim();
+
+        if let Some(open) = fence {
+            if trimmed.starts_with(open) && trimmed.trim_start_matches(&open[..1]).is_empty() {
+                fence = None;
+            }
+
+            out.push_str(line);
+            continue;
+        }
+
+        // the closing tag of a page break div written on its own line
+        if closing_div && trimmed == "</div>" {
+            closing_div = false;
+            continue;
+        }
+
+        closing_div = false;
+
+        if indent < 4 && (trimmed.starts_with("```") || trimmed.starts_with("~~~")) {
+            let marker = &trimmed[..1];
+            let length = trimmed.len() - trimmed.trim_start_matches(marker).len();
+            fence = Some(&trimmed[..length]);
+        } else if indent < 4 && is_page_break(trimmed, asterisks) {
+            closing_div = trimmed.starts_with("<div") && !trimmed.ends_with("</div>");
+
+            // the indentation keeps a break inside a list item in that item
+            out.push('\n');
+            out.push_str(&line[..indent]);
+            out.push_str(MARKER);
+            out.push_str("\n\n");
+            continue;
+        }
+
+        out.push_str(line);
+    }
+
+    out
+}
+
+fn is_page_break(line: &str, asterisks: bool) -> bool {
+    if line == MARKER {
+        return true;
+    }
+
+    if asterisks && line.starts_with('*') && line.chars().all(|c| c == '*' || c == ' ' || c == '\t')
+    {
+        return line.matches('*').count() >= 3;
+    }
+
+    let line = line.to_ascii_lowercase();
+
+    line.starts_with("<div")
+        && (line.contains("page-break-after") || line.contains("break-after: page"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::walk;
+    use pulldown_cmark::{Event, Tag};
+
+    // page breaks, rules and lists after marking
+    fn count(markdown: &str, asterisks: bool) -> (usize, usize, usize) {
+        let marked = mark(markdown, asterisks);
+        let events = walk::parse(&marked);
+        let matching = |test: fn(&Event) -> bool| events.iter().filter(|e| test(e)).count();
+
+        (
+            matching(|event| matches!(event, Event::Text(text) if text.as_ref() == MARKER)),
+            matching(|event| matches!(event, Event::Rule)),
+            matching(|event| matches!(event, Event::Start(Tag::List(_)))),
+        )
+    }
+
+    #[test]
+    fn breaks_in_list_items_stay_in_the_list() {
+        assert_eq!(count("1. one\n   \\pagebreak\n2. two\n", false), (1, 0, 1));
+        assert_eq!(count("- one\n\n  \\pagebreak\n- two\n", false), (1, 0, 1));
+    }
+
+    #[test]
+    fn asterisk_rules_are_page_breaks_only_when_asked_for() {
+        let markdown = "one\n\n***\n\ntwo\n\n* * *\n\nthree\n\n---\n\nfour\n";
+
+        assert_eq!(count(markdown, false), (0, 3, 0));
+        assert_eq!(count(markdown, true), (2, 1, 0));
+    }
+
+    #[test]
+    fn code_is_left_alone() {
+        let markdown = "```\n\\pagebreak\n```\n\n    \\pagebreak\n";
+
+        assert_eq!(mark(markdown, true), markdown);
+    }
+}
@@ -0,0 +1,621 @@
+use std::collections::BTreeMap;
+use std::io::Cursor;
+use std::path::Path;
+
+use printpdf::lopdf::{self, Dictionary, Document, Object, ObjectId, StringFormat};
+use printpdf::{
+    Color, ColorBits, ColorSpace, ImageTransform, ImageXObject, IndirectFontRef, Line, Mm,
+    PdfDocument, PdfDocumentReference, PdfLayerReference, Point, Polygon, PolygonMode, Pt, Px,
+    TextMatrix, TextRenderingMode, WindingOrder,
+};
+
+use crate::error::{Error, Result};
+use crate::layout::{Destination, Element, Image, Layout, OutlineItem, Page, TextRun};
+use crate::shaping;
+use crate::subset::{self, UsedFace};
+use crate::util;
+
+// paints a layout into a pdf
+pub struct PdfWriter<'a> {
+    layout: &'a Layout,
+    document: PdfDocumentReference,
+    // embedded the first time they are drawn with, fonts tend to be big
+    fonts: Vec<Option<IndirectFontRef>>,
+    // the glyphs drawn with each face and the text they stand for, the embedded fonts
+    // are cut down to these when the document is saved
+    used_glyphs: Vec<BTreeMap<u16, String>>,
+}
+
+impl PdfWriter<'_> {
+    pub fn write(layout: &Layout) -> Result<Vec<u8>> {
+        let (width, height) = (Mm::from(layout.width), Mm::from(layout.height));
+        let (document, page, layer) = PdfDocument::new(&layout.title, width, height, "Layer 1");
+        let first_layer = document.get_page(page).get_layer(layer);
+
+        let mut writer = PdfWriter {
+            layout,
+            document,
+            fonts: vec![None; layout.faces.len()],
+            used_glyphs: vec![BTreeMap::new(); layout.faces.len()],
+        };
+
+        for (i, page) in layout.pages.iter().enumerate() {
+            let layer = match i {
+                0 => first_layer.clone(),
+                _ => {
+                    let (page, layer) = writer.document.add_page(width, height, "Layer 1");
+                    writer.document.get_page(page).get_layer(layer)
+                }
+            };
+
+            writer.write_page(&layer, page);
+        }
+
+        // printpdf embeds whole fonts, they are subset afterwards
+        let used_faces = used_faces(layout, &writer.fonts, &writer.used_glyphs);
+        let pdf = writer.document.save_to_bytes()?;
+        let pdf = subset::subset_fonts(&pdf, &used_faces)?;
+
+        add_navigation(pdf, layout)
+    }
+
+    // text goes into one text section, lines and links are added after it, filled
+    // areas and images before it so they stay below the text
+    fn write_page(&mut self, layer: &PdfLayerReference, page: &Page) {
+        self.paint_background(layer);
+
+        for element in &page.elements {
+            match element {
+                Element::Rect { from, to, color } => fill_rect(layer, *from, *to, color),
+                Element::Image { from, to, image } => self.paint_image(layer, *from, *to, image),
+                _ => {}
+            }
+        }
+
+        layer.begin_text_section();
+        layer.set_text_rendering_mode(TextRenderingMode::Fill);
+
+        // the face and size set on the layer, the fill color, and where the text cursor
+        // is when it was left on the baseline by an upright run
+        let mut font: Option<(usize, f32)> = None;
+        let mut color: Option<&Color> = None;
+        let mut pen: Option<(Pt, Pt)> = None;
+
+        for element in &page.elements {
+            let Element::Text(text) = element else {
+                continue;
+            };
+
+            let fonts = self.document_fonts(text);
+            self.record_glyphs(text);
+
+            if font != Some((text.faces[0], text.run.size)) {
+                layer.set_font(fonts[0].as_ref().unwrap(), text.run.size);
+                font = Some((text.faces[0], text.run.size));
+            }
+
+            if color != Some(&text.color) {
+                layer.set_fill_color(text.color.clone());
+                color = Some(&text.color);
+            }
+
+            match pen {
+                Some((x, y)) if text.slant == 0.0 && y == text.y => {
+                    shaping::move_cursor(layer, text.x - x, text.run.size)
+                }
+                _ => layer.set_text_matrix(TextMatrix::Raw([
+                    1.0, 0.0, text.slant, 1.0, text.x.0, text.y.0,
+                ])),
+            }
+
+            text.run.write(layer, &fonts);
+
+            pen = match text.slant {
+                0.0 => Some((text.x + text.run.width(), text.y)),
+                _ => None,
+            };
+        }
+
+        layer.end_text_section();
+
+        for element in &page.elements {
+            match element {
+                Element::Text(_) | Element::Rect { .. } | Element::Image { .. } => {}
+                Element::Line {
+                    from,
+                    to,
+                    width,
+                    color,
+                } => {
+                    layer.set_outline_color(color.clone());
+                    layer.set_outline_thickness(width.0);
+                    layer.add_line(Line {
+                        points: vec![
+                            (
+                                Point {
+                                    x: from.0,
+                                    y: from.1,
+                                },
+                                false,
+                            ),
+                            (Point { x: to.0, y: to.1 }, false),
+                        ],
+                        is_closed: false,
+                    });
+                }
+                // printpdf only links to urls, see add_navigation
+                Element::Link { .. } | Element::PageLink { .. } => {}
+            }
+        }
+    }
+
+    fn paint_background(&self, layer: &PdfLayerReference) {
+        let Some(color) = &self.layout.background else {
+            return;
+        };
+
+        let (width, height) = (self.layout.width, self.layout.height);
+        fill_rect(layer, (Pt(0.0), Pt(0.0)), (width, height), color);
+    }
+
+    // pdf images have no alpha without a soft mask, so transparent pixels are blended
+    // with the page background up front
+    fn paint_image(&self, layer: &PdfLayerReference, from: (Pt, Pt), to: (Pt, Pt), image: &Image) {
+        let background = match &self.layout.background {
+            Some(Color::Rgb(rgb)) => [rgb.r, rgb.g, rgb.b],
+            _ => [1.0, 1.0, 1.0],
+        };
+        let image_data = image
+            .pixels
+            .chunks_exact(4)
+            .flat_map(|pixel| {
+                let alpha = pixel[3] as f32 / 255.0;
+                let blend = |i: usize| {
+                    let value = pixel[i] as f32 * alpha + background[i] * 255.0 * (1.0 - alpha);
+                    value.round() as u8
+                };
+                [blend(0), blend(1), blend(2)]
+            })
+            .collect();
+
+        let xobject = ImageXObject {
+            width: Px(image.width as usize),
+            height: Px(image.height as usize),
+            color_space: ColorSpace::Rgb,
+            bits_per_component: ColorBits::Bit8,
+            interpolate: true,
+            image_data,
+            image_filter: None,
+            clipping_bbox: None,
+        };
+
+        // at 72 dpi a pixel is a point
+        printpdf::Image::from(xobject).add_to_layer(
+            layer.clone(),
+            ImageTransform {
+                translate_x: Some(from.0.into()),
+                translate_y: Some(from.1.into()),
+                scale_x: Some((to.0 - from.0).0 / image.width as f32),
+                scale_y: Some((to.1 - from.1).0 / image.height as f32),
+                dpi: Some(72.0),
+                ..Default::default()
+            },
+        );
+    }
+
+    // the document fonts to draw `text` with, see ShapedRun::write
+    fn document_fonts(&mut self, text: &TextRun) -> Vec<Option<IndirectFontRef>> {
+        let used = text.run.fonts();
+
+        text.faces
+            .iter()
+            .enumerate()
+            .map(|(i, face)| (i == 0 || used.contains(&i)).then(|| self.embed(*face)))
+            .collect()
+    }
+
+    // remembers which glyphs `text` draws, the first text seen for a glyph wins
+    fn record_glyphs(&mut self, text: &TextRun) {
+        for (glyph, source) in text.run.glyph_texts() {
+            let source = match glyph.id {
+                // .notdef stands for nothing
+                0 => "",
+                _ => source,
+            };
+
+            let used = self.used_glyphs[text.faces[glyph.font]]
+                .entry(glyph.id)
+                .or_default();
+
+            if used.is_empty() {
+                *used = source.to_string();
+            }
+        }
+    }
+
+    fn embed(&mut self, index: usize) -> IndirectFontRef {
+        self.fonts[index]
+            .get_or_insert_with(|| {
+                self.document
+                    .add_external_font(Cursor::new(&self.layout.faces[index]))
+                    .expect("fonts are validated on load")
+            })
+            .clone()
+    }
+}
+
+fn fill_rect(layer: &PdfLayerReference, from: (Pt, Pt), to: (Pt, Pt), color: &Color) {
+    let corners = [from, (to.0, from.1), to, (from.0, to.1)];
+
+    layer.set_fill_color(color.clone());
+    layer.add_polygon(Polygon {
+        rings: vec![corners
+            .iter()
+            .map(|(x, y)| (Point { x: *x, y: *y }, false))
+            .collect()],
+        mode: PolygonMode::Fill,
+        winding_order: WindingOrder::NonZero,
+    });
+}
+
+// the faces that were embedded, with the glyphs drawn with them
+fn used_faces<'a>(
+    layout: &'a Layout,
+    fonts: &[Option<IndirectFontRef>],
+    used_glyphs: &'a [BTreeMap<u16, String>],
+) -> Vec<UsedFace<'a>> {
+    (0..layout.faces.len())
+        .filter(|index| fonts[*index].is_some())
+        .map(|index| UsedFace {
+            data: &layout.faces[index],
+            glyphs: &used_glyphs[index],
+        })
+        .collect()
+}
+
+// adds the links, the named destinations of the headings and the outline to the saved pdf,
+// printpdf can't make them
+fn add_navigation(pdf: Vec<u8>, layout: &Layout) -> Result<Vec<u8>> {
+    let links = layout
+        .pages
+        .iter()
+        .flat_map(|page| &page.elements)
+        .any(|element| matches!(element, Element::Link { .. } | Element::PageLink { .. }));
+
+    if !links && layout.outline.is_empty() && layout.destinations.is_empty() {
+        return Ok(pdf);
+    }
+
+    let mut document = Document::load_mem(&pdf).map_err(navigation_error)?;
+    let pages: Vec<ObjectId> = document.get_pages().into_values().collect();
+
+    for (page, id) in layout.pages.iter().zip(&pages) {
+        add_links(&mut document, page, *id, &pages).map_err(navigation_error)?;
+    }
+
+    if !layout.destinations.is_empty() {
+        add_destinations(&mut document, &layout.destinations, &pages).map_err(navigation_error)?;
+    }
+
+    if !layout.outline.is_empty() {
+        add_outline(&mut document, &layout.outline, &pages).map_err(navigation_error)?;
+    }
+
+    let mut bytes = vec![];
+    document.save_to(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+fn add_links(
+    document: &mut Document,
+    page: &Page,
+    id: ObjectId,
+    pages: &[ObjectId],
+) -> lopdf::Result<()> {
+    let mut annotations = vec![];
+
+    for element in &page.elements {
+        let (from, to) = match element {
+            Element::Link { from, to, .. } | Element::PageLink { from, to, .. } => (from, to),
+            _ => continue,
+        };
+
+        let mut annotation = Dictionary::new();
+        annotation.set("Type", "Annot");
+        annotation.set("Subtype", "Link");
+        annotation.set(
+            "Rect",
+            vec![
+                from.0 .0.into(),
+                from.1 .0.into(),
+                to.0 .0.into(),
+                to.1 .0.into(),
+            ],
+        );
+        annotation.set("Border", vec![0.into(), 0.into(), 0.into()]);
+
+        match element {
+            Element::PageLink { page, y, .. } => {
+                annotation.set("Dest", destination(pages[*page], *y))
+            }
+            Element::Link { url, .. } => annotation.set("A", link_action(url)),
+            _ => unreachable!(),
+        }
+
+        annotations.push(Object::Reference(document.add_object(annotation)));
+    }
+
+    if annotations.is_empty() {
+        return Ok(());
+    }
+
+    let dict = document.get_object_mut(id)?.as_dict_mut()?;
+
+    match dict.get_mut(b"Annots") {
+        Ok(Object::Array(known)) => known.extend(annotations),
+        _ => dict.set("Annots", annotations),
+    }
+
+    Ok(())
+}
+
+// absolute urls open in the browser, fragments jump to the heading with that anchor and
+// other links open the file next to the pdf, the pdf made from it for markdown files
+fn link_action(url: &str) -> Dictionary {
+    let mut action = Dictionary::new();
+    action.set("Type", "Action");
+
+    if util::is_absolute_url(url) {
+        action.set("S", "URI");
+        action.set("URI", Object::string_literal(url));
+        return action;
+    }
+
+    let (file, anchor) = url.split_once('#').unwrap_or((url, ""));
+    let anchor = Object::String(anchor.as_bytes().to_vec(), StringFormat::Literal);
+    let path = Path::new(file);
+
+    match path.extension().and_then(|extension| extension.to_str()) {
+        _ if file.is_empty() => {
+            action.set("S", "GoTo");
+            action.set("D", anchor);
+        }
+        Some("md" | "markdown" | "pdf") => {
+            let file = path.with_extension("pdf");
+
+            action.set("S", "GoToR");
+            action.set("F", Object::string_literal(file.to_string_lossy().as_ref()));
+
+            // the first page of the other document without a heading to go to
+            match url.contains('#') {
+                true => action.set("D", anchor),
+                false => action.set("D", vec![0.into(), "Fit".into()]),
+            }
+        }
+        _ => {
+            action.set("S", "Launch");
+            action.set("F", Object::string_literal(file));
+        }
+    }
+
+    action
+}
+
+// the headings by their anchor, so fragment links of this and other documents can go
+// to them
+fn add_destinations(
+    document: &mut Document,
+    destinations: &[Destination],
+    pages: &[ObjectId],
+) -> lopdf::Result<()> {
+    let mut destinations: Vec<&Destination> = destinations.iter().collect();
+    // name trees are sorted by the bytes of their names
+    destinations.sort_by(|a, b| a.name.as_bytes().cmp(b.name.as_bytes()));
+
+    let names = destinations
+        .iter()
+        .flat_map(|heading| {
+            [
+                Object::String(heading.name.as_bytes().to_vec(), StringFormat::Literal),
+                destination(pages[heading.page], heading.y).into(),
+            ]
+        })
+        .collect::<Vec<Object>>();
+
+    let mut dests = Dictionary::new();
+    dests.set("Names", names);
+
+    let mut name_dictionary = Dictionary::new();
+    name_dictionary.set("Dests", document.add_object(dests));
+
+    let catalog = document.trailer.get(b"Root")?.as_reference()?;
+    let catalog = document.get_object_mut(catalog)?.as_dict_mut()?;
+    catalog.set("Names", name_dictionary);
+
+    Ok(())
+}
+
+// the outline is nested by level, every item is closed so only the chapters show
+fn add_outline(
+    document: &mut Document,
+    items: &[OutlineItem],
+    pages: &[ObjectId],
+) -> lopdf::Result<()> {
+    let root = document.new_object_id();
+    let ids: Vec<ObjectId> = items.iter().map(|_| document.new_object_id()).collect();
+
+    // the parent of an item is the closest one before it with a lower level
+    let mut parents: Vec<Option<usize>> = vec![];
+    let mut open: Vec<usize> = vec![];
+
+    for item in items {
+        while open
+            .last()
+            .is_some_and(|last| items[*last].level >= item.level)
+        {
+            open.pop();
+        }
+
+        parents.push(open.last().copied());
+        open.push(parents.len() - 1);
+    }
+
+    let children = |parent: Option<usize>| -> Vec<usize> {
+        (0..items.len()).filter(|i| parents[*i] == parent).collect()
+    };
+
+    for (i, item) in items.iter().enumerate() {
+        let siblings = children(parents[i]);
+        let position = siblings.iter().position(|sibling| *sibling == i).unwrap();
+        let kids = children(Some(i));
+
+        let mut dict = Dictionary::new();
+        dict.set("Title", text_string(&item.title));
+        dict.set("Parent", parents[i].map_or(root, |parent| ids[parent]));
+        dict.set("Dest", destination(pages[item.page], item.y));
+
+        if position > 0 {
+            dict.set("Prev", ids[siblings[position - 1]]);
+        }
+
+        if let Some(next) = siblings.get(position + 1) {
+            dict.set("Next", ids[*next]);
+        }
+
+        if let (Some(first), Some(last)) = (kids.first(), kids.last()) {
+            dict.set("First", ids[*first]);
+            dict.set("Last", ids[*last]);
+            dict.set("Count", -(kids.len() as i64));
+        }
+
+        document.objects.insert(ids[i], Object::Dictionary(dict));
+    }
+
+    let top = children(None);
+    let mut outlines = Dictionary::new();
+    outlines.set("Type", "Outlines");
+    outlines.set("First", ids[top[0]]);
+    outlines.set("Last", ids[*top.last().unwrap()]);
+    outlines.set("Count", top.len() as i64);
+    document.objects.insert(root, Object::Dictionary(outlines));
+
+    let catalog = document.trailer.get(b"Root")?.as_reference()?;
+    let catalog = document.get_object_mut(catalog)?.as_dict_mut()?;
+    catalog.set("Outlines", root);
+    catalog.set("PageMode", "UseOutlines");
+
+    Ok(())
+}
+
+// the top of the view at `y` on the page, keeping the zoom
+fn destination(page: ObjectId, y: Pt) -> Vec<Object> {
+    vec![
+        page.into(),
+        "XYZ".into(),
+        Object::Null,
+        y.0.into(),
+        Object::Null,
+    ]
+}
+
+// ascii text is kept as is, anything else goes as utf-16 with a byte order mark
+fn text_string(text: &str) -> Object {
+    if text.is_ascii() {
+        return Object::String(text.as_bytes().to_vec(), StringFormat::Literal);
+    }
+
+    let bytes = [0xfe, 0xff]
+        .into_iter()
+        .chain(text.encode_utf16().flat_map(u16::to_be_bytes))
+        .collect();
+
+    Object::String(bytes, StringFormat::Hexadecimal)
+}
+
+fn navigation_error(err: lopdf::Error) -> Error {
+    Error::Config(format!("could not add links and outline: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout;
+    use crate::testing;
+
+    fn write(markdown: &str) -> Document {
+        let layout = layout::layout_document(markdown, Path::new("doc.md"), &testing::config());
+        Document::load_mem(&PdfWriter::write(&layout.unwrap()).unwrap()).unwrap()
+    }
+
+    // the actions of the links on the first page, in the order they were drawn
+    fn actions(document: &Document) -> Vec<Dictionary> {
+        let page = *document.get_pages().values().next().unwrap();
+        let annotations = document
+            .get_dictionary(page)
+            .unwrap()
+            .get(b"Annots")
+            .unwrap()
+            .as_array()
+            .unwrap();
+
+        annotations
+            .iter()
+            .map(|annotation| {
+                let annotation = document
+                    .get_dictionary(annotation.as_reference().unwrap())
+                    .unwrap();
+                annotation.get(b"A").unwrap().as_dict().unwrap().clone()
+            })
+            .collect()
+    }
+
+    fn string(dict: &Dictionary, key: &[u8]) -> String {
+        String::from_utf8(dict.get(key).unwrap().as_str().unwrap().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn only_absolute_urls_are_opened_in_the_browser() {
+        let document = write(concat!(
+            "# Overview\n\n",
+            "[a](#overview) [b](other.md#setup) [c](other.md) [d](data.csv) ",
+            "[e](https://example.com)\n",
+        ));
+        let actions = actions(&document);
+        let kinds: Vec<_> = actions
+            .iter()
+            .map(|action| action.get(b"S").unwrap().as_name_str().unwrap())
+            .collect();
+
+        assert_eq!(kinds, ["GoTo", "GoToR", "GoToR", "Launch", "URI"]);
+        assert_eq!(string(&actions[0], b"D"), "overview");
+        assert_eq!(string(&actions[1], b"F"), "other.pdf");
+        assert_eq!(string(&actions[1], b"D"), "setup");
+        assert!(actions[2].get(b"D").unwrap().as_array().is_ok());
+        assert_eq!(string(&actions[3], b"F"), "data.csv");
+        assert_eq!(string(&actions[4], b"URI"), "https://example.com");
+    }
+
+    #[test]
+    fn headings_are_named_destinations() {
+        let document = write("# Overview\n\ntext\n\n## Setup\n\n## Setup\n");
+        let catalog = document.catalog().unwrap();
+        let names = catalog.get(b"Names").unwrap().as_dict().unwrap();
+        let dests = names.get(b"Dests").unwrap().as_reference().unwrap();
+        let dests = document
+            .get_dictionary(dests)
+            .unwrap()
+            .get(b"Names")
+            .unwrap()
+            .as_array()
+            .unwrap();
+
+        let names: Vec<_> = dests
+            .iter()
+            .step_by(2)
+            .map(|name| String::from_utf8(name.as_str().unwrap().to_vec()).unwrap())
+            .collect();
+
+        assert_eq!(names, ["overview", "setup", "setup-1"]);
+    }
+}
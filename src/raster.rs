@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use printpdf::Color;
+use rustybuzz::ttf_parser::{self, GlyphId, OutlineBuilder};
+use tiny_skia::{FillRule, Paint, PathBuilder, Pixmap, Stroke, Transform};
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::layout::{self, Element, Layout, Page, TextRun};
+use crate::render::render_each;
+use crate::theme::rgb_channels;
+
+// width in pixels of the first page preview written with --thumbnail
+pub const THUMBNAIL_WIDTH: u32 = 256;
+
+// how pages are turned into images
+#[derive(Clone, Copy, Debug)]
+pub enum Raster {
+    // every page at this many pixels per inch
+    Pages { dpi: f32 },
+    // the first page scaled to THUMBNAIL_WIDTH
+    Thumbnail,
+}
+
+// rasterizes a layout on the cpu, glyph outlines come straight from the fonts
+pub struct PngWriter<'a> {
+    layout: &'a Layout,
+    faces: Vec<ttf_parser::Face<'a>>,
+    // outlines in font units, none for glyphs without one like spaces
+    outlines: HashMap<(usize, u16), Option<tiny_skia::Path>>,
+}
+
+impl<'a> PngWriter<'a> {
+    // one png per page
+    pub(crate) fn write(layout: &'a Layout, dpi: f32) -> Result<Vec<Vec<u8>>> {
+        let mut writer = PngWriter::new(layout);
+
+        layout
+            .pages
+            .iter()
+            .map(|page| writer.write_page(page, dpi))
+            .collect()
+    }
+
+    pub(crate) fn write_thumbnail(layout: &'a Layout) -> Result<Vec<u8>> {
+        let dpi = THUMBNAIL_WIDTH as f32 * 72.0 / layout.width.0;
+
+        PngWriter::new(layout).write_page(&layout.pages[0], dpi)
+    }
+
+    // `doc.md` becomes `doc-1.png`, `doc-2.png` and so on, or `doc-thumbnail.png`
+    pub fn render_files(
+        files: &HashMap<PathBuf, String>,
+        config: &Config,
+        raster: Raster,
+    ) -> Result<HashMap<PathBuf, Vec<u8>>> {
+        render_each(files, |path, content| {
+            let stem = path.file_stem().unwrap().to_string_lossy();
+            let layout = layout::layout_document(content, path, config)?;
+
+            let images = match raster {
+                Raster::Pages { dpi } => PngWriter::write(&layout, dpi)?
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, image)| (format!("{}-{}.png", stem, i + 1), image))
+                    .collect(),
+                Raster::Thumbnail => vec![(
+                    format!("{}-thumbnail.png", stem),
+                    PngWriter::write_thumbnail(&layout)?,
+                )],
+            };
+
+            Ok(images
+                .into_iter()
+                .map(|(name, image)| (path.with_file_name(name), image))
+                .collect())
+        })
+    }
+
+    fn new(layout: &'a Layout) -> Self {
+        PngWriter {
+            layout,
+            faces: layout
+                .faces
+                .iter()
+                .map(|face| ttf_parser::Face::parse(face, 0).expect("fonts are validated on load"))
+                .collect(),
+            outlines: HashMap::new(),
+        }
+    }
+
+    // pages without a background are painted white, like a pdf viewer shows them
+    fn write_page(&mut self, page: &Page, dpi: f32) -> Result<Vec<u8>> {
+        let scale = dpi / 72.0;
+        let width = (self.layout.width.0 * scale).round() as u32;
+        let height = (self.layout.height.0 * scale).round() as u32;
+
+        let mut pixmap = Pixmap::new(width, height)
+            .ok_or_else(|| Error::Image(format!("{}x{} pixels is too large", width, height)))?;
+
+        let background = self
+            .layout
+            .background
+            .as_ref()
+            .map_or(tiny_skia::Color::WHITE, skia_color);
+        pixmap.fill(background);
+
+        // filled areas and images go below everything else
+        for element in &page.elements {
+            match element {
+                Element::Rect { from, to, color } => {
+                    let rect = tiny_skia::Rect::from_ltrb(
+                        from.0 .0,
+                        self.layout.height.0 - to.1 .0,
+                        to.0 .0,
+                        self.layout.height.0 - from.1 .0,
+                    );
+
+                    if let Some(rect) = rect {
+                        pixmap.fill_rect(
+                            rect,
+                            &paint(color),
+                            Transform::from_scale(scale, scale),
+                            None,
+                        );
+                    }
+                }
+                Element::Image { from, to, image } => {
+                    let Ok(picture) = Pixmap::decode_png(&image.data) else {
+                        continue;
+                    };
+                    let transform = Transform::from_row(
+                        (to.0 - from.0).0 / image.width as f32,
+                        0.0,
+                        0.0,
+                        (to.1 - from.1).0 / image.height as f32,
+                        from.0 .0,
+                        self.layout.height.0 - to.1 .0,
+                    )
+                    .post_scale(scale, scale);
+
+                    pixmap.draw_pixmap(
+                        0,
+                        0,
+                        picture.as_ref(),
+                        &tiny_skia::PixmapPaint {
+                            quality: tiny_skia::FilterQuality::Bilinear,
+                            ..Default::default()
+                        },
+                        transform,
+                        None,
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        for element in &page.elements {
+            match element {
+                Element::Text(text) => self.draw_text(&mut pixmap, text, scale),
+                Element::Line {
+                    from,
+                    to,
+                    width,
+                    color,
+                } => {
+                    let mut path = PathBuilder::new();
+                    path.move_to(from.0 .0, self.layout.height.0 - from.1 .0);
+                    path.line_to(to.0 .0, self.layout.height.0 - to.1 .0);
+
+                    let stroke = Stroke {
+                        width: width.0,
+                        ..Stroke::default()
+                    };
+
+                    pixmap.stroke_path(
+                        &path.finish().unwrap(),
+                        &paint(color),
+                        &stroke,
+                        Transform::from_scale(scale, scale),
+                        None,
+                    );
+                }
+                Element::Rect { .. }
+                | Element::Image { .. }
+                | Element::Link { .. }
+                | Element::PageLink { .. } => {}
+            }
+        }
+
+        pixmap
+            .encode_png()
+            .map_err(|err| Error::Image(err.to_string()))
+    }
+
+    fn draw_text(&mut self, pixmap: &mut Pixmap, text: &TextRun, scale: f32) {
+        let paint = paint(&text.color);
+        let baseline = (self.layout.height.0 - text.y.0) * scale;
+        let mut pen = 0;
+
+        for glyph in &text.run.glyphs {
+            let face = text.faces[glyph.font];
+            let x = text.x.0 + (pen + glyph.x_offset) as f32 * text.run.size / 1000.0;
+            pen += glyph.advance;
+
+            // font units to pixels, flipped so y goes down, skewed for synthesized italics
+            let em = text.run.size / self.faces[face].units_per_em() as f32 * scale;
+            let transform = Transform::from_row(em, 0.0, text.slant * em, -em, x * scale, baseline);
+
+            if let Some(outline) = self.outline(face, glyph.id) {
+                pixmap.fill_path(outline, &paint, FillRule::Winding, transform, None);
+            }
+        }
+    }
+
+    fn outline(&mut self, face: usize, id: u16) -> Option<&tiny_skia::Path> {
+        let faces = &self.faces;
+
+        self.outlines
+            .entry((face, id))
+            .or_insert_with(|| {
+                let mut path = Path(PathBuilder::new());
+                faces[face].outline_glyph(GlyphId(id), &mut path)?;
+                path.0.finish()
+            })
+            .as_ref()
+    }
+}
+
+struct Path(PathBuilder);
+
+impl OutlineBuilder for Path {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.0.move_to(x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.0.line_to(x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.0.quad_to(x1, y1, x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.0.cubic_to(x1, y1, x2, y2, x, y);
+    }
+
+    fn close(&mut self) {
+        self.0.close();
+    }
+}
+
+fn skia_color(color: &Color) -> tiny_skia::Color {
+    let (r, g, b) = rgb_channels(color);
+
+    tiny_skia::Color::from_rgba(r.clamp(0.0, 1.0), g.clamp(0.0, 1.0), b.clamp(0.0, 1.0), 1.0)
+        .unwrap()
+}
+
+fn paint(color: &Color) -> Paint<'static> {
+    let mut paint = Paint::default();
+    paint.set_color(skia_color(color));
+    paint
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    const DOCUMENT: &str =
+        "# Blocks\n\n> quoted\n\n```\nlet code = 1;\n```\n\n| a | b |\n|---|---|\n| 1 | 2 |\n";
+
+    #[test]
+    fn writes_tables_code_and_quotes() {
+        let config = testing::config();
+        let files = HashMap::from([(PathBuf::from("doc.md"), DOCUMENT.to_string())]);
+        let rendered =
+            PngWriter::render_files(&files, &config, Raster::Pages { dpi: 36.0 }).unwrap();
+        let png = Pixmap::decode_png(&rendered[&PathBuf::from("doc-1.png")]).unwrap();
+
+        assert_eq!(rendered.len(), 1);
+        // text was drawn on the white page
+        assert!(png.pixels().iter().any(|pixel| pixel.red() < 128));
+    }
+}
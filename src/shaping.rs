@@ -0,0 +1,467 @@
+use printpdf::lopdf::content::Operation;
+use printpdf::lopdf::{Dictionary, Object, StringFormat};
+use printpdf::{IndirectFontRef, PdfLayerReference, Pt};
+use rustybuzz::{Direction, Face, UnicodeBuffer};
+use unicode_segmentation::UnicodeSegmentation;
+
+// glyph positions are kept in thousandths of an em, the unit pdf uses for glyph widths,
+// so what gets measured is exactly what gets drawn
+#[derive(Clone, Debug)]
+pub struct ShapedGlyph {
+    pub id: u16,
+    pub advance: i32,
+    pub x_offset: i32,
+    // the width the pdf viewer will advance by, taken from the embedded font
+    pub pdf_width: i32,
+    // byte offset of the cluster this glyph belongs to
+    pub cluster: usize,
+    // index of the face in the list the run was shaped with, 0 is the primary face
+    pub font: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct ShapedRun {
+    pub text: String,
+    pub glyphs: Vec<ShapedGlyph>,
+    pub size: f32,
+}
+
+impl ShapedRun {
+    pub fn width(&self) -> Pt {
+        let advance: i32 = self.glyphs.iter().map(|glyph| glyph.advance).sum();
+        Pt(advance as f32 * self.size / 1000.0)
+    }
+
+    // indices of the faces used by the run
+    pub fn fonts(&self) -> Vec<usize> {
+        let mut fonts: Vec<usize> = self.glyphs.iter().map(|glyph| glyph.font).collect();
+        fonts.sort_unstable();
+        fonts.dedup();
+        fonts
+    }
+
+    // characters none of the faces had a glyph for, they are drawn as .notdef
+    pub fn missing(&self) -> Vec<char> {
+        let clusters = self.cluster_ranges();
+
+        self.glyphs
+            .iter()
+            .filter(|glyph| glyph.id == 0)
+            .filter_map(|glyph| clusters.iter().find(|(start, _)| *start == glyph.cluster))
+            .flat_map(|(start, end)| self.text[*start..*end].chars())
+            .collect()
+    }
+
+    // every glyph with the text it stands for, the first glyph of a cluster gets the
+    // whole cluster, the others an empty string
+    pub fn glyph_texts(&self) -> Vec<(&ShapedGlyph, &str)> {
+        let clusters = self.cluster_ranges();
+        let mut previous = None;
+
+        self.glyphs
+            .iter()
+            .map(|glyph| {
+                if previous == Some(glyph.cluster) {
+                    return (glyph, "");
+                }
+
+                previous = Some(glyph.cluster);
+
+                let text = clusters
+                    .iter()
+                    .find(|(start, _)| *start == glyph.cluster)
+                    .map_or("", |(start, end)| &self.text[*start..*end]);
+
+                (glyph, text)
+            })
+            .collect()
+    }
+
+    // draws the run at the current text cursor and leaves the cursor at the end of it,
+    // ligatures and other many-to-one clusters are tagged with their source text so
+    // copy and paste keeps working
+    //
+    // `fonts` are the document fonts for the faces the run was shaped with, the first
+    // one has to be set on the layer already, unused ones can be left out
+    pub fn write(&self, layer: &PdfLayerReference, fonts: &[Option<IndirectFontRef>]) {
+        let clusters = self.cluster_ranges();
+        let mut array = vec![];
+        // where the viewer thinks the pen is vs. where the shaper put it
+        let mut pdf_pen = 0;
+        let mut pen = 0;
+        let mut current_font = 0;
+
+        let mut i = 0;
+        while i < self.glyphs.len() {
+            let cluster = self.glyphs[i].cluster;
+            let end = self.glyphs[i..]
+                .iter()
+                .position(|glyph| glyph.cluster != cluster)
+                .map_or(self.glyphs.len(), |len| i + len);
+            let (start, stop) = clusters
+                .iter()
+                .copied()
+                .find(|(start, _)| *start == cluster)
+                .unwrap_or((cluster, cluster));
+            let source = &self.text[start..stop];
+            let tagged = source.chars().count() != end - i;
+
+            if tagged {
+                flush(layer, &mut array);
+                layer.add_operation(actual_text(source));
+            }
+
+            for glyph in &self.glyphs[i..end] {
+                if glyph.font != current_font {
+                    flush(layer, &mut array);
+                    set_font(layer, fonts, glyph.font, self.size);
+                    current_font = glyph.font;
+                }
+
+                let position = pen + glyph.x_offset;
+
+                if pdf_pen != position {
+                    array.push(Object::Integer((pdf_pen - position) as i64));
+                }
+
+                array.push(Object::String(
+                    glyph.id.to_be_bytes().to_vec(),
+                    StringFormat::Hexadecimal,
+                ));
+
+                pdf_pen = position + glyph.pdf_width;
+                pen += glyph.advance;
+            }
+
+            if tagged {
+                flush(layer, &mut array);
+                layer.add_operation(Operation::new("EMC", vec![]));
+            }
+
+            i = end;
+        }
+
+        if pdf_pen != pen {
+            array.push(Object::Integer((pdf_pen - pen) as i64));
+        }
+
+        flush(layer, &mut array);
+
+        if current_font != 0 {
+            set_font(layer, fonts, 0, self.size);
+        }
+    }
+
+    // splits the run in front of the byte `offset` of its text, fails when that is
+    // inside a cluster, e.g. in the middle of a ligature, or the run is right to left
+    pub fn split(&self, offset: usize) -> Option<(ShapedRun, ShapedRun)> {
+        let index = self
+            .glyphs
+            .iter()
+            .position(|glyph| glyph.cluster >= offset)?;
+
+        if index == 0
+            || self.glyphs[index].cluster != offset
+            || self.glyphs[..index]
+                .iter()
+                .any(|glyph| glyph.cluster > offset)
+        {
+            return None;
+        }
+
+        let head = ShapedRun {
+            text: self.text[..offset].to_string(),
+            glyphs: self.glyphs[..index].to_vec(),
+            size: self.size,
+        };
+        let tail = ShapedRun {
+            text: self.text[offset..].to_string(),
+            glyphs: self.glyphs[index..]
+                .iter()
+                .map(|glyph| ShapedGlyph {
+                    cluster: glyph.cluster - offset,
+                    ..glyph.clone()
+                })
+                .collect(),
+            size: self.size,
+        };
+
+        Some((head, tail))
+    }
+
+    // (start, end) byte ranges of every cluster in the source text
+    fn cluster_ranges(&self) -> Vec<(usize, usize)> {
+        let mut starts: Vec<usize> = self.glyphs.iter().map(|glyph| glyph.cluster).collect();
+        starts.sort_unstable();
+        starts.dedup();
+
+        starts
+            .iter()
+            .enumerate()
+            .map(|(i, start)| {
+                (
+                    *start,
+                    starts.get(i + 1).copied().unwrap_or(self.text.len()),
+                )
+            })
+            .collect()
+    }
+}
+
+// shapes `text` with the first of `faces`, parts it has no glyphs for go to the first
+// face that does, every face has to be a font rustybuzz can parse, callers check this
+// once when loading fonts
+//
+// `rtl` runs come out in visual order, right to left, like rustybuzz returns them
+pub fn shape(faces: &[&[u8]], text: &str, size: f32, rtl: bool) -> ShapedRun {
+    let primary = parse(faces[0]);
+
+    if text
+        .chars()
+        .all(|c| c.is_control() || primary.glyph_index(c).is_some())
+    {
+        return ShapedRun {
+            text: text.to_string(),
+            glyphs: shape_segment(&primary, 0, text, 0, rtl),
+            size,
+        };
+    }
+
+    let faces: Vec<Face> = faces.iter().map(|face| parse(face)).collect();
+    let mut segments: Vec<(usize, usize)> = vec![];
+
+    // whole graphemes go to one face, so marks and emoji sequences stay together
+    for (start, grapheme) in text.grapheme_indices(true) {
+        let first = grapheme.chars().next().unwrap();
+        let font = faces
+            .iter()
+            .position(|face| face.glyph_index(first).is_some());
+        let previous = segments.last().map(|(font, _)| *font);
+
+        let font = match (font, previous) {
+            // whitespace stays with the face before it if that face has it
+            (Some(_), Some(previous))
+                if first.is_whitespace() && faces[previous].glyph_index(first).is_some() =>
+            {
+                previous
+            }
+            (Some(font), _) => font,
+            // characters no face has stay with the face before them
+            (None, previous) => previous.unwrap_or(0),
+        };
+
+        if segments.last().map(|(last, _)| *last) != Some(font) {
+            segments.push((font, start));
+        }
+    }
+
+    let mut shaped: Vec<Vec<ShapedGlyph>> = segments
+        .iter()
+        .enumerate()
+        .map(|(i, (font, start))| {
+            let end = segments.get(i + 1).map_or(text.len(), |(_, end)| *end);
+            shape_segment(&faces[*font], *font, &text[*start..end], *start, rtl)
+        })
+        .collect();
+
+    if rtl {
+        shaped.reverse();
+    }
+
+    let glyphs = shaped.into_iter().flatten().collect();
+
+    ShapedRun {
+        text: text.to_string(),
+        glyphs,
+        size,
+    }
+}
+
+fn parse(face: &[u8]) -> Face<'_> {
+    Face::from_slice(face, 0).expect("font was validated on load")
+}
+
+fn shape_segment(
+    face: &Face,
+    font: usize,
+    text: &str,
+    offset: usize,
+    rtl: bool,
+) -> Vec<ShapedGlyph> {
+    let scale = 1000.0 / face.units_per_em() as f32;
+
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.guess_segment_properties();
+    // the direction comes from the bidi level of the text, not its script
+    buffer.set_direction(match rtl {
+        true => Direction::RightToLeft,
+        false => Direction::LeftToRight,
+    });
+
+    let output = rustybuzz::shape(face, &[], buffer);
+
+    output
+        .glyph_infos()
+        .iter()
+        .zip(output.glyph_positions())
+        .map(|(info, position)| {
+            let id = info.glyph_id as u16;
+            let hor_advance = face
+                .glyph_hor_advance(rustybuzz::ttf_parser::GlyphId(id))
+                .unwrap_or(0);
+
+            ShapedGlyph {
+                id,
+                advance: (position.x_advance as f32 * scale).round() as i32,
+                x_offset: (position.x_offset as f32 * scale).round() as i32,
+                // the widths array truncates, see subset::widths
+                pdf_width: (hor_advance as f32 * scale) as i32,
+                cluster: offset + info.cluster as usize,
+                font,
+            }
+        })
+        .collect()
+}
+
+pub fn is_valid_face(face: &[u8]) -> bool {
+    Face::from_slice(face, 0).is_some()
+}
+
+// moves the text cursor along the line without drawing anything,
+// `size` has to be the size of the font currently set on the layer
+pub fn move_cursor(layer: &PdfLayerReference, distance: Pt, size: f32) {
+    if distance.0 != 0.0 {
+        let amount = Object::Real(-distance.0 * 1000.0 / size);
+        layer.add_operation(Operation::new("TJ", vec![Object::Array(vec![amount])]));
+    }
+}
+
+fn set_font(layer: &PdfLayerReference, fonts: &[Option<IndirectFontRef>], font: usize, size: f32) {
+    let font = fonts[font]
+        .as_ref()
+        .expect("fonts used by a run are embedded before it is drawn");
+
+    layer.set_font(font, size);
+}
+
+fn flush(layer: &PdfLayerReference, array: &mut Vec<Object>) {
+    if !array.is_empty() {
+        let array = std::mem::take(array);
+        layer.add_operation(Operation::new("TJ", vec![Object::Array(array)]));
+    }
+}
+
+// /Span <</ActualText (...)>> BDC, the text is utf-16 with a byte order mark
+fn actual_text(text: &str) -> Operation {
+    let mut utf16 = vec![0xfe, 0xff];
+    utf16.extend(text.encode_utf16().flat_map(|unit| unit.to_be_bytes()));
+
+    let mut properties = Dictionary::new();
+    properties.set(
+        "ActualText",
+        Object::String(utf16, StringFormat::Hexadecimal),
+    );
+
+    Operation::new(
+        "BDC",
+        vec![
+            Object::Name(b"Span".to_vec()),
+            Object::Dictionary(properties),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use printpdf::lopdf::content::Content;
+    use printpdf::lopdf::Document;
+    use printpdf::{Mm, PdfDocument};
+    use std::io::Cursor;
+
+    const ROBOTO: &[u8] = include_bytes!("../assets/fonts/Roboto-Regular.ttf");
+
+    // how far the text cursor moves while drawing `run`, in thousandths of an em
+    fn drawn_advance(run: &ShapedRun) -> i64 {
+        let (document, page, layer) = PdfDocument::new("test", Mm(100.0), Mm(100.0), "Layer 1");
+        let font = document.add_external_font(Cursor::new(ROBOTO)).unwrap();
+        let layer = document.get_page(page).get_layer(layer);
+
+        layer.begin_text_section();
+        layer.set_font(&font, run.size);
+        run.write(&layer, &[Some(font)]);
+        layer.end_text_section();
+
+        let pdf = Document::load_mem(&document.save_to_bytes().unwrap()).unwrap();
+        let page = *pdf.get_pages().values().next().unwrap();
+        let content = Content::decode(&pdf.get_page_content(page).unwrap()).unwrap();
+        let width = |id: u16| {
+            let glyph = run.glyphs.iter().find(|glyph| glyph.id == id).unwrap();
+            glyph.pdf_width as i64
+        };
+
+        content
+            .operations
+            .iter()
+            .filter(|operation| operation.operator == "TJ")
+            .flat_map(|operation| operation.operands[0].as_array().unwrap())
+            .map(|element| match element {
+                Object::String(bytes, _) => width(u16::from_be_bytes([bytes[0], bytes[1]])),
+                adjustment => -adjustment.as_i64().unwrap(),
+            })
+            .sum()
+    }
+
+    #[test]
+    fn drawn_runs_are_as_wide_as_measured() {
+        // kerning, a ligature and a run the shaper moves glyphs around in
+        for text in ["AVATAR office", "Tr\u{e9}s fl\u{fb01} Type"] {
+            let run = shape(&[ROBOTO], text, 11.0, false);
+            let measured: i32 = run.glyphs.iter().map(|glyph| glyph.advance).sum();
+
+            // the line breaker works with run.width(), which is `measured` at the run's size
+            assert_eq!(drawn_advance(&run), measured as i64, "{}", text);
+        }
+    }
+
+    #[test]
+    fn characters_the_primary_face_lacks_go_to_the_fallbacks() {
+        let fallback = testing::font("\u{65e5}\u{672c}");
+        let run = shape(
+            &[ROBOTO, &fallback],
+            "see \u{65e5}\u{672c} now\u{10fffd}",
+            11.0,
+            false,
+        );
+        let fonts: Vec<_> = run
+            .glyph_texts()
+            .into_iter()
+            .map(|(glyph, text)| (text, glyph.font))
+            .collect();
+
+        // the fallback face has no space, so the one after its run is drawn with the
+        // primary face, the missing character stays with the face before it
+        assert_eq!(
+            fonts,
+            [
+                ("s", 0),
+                ("e", 0),
+                ("e", 0),
+                (" ", 0),
+                ("\u{65e5}", 1),
+                ("\u{672c}", 1),
+                (" ", 0),
+                ("n", 0),
+                ("o", 0),
+                ("w", 0),
+                ("\u{10fffd}", 0),
+            ]
+        );
+        assert_eq!(run.fonts(), [0, 1]);
+        assert_eq!(run.missing(), ['\u{10fffd}']);
+        // the fallback glyphs are 500 units wide
+        assert_eq!(run.glyphs[4].advance, 500);
+    }
+}
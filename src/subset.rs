@@ -0,0 +1,299 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use printpdf::lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+use rustybuzz::ttf_parser::{name_id, Face, GlyphId};
+
+use crate::error::{Error, Result};
+
+// a face printpdf embedded in full, with the glyphs that were drawn with it
+// and the text each of them stands for
+pub struct UsedFace<'a> {
+    pub data: &'a [u8],
+    pub glyphs: &'a BTreeMap<u16, String>,
+}
+
+// replaces the fonts embedded in `pdf` with subsets of just the glyphs that were drawn,
+// and their widths and ToUnicode CMaps with ones covering only those glyphs, faces are
+// found by the contents of their font files
+pub fn subset_fonts(pdf: &[u8], faces: &[UsedFace]) -> Result<Vec<u8>> {
+    let mut document = Document::load_mem(pdf).map_err(subset_error)?;
+
+    // the same file can be loaded into several faces, every copy of it is embedded
+    // and can't be told apart from the others, so they all get the glyphs of all of them
+    let mut merged: Vec<(&[u8], BTreeMap<u16, String>)> = vec![];
+
+    for face in faces {
+        match merged.iter_mut().find(|(data, _)| *data == face.data) {
+            Some((_, glyphs)) => {
+                for (glyph, text) in face.glyphs {
+                    glyphs.entry(*glyph).or_insert_with(|| text.clone());
+                }
+            }
+            None => merged.push((face.data, face.glyphs.clone())),
+        }
+    }
+
+    let fonts: Vec<ObjectId> = document
+        .objects
+        .iter()
+        .filter(|(_, object)| {
+            object.as_dict().is_ok_and(|dict| {
+                dict.get(b"Subtype")
+                    .and_then(Object::as_name)
+                    .is_ok_and(|subtype| subtype == b"Type0")
+            })
+        })
+        .map(|(id, _)| *id)
+        .collect();
+
+    for id in fonts {
+        subset_font(&mut document, id, &merged).map_err(subset_error)?;
+    }
+
+    let mut bytes = vec![];
+    document.save_to(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+fn subset_font(
+    document: &mut Document,
+    id: ObjectId,
+    faces: &[(&[u8], BTreeMap<u16, String>)],
+) -> printpdf::lopdf::Result<()> {
+    let font = document.get_dictionary(id)?;
+    let to_unicode = font.get(b"ToUnicode")?.as_reference()?;
+    let mut descendant = font.get(b"DescendantFonts")?.as_array()?[0]
+        .as_dict()?
+        .clone();
+    let descriptor = descendant.get(b"FontDescriptor")?.as_reference()?;
+    let file = document
+        .get_dictionary(descriptor)?
+        .get(b"FontFile2")?
+        .as_reference()?;
+
+    let content = &document.get_object(file)?.as_stream()?.content;
+    let Some((data, used_glyphs)) = faces.iter().find(|(data, _)| *data == &content[..]) else {
+        return Ok(());
+    };
+
+    let face = Face::parse(data, 0).expect("fonts are validated on load");
+    let glyphs: Vec<u16> = used_glyphs.keys().copied().collect();
+
+    // fonts the subsetter can't handle stay embedded in full
+    let Ok(data) = subsetter::subset(data, 0, subsetter::Profile::pdf(&glyphs)) else {
+        return Ok(());
+    };
+
+    let name = format!("{}+{}", subset_tag(&glyphs), postscript_name(&face));
+
+    let mut file_dict = Dictionary::new();
+    file_dict.set("Length1", data.len() as i64);
+
+    let mut file_stream = Stream::new(file_dict, data);
+    file_stream.compress()?;
+
+    // cff outlines can't go into a FontFile2, they are embedded as OpenType
+    let cff = face.tables().cff.is_some();
+    let descriptor = document.get_dictionary_mut(descriptor)?;
+    descriptor.set("FontName", Object::Name(name.clone().into_bytes()));
+    descriptor.remove(b"FontFile2");
+
+    if cff {
+        file_stream
+            .dict
+            .set("Subtype", Object::Name(b"OpenType".to_vec()));
+        descriptor.set("FontFile3", Object::Reference(file));
+        descendant.set("Subtype", Object::Name(b"CIDFontType0".to_vec()));
+    } else {
+        descriptor.set("FontFile2", Object::Reference(file));
+    }
+
+    document.objects.insert(file, Object::Stream(file_stream));
+
+    descendant.set("BaseFont", Object::Name(name.clone().into_bytes()));
+    descendant.set("W", widths(&face, &glyphs));
+
+    let font = document.get_dictionary_mut(id)?;
+    font.set("BaseFont", Object::Name(name.into_bytes()));
+    font.set("DescendantFonts", Object::Array(vec![descendant.into()]));
+
+    let mut cmap = Stream::new(Dictionary::new(), to_unicode_cmap(used_glyphs).into_bytes());
+    cmap.compress()?;
+    document.objects.insert(to_unicode, Object::Stream(cmap));
+
+    Ok(())
+}
+
+// [first [w1 w2 ...] first [...] ...] for runs of consecutive glyph ids, widths are
+// truncated like ShapedGlyph::pdf_width expects
+fn widths(face: &Face, glyphs: &[u16]) -> Object {
+    let scale = 1000.0 / face.units_per_em() as f32;
+    let mut widths: Vec<Object> = vec![];
+    let mut run: Vec<Object> = vec![];
+    let mut first = None;
+
+    for (i, glyph) in glyphs.iter().enumerate() {
+        let advance = face.glyph_hor_advance(GlyphId(*glyph)).unwrap_or(0);
+        run.push(Object::Integer((advance as f32 * scale) as i64));
+        first.get_or_insert(*glyph);
+
+        if glyphs.get(i + 1) != Some(&(glyph + 1)) {
+            widths.push(Object::Integer(first.take().unwrap() as i64));
+            widths.push(Object::Array(std::mem::take(&mut run)));
+        }
+    }
+
+    Object::Array(widths)
+}
+
+// maps glyph ids straight to their text, utf-16 with surrogate pairs for characters
+// outside the basic multilingual plane
+fn to_unicode_cmap(glyphs: &BTreeMap<u16, String>) -> String {
+    let mut cmap = String::from(
+        "/CIDInit /ProcSet findresource begin\n\
+         12 dict begin\n\
+         begincmap\n\
+         /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+         /CMapName /Adobe-Identity-UCS def\n\
+         /CMapType 2 def\n\
+         1 begincodespacerange\n\
+         <0000> <FFFF>\n\
+         endcodespacerange\n",
+    );
+
+    let mapped: Vec<(&u16, &String)> = glyphs.iter().filter(|(_, text)| !text.is_empty()).collect();
+
+    // at most 100 mappings per block
+    for block in mapped.chunks(100) {
+        writeln!(cmap, "{} beginbfchar", block.len()).unwrap();
+
+        for (glyph, text) in block {
+            let utf16: String = text
+                .encode_utf16()
+                .map(|unit| format!("{:04X}", unit))
+                .collect();
+
+            writeln!(cmap, "<{:04X}> <{}>", glyph, utf16).unwrap();
+        }
+
+        cmap.push_str("endbfchar\n");
+    }
+
+    cmap.push_str(
+        "endcmap\n\
+         CMapName currentdict /CMap defineresource pop\n\
+         end\n\
+         end\n",
+    );
+
+    cmap
+}
+
+// six capital letters derived from the glyph set, so different subsets of a face
+// get different names and the same document always gets the same ones
+fn subset_tag(glyphs: &[u16]) -> String {
+    // fnv-1a
+    let mut hash: u32 = 0x811c9dc5;
+
+    for byte in glyphs.iter().flat_map(|glyph| glyph.to_be_bytes()) {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+
+    (0..6)
+        .map(|i| (b'A' + (hash >> (i * 5) & 0x1f) as u8 % 26) as char)
+        .collect()
+}
+
+fn postscript_name(face: &Face) -> String {
+    face.names()
+        .into_iter()
+        .filter(|name| name.name_id == name_id::POST_SCRIPT_NAME)
+        .find_map(|name| name.to_string())
+        .map(|name| {
+            name.chars()
+                .filter(|c| c.is_ascii_graphic() && !"[](){}<>/%".contains(*c))
+                .collect()
+        })
+        .filter(|name: &String| !name.is_empty())
+        .unwrap_or_else(|| "Font".to_string())
+}
+
+fn subset_error(err: printpdf::lopdf::Error) -> Error {
+    Error::Font(format!("could not subset fonts: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Backend, Config, FontFamily, Inhouse};
+    use std::path::Path;
+
+    #[test]
+    fn faces_loaded_twice_keep_the_glyphs_of_both() {
+        // the bold face is a second copy of the regular one
+        let data = std::fs::read("assets/fonts/Roboto-Regular.ttf").unwrap();
+        let family = FontFamily::new(data.clone(), data.clone(), None, None);
+        let config = Config::builder().body_font(family).build().unwrap();
+        let pdf = Inhouse::render_document("plain **bold**\n", Path::new("a.md"), &config).unwrap();
+
+        let face = Face::parse(&data, 0).unwrap();
+        let glyph = |c: char| face.glyph_index(c).unwrap().0 as i64;
+        let document = Document::load_mem(&pdf).unwrap();
+        let mut fonts = 0;
+
+        for object in document.objects.values() {
+            let Ok(font) = object.as_dict() else {
+                continue;
+            };
+
+            if !font
+                .get(b"Subtype")
+                .and_then(Object::as_name)
+                .is_ok_and(|subtype| subtype == b"Type0")
+            {
+                continue;
+            }
+
+            fonts += 1;
+
+            // every drawn letter has a width
+            let descendant = font.get(b"DescendantFonts").unwrap().as_array().unwrap()[0]
+                .as_dict()
+                .unwrap();
+            let runs = descendant.get(b"W").unwrap().as_array().unwrap();
+            let covered: Vec<i64> = runs
+                .chunks(2)
+                .flat_map(|run| {
+                    let first = run[0].as_i64().unwrap();
+                    let count = run[1].as_array().unwrap().len() as i64;
+                    first..first + count
+                })
+                .collect();
+
+            // and maps back to its text
+            let to_unicode = font.get(b"ToUnicode").unwrap().as_reference().unwrap();
+            let cmap = document
+                .get_object(to_unicode)
+                .unwrap()
+                .as_stream()
+                .unwrap()
+                .decompressed_content()
+                .unwrap();
+            let cmap = String::from_utf8(cmap).unwrap();
+
+            for c in "plainbod".chars() {
+                assert!(covered.contains(&glyph(c)), "{} has no width", c);
+                assert!(
+                    cmap.contains(&format!("<{:04X}> <{:04X}>", glyph(c), c as u32)),
+                    "{} is not mapped",
+                    c
+                );
+            }
+        }
+
+        assert_eq!(fonts, 2);
+    }
+}
@@ -0,0 +1,362 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use rustybuzz::ttf_parser::{Face, GlyphId, OutlineBuilder};
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::layout::{self, Element, Layout, Page, TextRun};
+use crate::render::render_each;
+use crate::theme::css_color;
+use crate::util::escape;
+
+// paints a layout as one svg document per page, glyphs are drawn as outlines so the
+// pages look the same without the fonts installed, every run keeps its text in an
+// `aria-label` so renders can be diffed and read by screen readers
+pub struct SvgWriter<'a> {
+    layout: &'a Layout,
+    faces: Vec<Face<'a>>,
+    // path data of every glyph drawn so far in thousandths of an em with y going down,
+    // none for glyphs without an outline like spaces
+    outlines: HashMap<(usize, u16), Option<String>>,
+}
+
+impl<'a> SvgWriter<'a> {
+    pub(crate) fn write(layout: &'a Layout) -> Vec<String> {
+        let mut writer = SvgWriter {
+            layout,
+            faces: layout
+                .faces
+                .iter()
+                .map(|face| Face::parse(face, 0).expect("fonts are validated on load"))
+                .collect(),
+            outlines: HashMap::new(),
+        };
+
+        layout
+            .pages
+            .iter()
+            .map(|page| writer.write_page(page))
+            .collect()
+    }
+
+    // `doc.md` becomes `doc-1.svg`, `doc-2.svg` and so on
+    pub fn render_files(
+        files: &HashMap<PathBuf, String>,
+        config: &Config,
+    ) -> Result<HashMap<PathBuf, Vec<u8>>> {
+        render_each(files, |path, content| {
+            let stem = path.file_stem().unwrap().to_string_lossy();
+            let layout = layout::layout_document(content, path, config)?;
+
+            Ok(SvgWriter::write(&layout)
+                .into_iter()
+                .enumerate()
+                .map(|(i, page)| {
+                    let name = format!("{}-{}.svg", stem, i + 1);
+                    (path.with_file_name(name), page.into_bytes())
+                })
+                .collect())
+        })
+    }
+
+    // links go last so nothing is drawn over them
+    fn write_page(&mut self, page: &Page) -> String {
+        let (width, height) = (self.layout.width.0, self.layout.height.0);
+        let mut glyphs = BTreeSet::new();
+        let mut body = String::new();
+
+        if let Some(color) = &self.layout.background {
+            writeln!(
+                body,
+                r#"<rect width="{}" height="{}" fill="{}"/>"#,
+                number(width),
+                number(height),
+                css_color(color)
+            )
+            .unwrap();
+        }
+
+        // filled areas and images go below everything else
+        for element in &page.elements {
+            match element {
+                Element::Rect { from, to, color } => writeln!(
+                    body,
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+                    number(from.0 .0),
+                    number(height - to.1 .0),
+                    number(to.0 .0 - from.0 .0),
+                    number(to.1 .0 - from.1 .0),
+                    css_color(color)
+                )
+                .unwrap(),
+                Element::Image { from, to, image } => writeln!(
+                    body,
+                    r#"<image x="{}" y="{}" width="{}" height="{}" preserveAspectRatio="none" href="data:image/png;base64,{}"/>"#,
+                    number(from.0 .0),
+                    number(height - to.1 .0),
+                    number(to.0 .0 - from.0 .0),
+                    number(to.1 .0 - from.1 .0),
+                    base64(&image.data)
+                )
+                .unwrap(),
+                _ => {}
+            }
+        }
+
+        for element in &page.elements {
+            match element {
+                Element::Text(text) => self.write_text(&mut body, &mut glyphs, text),
+                Element::Line {
+                    from,
+                    to,
+                    width,
+                    color,
+                } => writeln!(
+                    body,
+                    r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="{}"/>"#,
+                    number(from.0 .0),
+                    number(height - from.1 .0),
+                    number(to.0 .0),
+                    number(height - to.1 .0),
+                    css_color(color),
+                    number(width.0)
+                )
+                .unwrap(),
+                Element::Rect { .. }
+                | Element::Image { .. }
+                | Element::Link { .. }
+                | Element::PageLink { .. } => {}
+            }
+        }
+
+        for element in &page.elements {
+            if let Element::Link { from, to, url } = element {
+                writeln!(
+                    body,
+                    r#"<a href="{}"><rect x="{}" y="{}" width="{}" height="{}" fill-opacity="0"/></a>"#,
+                    escape(url),
+                    number(from.0 .0),
+                    number(height - to.1 .0),
+                    number(to.0 .0 - from.0 .0),
+                    number(to.1 .0 - from.1 .0)
+                )
+                .unwrap();
+            }
+        }
+
+        let mut svg = String::new();
+
+        writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}pt" height="{h}pt" viewBox="0 0 {w} {h}">"#,
+            w = number(width),
+            h = number(height)
+        )
+        .unwrap();
+        writeln!(svg, "<title>{}</title>", escape(&self.layout.title)).unwrap();
+
+        if !glyphs.is_empty() {
+            svg.push_str("<defs>\n");
+
+            for (face, id) in glyphs {
+                let outline = self.outlines[&(face, id)].as_ref().unwrap();
+                writeln!(svg, r#"<path id="g{}-{}" d="{}"/>"#, face, id, outline).unwrap();
+            }
+
+            svg.push_str("</defs>\n");
+        }
+
+        svg.push_str(&body);
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    // a group scaled to the font size, with the glyphs placed in thousandths of an em
+    fn write_text(
+        &mut self,
+        body: &mut String,
+        glyphs: &mut BTreeSet<(usize, u16)>,
+        text: &TextRun,
+    ) {
+        let scale = text.run.size / 1000.0;
+        let (x, y) = (number(text.x.0), number(self.layout.height.0 - text.y.0));
+        let transform = match text.slant {
+            0.0 => format!("translate({} {}) scale({})", x, y, scale),
+            slant => format!(
+                "matrix({s} 0 {} {s} {} {})",
+                -slant * scale,
+                x,
+                y,
+                s = scale
+            ),
+        };
+
+        let mut uses = String::new();
+        let mut pen = 0;
+
+        for glyph in &text.run.glyphs {
+            let face = text.faces[glyph.font];
+
+            if self.outline(face, glyph.id).is_some() {
+                glyphs.insert((face, glyph.id));
+
+                match pen + glyph.x_offset {
+                    0 => writeln!(uses, r##"<use href="#g{}-{}"/>"##, face, glyph.id),
+                    x => writeln!(uses, r##"<use href="#g{}-{}" x="{}"/>"##, face, glyph.id, x),
+                }
+                .unwrap();
+            }
+
+            pen += glyph.advance;
+        }
+
+        // runs of spaces only move the pen
+        if uses.is_empty() {
+            return;
+        }
+
+        writeln!(
+            body,
+            r#"<g aria-label="{}" fill="{}" transform="{}">"#,
+            escape(&text.run.text),
+            css_color(&text.color),
+            transform
+        )
+        .unwrap();
+        body.push_str(&uses);
+        body.push_str("</g>\n");
+    }
+
+    fn outline(&mut self, face: usize, id: u16) -> Option<&String> {
+        let faces = &self.faces;
+
+        self.outlines
+            .entry((face, id))
+            .or_insert_with(|| {
+                let face = &faces[face];
+                let mut path = Path {
+                    data: String::new(),
+                    scale: 1000.0 / face.units_per_em() as f32,
+                };
+
+                face.outline_glyph(GlyphId(id), &mut path)
+                    .map(|_| path.data.trim_end().to_string())
+            })
+            .as_ref()
+    }
+}
+
+// svg path data for a glyph outline, flipped so y goes down
+struct Path {
+    data: String,
+    scale: f32,
+}
+
+impl Path {
+    fn point(&mut self, x: f32, y: f32) {
+        write!(
+            self.data,
+            "{} {} ",
+            number(x * self.scale),
+            number(-y * self.scale)
+        )
+        .unwrap();
+    }
+}
+
+impl OutlineBuilder for Path {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.data.push('M');
+        self.point(x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.data.push('L');
+        self.point(x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.data.push('Q');
+        self.point(x1, y1);
+        self.point(x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.data.push('C');
+        self.point(x1, y1);
+        self.point(x2, y2);
+        self.point(x, y);
+    }
+
+    fn close(&mut self) {
+        self.data.push_str("Z ");
+    }
+}
+
+// two decimals without trailing zeros, keeps the files small and stable to diff
+fn number(value: f32) -> String {
+    let formatted = format!("{:.2}", value);
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+
+    match trimmed {
+        "-0" => "0".to_string(),
+        _ => trimmed.to_string(),
+    }
+}
+
+// standard alphabet with padding, for images embedded as data urls
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    const DOCUMENT: &str =
+        "# Blocks\n\n> quoted\n\n```\nlet code = 1;\n```\n\n| a | b |\n|---|---|\n| 1 | 2 |\n";
+
+    #[test]
+    fn writes_tables_code_and_quotes() {
+        let config = testing::config();
+        let files = HashMap::from([(PathBuf::from("doc.md"), DOCUMENT.to_string())]);
+        let rendered = SvgWriter::render_files(&files, &config).unwrap();
+        let svg = String::from_utf8(rendered[&PathBuf::from("doc-1.svg")].clone()).unwrap();
+
+        assert_eq!(rendered.len(), 1);
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains(r#"aria-label="quoted""#));
+        assert!(svg.contains("<rect"));
+    }
+
+    #[test]
+    fn encodes_base64_with_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+}
@@ -0,0 +1,117 @@
+// helpers shared by the unit tests
+
+use std::env;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::config::Config;
+use crate::render::Style;
+
+// the default configuration, with the builtin assets
+pub(crate) fn config() -> Config {
+    styled(Style::default())
+}
+
+pub(crate) fn styled(style: Style) -> Config {
+    Config::builder().style(style).build().unwrap()
+}
+
+// a fresh directory in the system temp dir, removed again when it goes out of scope
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    // `name` only makes leftovers easier to trace, every dir is unique
+    pub(crate) fn new(name: &str) -> TempDir {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        let count = COUNT.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("jamdr-{}-{}-{}", name, process::id(), count));
+        fs::create_dir_all(&path).unwrap();
+
+        TempDir(path)
+    }
+
+    // writes `content` to `file` in the dir, creating parent dirs
+    pub(crate) fn write(&self, file: impl AsRef<Path>, content: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.0.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+
+        path
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.0).ok();
+    }
+}
+
+// a bare truetype font with a 500 unit wide, empty glyph for each of `chars`,
+// for faces that cover what the bundled ones don't
+pub(crate) fn font(chars: &str) -> Vec<u8> {
+    let count = chars.chars().count() as u16 + 1;
+    let be16 =
+        |values: &[u16]| -> Vec<u8> { values.iter().flat_map(|v| v.to_be_bytes()).collect() };
+    let be32 =
+        |values: &[u32]| -> Vec<u8> { values.iter().flat_map(|v| v.to_be_bytes()).collect() };
+
+    // format 12 cmap, one group per character
+    let mut cmap = [be16(&[0, 1, 3, 10]), be32(&[12])].concat();
+    cmap.extend(be16(&[12, 0]));
+    cmap.extend(be32(&[16 + 12 * (count as u32 - 1), 0, count as u32 - 1]));
+    for (glyph, c) in chars.chars().enumerate() {
+        cmap.extend(be32(&[c as u32, c as u32, glyph as u32 + 1]));
+    }
+
+    let head = [
+        be32(&[0x10000, 0x10000, 0, 0x5f0f3cf5]),
+        be16(&[0, 1000]),
+        vec![0; 16],
+        be16(&[0, 0xff38, 500, 800, 0, 8, 2, 0, 0]),
+    ]
+    .concat();
+    let hhea = [
+        be32(&[0x10000]),
+        be16(&[800, 0xff38, 0, 500]),
+        vec![0; 22],
+        be16(&[count]),
+    ]
+    .concat();
+    let hmtx = be16(&[500, 0].repeat(count as usize));
+    let maxp = [be32(&[0x5000]), be16(&[count])].concat();
+
+    let tables = [
+        (b"cmap", cmap),
+        (b"head", head),
+        (b"hhea", hhea),
+        (b"hmtx", hmtx),
+        (b"maxp", maxp),
+    ];
+
+    let mut font = [be32(&[0x10000]), be16(&[tables.len() as u16, 0, 0, 0])].concat();
+    let start = font.len() + 16 * tables.len();
+    let mut data = vec![];
+
+    for (tag, table) in &tables {
+        font.extend(*tag);
+        font.extend(be32(&[0, (start + data.len()) as u32, table.len() as u32]));
+        data.extend(table);
+        // tables start on four byte boundaries
+        data.resize(data.len().next_multiple_of(4), 0);
+    }
+
+    font.extend(data);
+    font
+}
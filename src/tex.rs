@@ -0,0 +1,634 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use handlebars::Handlebars;
+use pulldown_cmark::{Alignment, CodeBlockKind, Tag};
+use serde_json::json;
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::front_matter::FrontMatter;
+use crate::page_break;
+use crate::render::{render_each, Backend, PageBreak};
+use crate::theme::hex_color;
+use crate::walk::{self, Walker};
+
+// stands in for a math span while the markdown is parsed, so markdown syntax inside
+// the math is left alone, the number between two of them is the index of the span,
+// the spans are only put back once the notes are filled in
+const MATH: char = '\u{e000}';
+// where a footnote goes, notes are defined after the text that references them
+const NOTE: char = '\u{e001}';
+
+// writes documents as latex sources, the body comes from the markdown events and is
+// placed into the tex template of the assets, which holds the preamble
+pub struct Tex {}
+
+impl Backend for Tex {
+    // relative image paths start next to the document, the source is expected to be
+    // written next to the markdown so the paths still work
+    fn render_document(markdown: &str, path: &Path, config: &Config) -> Result<Vec<u8>> {
+        let (front_matter, markdown) = FrontMatter::split(markdown)?;
+        let mut style = config.style.clone();
+        front_matter.apply(&mut style)?;
+
+        let (markdown, math) = protect_math(&page_break::mark(markdown, style.asterisk_breaks));
+        let events = walk::parse(&markdown);
+
+        let base = path.parent().unwrap_or(Path::new(""));
+        let mut writer = TexWriter::new(base, math);
+        walk::walk(&events, &mut writer);
+        writer.finish();
+
+        let mut hb = Handlebars::new();
+        hb.register_escape_fn(escape);
+        hb.register_template_string("tex", &config.assets.tex_template)?;
+
+        let lang = front_matter.lang.as_deref().unwrap_or(&config.lang);
+        let context = json!({
+            "content": writer.body,
+            "title": front_matter.title,
+            "author": front_matter.author,
+            "date": front_matter.date,
+            "lang": lang,
+            "babel": babel_language(lang),
+            // the standard classes only come in these sizes
+            "font_size": match style.font_size {
+                size if size < 10.5 => 10,
+                size if size < 11.5 => 11,
+                _ => 12,
+            },
+            "paper_width": format!("{:.2}mm", style.width.0),
+            "paper_height": format!("{:.2}mm", style.height.0),
+            "margin_x": format!("{:.2}mm", style.horizontal_padding.0),
+            "margin_y": format!("{:.2}mm", style.vertical_padding.0),
+            // latex lines are 1.2 times the font size to begin with
+            "line_spread": format!("{:.3}", style.line_height / 1.2),
+            "text_color": hex_color(&style.text_color),
+            "link_color": hex_color(&style.link_color),
+            "rule_color": hex_color(&style.rule_color),
+            "h1_break": style.h1_break != PageBreak::Auto,
+            "h1_break_right": style.h1_break == PageBreak::Right,
+            "columns": Some(style.columns).filter(|columns| *columns > 1),
+        });
+
+        let tex = hb.render_with_context("tex", &handlebars::Context::from(context))?;
+        Ok(tex.into_bytes())
+    }
+
+    fn render_files(
+        files: &HashMap<PathBuf, String>,
+        config: &Config,
+    ) -> Result<HashMap<PathBuf, Vec<u8>>> {
+        render_each(files, |path, content| {
+            let tex = Tex::render_document(content, path, config)?;
+
+            Ok(vec![(path.with_extension("tex"), tex)])
+        })
+    }
+}
+
+struct TableState {
+    cell: usize,
+    head: bool,
+}
+
+struct TexWriter<'a> {
+    base: &'a Path,
+    // latex for the placeholders in the body, math spans as they were written
+    spans: Vec<String>,
+
+    body: String,
+    footnote_ids: HashMap<String, usize>,
+    footnotes: HashMap<usize, String>,
+    footnote: Option<usize>,
+
+    // whether each open list is numbered
+    lists: Vec<bool>,
+    // whether each open link became a hyperlink, links to anchors stay plain text
+    links: Vec<bool>,
+    // the code and its listings language
+    code_block: Option<(String, Option<&'static str>)>,
+    table: Option<TableState>,
+    // the url and alt text of the image being read
+    image: Option<(String, String)>,
+}
+
+impl<'a> TexWriter<'a> {
+    fn new(base: &'a Path, spans: Vec<String>) -> Self {
+        TexWriter {
+            base,
+            spans,
+            body: String::new(),
+            footnote_ids: HashMap::new(),
+            footnotes: HashMap::new(),
+            footnote: None,
+            lists: vec![],
+            links: vec![],
+            code_block: None,
+            table: None,
+            image: None,
+        }
+    }
+
+    fn finish(&mut self) {
+        self.body = self.restore_spans(&self.fill_notes());
+    }
+
+    // where text goes, the body or the note being written
+    fn out(&mut self) -> &mut String {
+        match self.footnote {
+            Some(id) => self.footnotes.get_mut(&id).unwrap(),
+            None => &mut self.body,
+        }
+    }
+
+    // only local images are included, latex can't fetch the others
+    fn image(&mut self, url: &str, alt: &str) {
+        if url.contains("://") || !self.base.join(url).is_file() {
+            eprintln!("warning: could not include image {}", url);
+            self.out().push_str(&escape(alt));
+            return;
+        }
+
+        self.out()
+            .push_str(&format!("\\includegraphics{{{}}}", escape_url(url)));
+    }
+
+    // code spans show math spans as text, escaped like the rest of the code
+    fn escape_code(&mut self, code: &str) -> String {
+        let mut escaped = String::new();
+
+        for (i, part) in code.split(MATH).enumerate() {
+            match i % 2 {
+                0 => escaped.push_str(&escape(part)),
+                _ => {
+                    let span = escape(&self.spans[part.parse::<usize>().unwrap()]);
+                    escaped.push_str(&placeholder(self.spans.len()));
+                    self.spans.push(span);
+                }
+            }
+        }
+
+        escaped
+    }
+
+    // the placeholders are safe to parse, protect_math turned the characters they are
+    // made of into placeholders as well where the markdown had them
+    fn restore_spans(&self, text: &str) -> String {
+        text.split(MATH)
+            .enumerate()
+            .map(|(i, part)| match i % 2 {
+                0 => part,
+                _ => &self.spans[part.parse::<usize>().unwrap()],
+            })
+            .collect()
+    }
+
+    // notes are numbered in the order they are first mentioned
+    fn footnote_id(&mut self, label: &str) -> usize {
+        let next = self.footnote_ids.len() + 1;
+        *self.footnote_ids.entry(label.to_string()).or_insert(next)
+    }
+
+    // every reference gets its own copy of the note
+    fn fill_notes(&self) -> String {
+        self.body
+            .split(NOTE)
+            .enumerate()
+            .map(|(i, part)| match i % 2 {
+                0 => part.to_string(),
+                _ => {
+                    let id = part.parse::<usize>().unwrap();
+                    let note = self.footnotes.get(&id).map_or("", |note| note.trim());
+                    format!("\\footnote{{{}}}", note)
+                }
+            })
+            .collect()
+    }
+}
+
+impl<'a> Walker<'a> for TexWriter<'a> {
+    fn start(&mut self, tag: Tag<'a>) {
+        match tag {
+            Tag::Paragraph => {}
+            Tag::Heading(level, _, _) => {
+                let command = [
+                    "section",
+                    "subsection",
+                    "subsubsection",
+                    "paragraph",
+                    "subparagraph",
+                    "subparagraph",
+                ][level as usize - 1];
+                self.out().push_str(&format!("\\{}{{", command));
+            }
+            Tag::BlockQuote => self.out().push_str("\\begin{quote}\n"),
+            Tag::CodeBlock(kind) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => {
+                        listings_language(info.split_whitespace().next().unwrap_or(""))
+                    }
+                    CodeBlockKind::Indented => None,
+                };
+                self.code_block = Some((String::new(), language));
+            }
+            Tag::List(start) => {
+                match start {
+                    Some(start) => {
+                        self.out().push_str("\\begin{enumerate}\n");
+
+                        // the enumerate counters are enumi to enumiv by depth
+                        let depth = self.lists.iter().filter(|numbered| **numbered).count();
+
+                        if start != 1 && depth < 4 {
+                            let counter = ["i", "ii", "iii", "iv"][depth];
+                            let set = format!("\\setcounter{{enum{}}}{{{}}}\n", counter, start - 1);
+                            self.out().push_str(&set);
+                        }
+                    }
+                    None => self.out().push_str("\\begin{itemize}\n"),
+                }
+
+                self.lists.push(start.is_some());
+            }
+            Tag::Item => self.out().push_str("\\item "),
+            Tag::FootnoteDefinition(label) => {
+                let id = self.footnote_id(&label);
+                self.footnotes.insert(id, String::new());
+                self.footnote = Some(id);
+            }
+            Tag::Table(alignments) => {
+                let columns: Vec<&str> = alignments
+                    .iter()
+                    .map(|alignment| match alignment {
+                        Alignment::Center => ">{\\centering\\arraybackslash}X",
+                        Alignment::Right => ">{\\raggedleft\\arraybackslash}X",
+                        _ => "X",
+                    })
+                    .collect();
+
+                let table = format!(
+                    "\\begin{{tabularx}}{{\\linewidth}}{{|{}|}}\n\\hline\n",
+                    columns.join("|")
+                );
+                self.out().push_str(&table);
+                self.table = Some(TableState {
+                    cell: 0,
+                    head: false,
+                });
+            }
+            Tag::TableHead => {
+                self.table = Some(TableState {
+                    cell: 0,
+                    head: true,
+                })
+            }
+            Tag::TableRow => self.table.as_mut().unwrap().cell = 0,
+            Tag::TableCell => {
+                let table = self.table.as_ref().unwrap();
+                let mut cell = match table.cell {
+                    0 => String::new(),
+                    _ => " & ".to_string(),
+                };
+
+                if table.head {
+                    cell.push_str("\\textbf{");
+                }
+
+                self.out().push_str(&cell);
+            }
+            Tag::Emphasis => self.out().push_str("\\emph{"),
+            Tag::Strong => self.out().push_str("\\textbf{"),
+            Tag::Strikethrough => self.out().push_str("\\sout{"),
+            Tag::Link(_, url, _) => {
+                let hyperlink = !url.starts_with('#');
+
+                if hyperlink {
+                    let link = format!("\\href{{{}}}{{", escape_url(&url));
+                    self.out().push_str(&link);
+                }
+
+                self.links.push(hyperlink);
+            }
+            Tag::Image(_, url, _) => self.image = Some((url.to_string(), String::new())),
+        }
+    }
+
+    fn end(&mut self, tag: Tag<'a>) {
+        match tag {
+            Tag::Paragraph => self.out().push_str("\n\n"),
+            Tag::Heading(..) => self.out().push_str("}\n\n"),
+            Tag::BlockQuote => self.out().push_str("\\end{quote}\n\n"),
+            Tag::CodeBlock(_) => {
+                let (code, language) = self.code_block.take().unwrap();
+                let options = language.map_or(String::new(), |language| {
+                    format!("[language={{{}}}]", language)
+                });
+
+                let listing = format!(
+                    "\\begin{{lstlisting}}{}\n{}\n\\end{{lstlisting}}\n\n",
+                    options,
+                    code.trim_end_matches('\n')
+                );
+                self.out().push_str(&listing);
+            }
+            Tag::List(_) => {
+                let environment = match self.lists.pop().unwrap() {
+                    true => "enumerate",
+                    false => "itemize",
+                };
+                self.out()
+                    .push_str(&format!("\\end{{{}}}\n\n", environment));
+            }
+            Tag::Item => self.out().push('\n'),
+            Tag::FootnoteDefinition(_) => self.footnote = None,
+            Tag::Table(_) => {
+                self.out().push_str("\\end{tabularx}\n\n");
+                self.table = None;
+            }
+            Tag::TableHead => {
+                self.out().push_str(" \\\\\n\\hline\n");
+                self.table.as_mut().unwrap().head = false;
+            }
+            Tag::TableRow => self.out().push_str(" \\\\\n\\hline\n"),
+            Tag::TableCell => {
+                let table = self.table.as_mut().unwrap();
+                table.cell += 1;
+
+                if table.head {
+                    self.out().push('}');
+                }
+            }
+            Tag::Emphasis | Tag::Strong | Tag::Strikethrough => self.out().push('}'),
+            Tag::Link(..) => {
+                if self.links.pop() == Some(true) {
+                    self.out().push('}');
+                }
+            }
+            Tag::Image(..) => {
+                let (url, alt) = self.image.take().unwrap();
+                self.image(&url, &alt);
+            }
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if let Some((code, _)) = &mut self.code_block {
+            code.push_str(text);
+        } else if let Some((_, alt)) = &mut self.image {
+            alt.push_str(text);
+        } else {
+            self.out().push_str(&escape(text));
+        }
+    }
+
+    fn code(&mut self, code: &str) {
+        let code = format!("\\texttt{{{}}}", self.escape_code(code));
+        self.out().push_str(&code);
+    }
+
+    // latex can't put notes into notes, references inside them are dropped
+    fn footnote_reference(&mut self, label: &str) {
+        let id = self.footnote_id(label);
+
+        if self.footnote.is_none() {
+            self.body.push_str(&format!("{}{}{}", NOTE, id, NOTE));
+        }
+    }
+
+    fn soft_break(&mut self) {
+        self.out().push('\n');
+    }
+
+    fn hard_break(&mut self) {
+        self.out().push_str("\\\\\n");
+    }
+
+    fn rule(&mut self) {
+        self.out()
+            .push_str("\\noindent{\\color{rule}\\rule{\\linewidth}{0.4pt}}\n\n");
+    }
+
+    fn task_list_marker(&mut self, checked: bool) {
+        match checked {
+            true => self.out().push_str("[$\\boxtimes$] "),
+            false => self.out().push_str("[$\\square$] "),
+        }
+    }
+
+    fn page_break(&mut self) {
+        self.out().push_str("\\newpage\n\n");
+    }
+}
+
+// replaces `$...$` and `$$...$$` with placeholders, like pandoc an inline span can't
+// start with a space, end after one or be followed by a digit, so prices stay text
+//
+// the characters placeholders are made of become placeholders of their own, so they
+// can't be mistaken for one
+fn protect_math(markdown: &str) -> (String, Vec<String>) {
+    let mut protected = String::new();
+    let mut math = vec![];
+    let mut copied = 0;
+    let mut at = 0;
+
+    while let Some(offset) = markdown[at..].find(['$', MATH, NOTE]) {
+        let start = at + offset;
+
+        if let Some(character) = markdown[start..].chars().next().filter(|c| *c != '$') {
+            protected.push_str(&markdown[copied..start]);
+            protected.push_str(&placeholder(math.len()));
+            math.push(character.to_string());
+            copied = start + character.len_utf8();
+            at = copied;
+            continue;
+        }
+
+        let display = markdown[start..].starts_with("$$");
+
+        let end = match (display, markdown[..start].ends_with('\\')) {
+            (_, true) => None,
+            (true, false) => markdown[start + 2..]
+                .find("$$")
+                .map(|end| start + 2 + end + 2)
+                .filter(|end| !markdown[start..*end].contains("\n\n")),
+            (false, false) => inline_math_end(&markdown[start..]).map(|end| start + end),
+        };
+
+        let Some(end) = end else {
+            at = start + if display { 2 } else { 1 };
+            continue;
+        };
+
+        protected.push_str(&markdown[copied..start]);
+        protected.push_str(&placeholder(math.len()));
+        math.push(markdown[start..end].to_string());
+        copied = end;
+        at = end;
+    }
+
+    protected.push_str(&markdown[copied..]);
+    (protected, math)
+}
+
+fn placeholder(index: usize) -> String {
+    format!("{}{}{}", MATH, index, MATH)
+}
+
+// the length of the inline math span at the start of `text`, spans stay on one line
+// and don't reach into code spans
+fn inline_math_end(text: &str) -> Option<usize> {
+    let inner = &text[1..];
+    let line = inner.split(['\n', '`']).next().unwrap();
+
+    if line.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    line.char_indices()
+        .filter(|(i, character)| *character == '$' && *i > 0)
+        .find(|(i, _)| {
+            let before = &line[..*i];
+            !before.ends_with(char::is_whitespace)
+                && !before.ends_with('\\')
+                && !line[i + 1..].starts_with(|next: char| next.is_ascii_digit())
+        })
+        .map(|(i, _)| i + 2)
+}
+
+// the languages listings knows, code in others is shown plain
+fn listings_language(info: &str) -> Option<&'static str> {
+    let language = match info.to_lowercase().as_str() {
+        "c" => "C",
+        "c++" | "cpp" => "C++",
+        "java" => "Java",
+        "python" | "py" => "Python",
+        "bash" | "sh" | "shell" => "bash",
+        "html" => "HTML",
+        "xml" => "XML",
+        "sql" => "SQL",
+        "ruby" | "rb" => "Ruby",
+        "perl" => "Perl",
+        "php" => "PHP",
+        "haskell" | "hs" => "Haskell",
+        "lua" => "Lua",
+        "r" => "R",
+        "matlab" => "Matlab",
+        "fortran" => "Fortran",
+        "pascal" => "Pascal",
+        "make" | "makefile" => "make",
+        "tex" | "latex" => "[LaTeX]TeX",
+        _ => return None,
+    };
+
+    Some(language)
+}
+
+// the babel name of a language tag, none for languages without a known name
+fn babel_language(lang: &str) -> Option<&'static str> {
+    let lang = lang.to_lowercase();
+    let primary = lang.split(['-', '_']).next().unwrap();
+
+    let language = match (lang.as_str(), primary) {
+        ("en-us", _) => "american",
+        ("en-gb", _) => "british",
+        ("de-ch", _) => "nswissgerman",
+        ("de-at", _) => "naustrian",
+        ("pt-br", _) => "brazilian",
+        (_, "en") => "english",
+        (_, "de") => "ngerman",
+        (_, "fr") => "french",
+        (_, "es") => "spanish",
+        (_, "it") => "italian",
+        (_, "nl") => "dutch",
+        (_, "pt") => "portuguese",
+        (_, "sv") => "swedish",
+        (_, "da") => "danish",
+        (_, "nb" | "no") => "norsk",
+        (_, "fi") => "finnish",
+        (_, "pl") => "polish",
+        (_, "cs") => "czech",
+        (_, "hu") => "magyar",
+        (_, "tr") => "turkish",
+        _ => return None,
+    };
+
+    Some(language)
+}
+
+// brackets are braced so text after `\item` isn't read as its optional argument
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for character in text.chars() {
+        match character {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '{' | '}' | '$' | '&' | '#' | '_' | '%' => {
+                escaped.push('\\');
+                escaped.push(character);
+            }
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '[' | ']' => {
+                escaped.push('{');
+                escaped.push(character);
+                escaped.push('}');
+            }
+            _ => escaped.push(character),
+        }
+    }
+
+    escaped
+}
+
+// hyperref takes urls mostly as they are
+fn escape_url(url: &str) -> String {
+    let mut escaped = String::with_capacity(url.len());
+
+    for character in url.chars() {
+        if matches!(character, '\\' | '#' | '%' | '{' | '}') {
+            escaped.push('\\');
+        }
+
+        escaped.push(character);
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempDir};
+
+    fn render(markdown: &str, path: &Path) -> String {
+        let config = testing::config();
+        String::from_utf8(Tex::render_document(markdown, path, &config).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn math_is_kept_as_written() {
+        let tex = render("area $a_b^2$ and `$x_1$`\n", Path::new("doc.md"));
+
+        assert!(tex.contains("area $a_b^2$ and \\texttt{\\$x\\_1\\$}"));
+    }
+
+    #[test]
+    fn placeholder_characters_in_the_text_stay_text() {
+        let markdown = "a \u{e000}1\u{e000} $x$ b\u{e001}2\u{e001}[^n]\n\n[^n]: note\n";
+        let tex = render(markdown, Path::new("doc.md"));
+
+        assert!(tex.contains("a \u{e000}1\u{e000} $x$ b\u{e001}2\u{e001}\\footnote{note}"));
+    }
+
+    #[test]
+    fn image_paths_are_escaped() {
+        let dir = TempDir::new("tex");
+        dir.write("100%.png", b"");
+
+        let tex = render("![chart](100%.png)\n", &dir.join("doc.md"));
+
+        assert!(tex.contains("\\includegraphics{100\\%.png}"));
+    }
+}
@@ -0,0 +1,875 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use pulldown_cmark::{Alignment, HeadingLevel, Tag};
+use unicode_width::UnicodeWidthChar;
+
+use crate::error::Result;
+use crate::front_matter::FrontMatter;
+use crate::page_break;
+use crate::render::render_each;
+use crate::walk::{self, Walker};
+
+// reflows documents as plain text, links are numbered and listed at the end, for
+// terminals the text can be styled with ansi escapes instead of markup
+pub struct PlainText {}
+
+impl PlainText {
+    // wrapped at `width` columns
+    pub fn render_document(markdown: &str, width: usize) -> Result<String> {
+        render(markdown, width, false)
+    }
+
+    // like render_document, with bold, italics, colors and clickable links
+    pub fn render_ansi(markdown: &str, width: usize) -> Result<String> {
+        render(markdown, width, true)
+    }
+
+    pub fn render_files(
+        files: &HashMap<PathBuf, String>,
+        width: usize,
+    ) -> Result<HashMap<PathBuf, Vec<u8>>> {
+        render_each(files, |path, content| {
+            let text = PlainText::render_document(content, width)?;
+            Ok(vec![(path.with_extension("txt"), text.into_bytes())])
+        })
+    }
+}
+
+fn render(markdown: &str, width: usize, ansi: bool) -> Result<String> {
+    let (front_matter, markdown) = FrontMatter::split(markdown)?;
+    let asterisks = front_matter.asterisk_breaks.unwrap_or(false);
+    let markdown = page_break::mark(markdown, asterisks);
+    let events = walk::parse(&markdown);
+
+    let mut writer = TextWriter::new(width, ansi);
+    walk::walk(&events, &mut writer);
+    writer.finish();
+
+    Ok(writer.lines.join("\n") + "\n")
+}
+
+// lines are never narrower than this, however deep the nesting
+const MIN_WIDTH: usize = 20;
+
+#[derive(Clone, Copy, Default, PartialEq)]
+struct SpanStyle {
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    strike: bool,
+    code: bool,
+    // index of the url in `urls`
+    link: Option<usize>,
+}
+
+#[derive(Clone)]
+struct Span {
+    text: String,
+    style: SpanStyle,
+}
+
+// what goes in front of the lines of a block, the first line of a list item or note
+// gets its marker and the others line up with the text after it
+struct Prefix {
+    first: String,
+    rest: String,
+    used: bool,
+}
+
+struct TableState {
+    alignments: Vec<Alignment>,
+    rows: Vec<Vec<Vec<Span>>>,
+    head: bool,
+}
+
+struct TextWriter {
+    width: usize,
+    ansi: bool,
+
+    lines: Vec<String>,
+    // whether the last line is empty, blocks are separated by one empty line
+    at_blank: bool,
+    prefixes: Vec<Prefix>,
+    // the inline content of the block being read
+    spans: Vec<Span>,
+    style: SpanStyle,
+    bold: usize,
+    italic: usize,
+    strike: usize,
+    heading: Option<HeadingLevel>,
+
+    // the next number of each open list, none for bullets
+    lists: Vec<Option<u64>>,
+    urls: Vec<String>,
+    // the urls listed at the end, in the order they are first referenced
+    references: Vec<usize>,
+    // the url of each open link and where its text starts in `spans`
+    links: Vec<Option<(usize, usize)>>,
+    footnote_ids: HashMap<String, usize>,
+    code_block: Option<String>,
+    table: Option<TableState>,
+    // the url and alt text of the image being read
+    image: Option<(String, String)>,
+}
+
+impl TextWriter {
+    fn new(width: usize, ansi: bool) -> Self {
+        TextWriter {
+            width,
+            ansi,
+            lines: vec![],
+            at_blank: true,
+            prefixes: vec![],
+            spans: vec![],
+            style: SpanStyle::default(),
+            bold: 0,
+            italic: 0,
+            strike: 0,
+            heading: None,
+            lists: vec![],
+            urls: vec![],
+            references: vec![],
+            links: vec![],
+            footnote_ids: HashMap::new(),
+            code_block: None,
+            table: None,
+            image: None,
+        }
+    }
+
+    // the links are listed below the text
+    fn finish(&mut self) {
+        self.flush();
+
+        if !self.references.is_empty() {
+            self.separate();
+
+            for (i, url) in self.references.clone().iter().enumerate() {
+                let reference = format!("[{}] {}", i + 1, self.urls[*url]);
+                self.emit(&reference);
+            }
+        }
+
+        while self.lines.last().is_some_and(|line| line.is_empty()) {
+            self.lines.pop();
+        }
+    }
+
+    fn push(&mut self, text: &str) {
+        self.spans.push(Span {
+            text: text.to_string(),
+            style: self.style,
+        });
+    }
+
+    fn push_styled(&mut self, text: &str, styled: impl Fn(&mut SpanStyle)) {
+        let mut style = self.style;
+        styled(&mut style);
+        self.spans.push(Span {
+            text: text.to_string(),
+            style,
+        });
+    }
+
+    // markup around emphasis, plain text only
+    fn marker(&mut self, marker: &str) {
+        self.update_style();
+
+        if !self.ansi {
+            self.push(marker);
+        }
+    }
+
+    fn update_style(&mut self) {
+        let heading = self.ansi && self.heading.is_some();
+
+        self.style.bold = self.bold > 0 || heading;
+        self.style.italic = self.italic > 0;
+        self.style.strike = self.strike > 0;
+        self.style.link = self
+            .links
+            .iter()
+            .rev()
+            .flatten()
+            .next()
+            .map(|(url, _)| *url);
+    }
+
+    fn push_prefix(&mut self, first: String) {
+        let rest = " ".repeat(visible_width(&first));
+        self.prefixes.push(Prefix {
+            first,
+            rest,
+            used: false,
+        });
+    }
+
+    fn url(&mut self, url: &str) -> usize {
+        match self.urls.iter().position(|known| known == url) {
+            Some(index) => index,
+            None => {
+                self.urls.push(url.to_string());
+                self.urls.len() - 1
+            }
+        }
+    }
+
+    // the number a url is listed under at the end
+    fn reference(&mut self, url: usize) -> usize {
+        match self.references.iter().position(|known| *known == url) {
+            Some(index) => index + 1,
+            None => {
+                self.references.push(url);
+                self.references.len()
+            }
+        }
+    }
+
+    // notes are numbered in the order they are first mentioned
+    fn footnote_id(&mut self, label: &str) -> usize {
+        let next = self.footnote_ids.len() + 1;
+        *self.footnote_ids.entry(label.to_string()).or_insert(next)
+    }
+
+    // the width left for text after the prefixes
+    fn line_width(&self) -> usize {
+        let prefix: usize = self
+            .prefixes
+            .iter()
+            .map(|prefix| visible_width(&prefix.rest))
+            .sum();
+
+        self.width.saturating_sub(prefix).max(MIN_WIDTH)
+    }
+
+    // writes the inline content read so far as wrapped lines
+    fn flush(&mut self) {
+        if self.spans.is_empty() || self.table.is_some() {
+            return;
+        }
+
+        let spans = std::mem::take(&mut self.spans);
+
+        for line in self.wrap(&spans, self.line_width()) {
+            self.emit(&line);
+        }
+    }
+
+    fn emit(&mut self, line: &str) {
+        let mut prefixed = String::new();
+
+        for prefix in &mut self.prefixes {
+            match prefix.used {
+                true => prefixed.push_str(&prefix.rest),
+                false => prefixed.push_str(&prefix.first),
+            }
+
+            prefix.used = true;
+        }
+
+        prefixed.push_str(line);
+        self.lines.push(prefixed.trim_end().to_string());
+        self.at_blank = false;
+    }
+
+    // an empty line between blocks, quotes keep their bar on it
+    fn separate(&mut self) {
+        if self.at_blank {
+            return;
+        }
+
+        let prefix: String = self
+            .prefixes
+            .iter()
+            .map(|prefix| prefix.rest.as_str())
+            .collect();
+        self.lines.push(prefix.trim_end().to_string());
+        self.at_blank = true;
+    }
+
+    // greedy, words longer than the line get a line of their own
+    fn wrap(&self, spans: &[Span], width: usize) -> Vec<String> {
+        let mut lines = vec![];
+        let mut line = String::new();
+        let mut line_width = 0;
+        let mut space = false;
+
+        for token in words(spans) {
+            let Some(word) = token else {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0;
+                space = false;
+                continue;
+            };
+
+            if word.is_empty() {
+                space = line_width > 0;
+                continue;
+            }
+
+            let word_width: usize = word.iter().map(|span| visible_width(&span.text)).sum();
+
+            if space && line_width + 1 + word_width > width {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0;
+            } else if space {
+                line.push(' ');
+                line_width += 1;
+            }
+
+            for span in &word {
+                line.push_str(&self.styled(span));
+            }
+
+            line_width += word_width;
+            space = false;
+        }
+
+        if !line.is_empty() {
+            lines.push(line);
+        }
+
+        lines
+    }
+
+    fn styled(&self, span: &Span) -> String {
+        let style = span.style;
+
+        if !self.ansi || style == SpanStyle::default() {
+            return span.text.clone();
+        }
+
+        let mut codes = vec![];
+
+        for (on, code) in [
+            (style.bold, "1"),
+            (style.italic, "3"),
+            (style.underline || style.link.is_some(), "4"),
+            (style.strike, "9"),
+            (style.code, "36"),
+            (style.link.is_some(), "34"),
+        ] {
+            if on {
+                codes.push(code);
+            }
+        }
+
+        let text = format!("\x1b[{}m{}\x1b[0m", codes.join(";"), span.text);
+
+        // terminals that know osc 8 make the text clickable
+        match style.link {
+            Some(url) => format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", self.urls[url], text),
+            None => text,
+        }
+    }
+
+    // columns shrink to fit the line, with the cells wrapped inside them
+    fn table(&mut self, table: TableState) {
+        let columns = table.alignments.len().max(1);
+        let cells: Vec<Vec<Vec<String>>> = table
+            .rows
+            .iter()
+            .map(|row| {
+                (0..columns)
+                    .map(|column| {
+                        let spans = row.get(column).map_or(&[][..], |cell| cell.as_slice());
+                        self.wrap(spans, usize::MAX)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut widths: Vec<usize> = (0..columns)
+            .map(|column| {
+                cells
+                    .iter()
+                    .flat_map(|row| row[column].iter())
+                    .map(|line| visible_width(line))
+                    .max()
+                    .unwrap_or(0)
+                    .max(3)
+            })
+            .collect();
+
+        let available = self.line_width();
+
+        while widths.iter().sum::<usize>() + 3 * columns + 1 > available {
+            let (widest, width) = widths
+                .iter()
+                .copied()
+                .enumerate()
+                .max_by_key(|(_, width)| *width)
+                .unwrap();
+
+            if width <= 5 {
+                break;
+            }
+
+            widths[widest] -= 1;
+        }
+
+        let border = |fill: char| {
+            let cells: Vec<String> = widths
+                .iter()
+                .map(|width| fill.to_string().repeat(width + 2))
+                .collect();
+            format!("+{}+", cells.join("+"))
+        };
+
+        self.emit(&border('-'));
+
+        for (i, row) in table.rows.iter().enumerate() {
+            let wrapped: Vec<Vec<String>> = (0..columns)
+                .map(|column| {
+                    let spans = row.get(column).map_or(&[][..], |cell| cell.as_slice());
+                    self.wrap(spans, widths[column])
+                })
+                .collect();
+            let height = wrapped.iter().map(Vec::len).max().unwrap_or(0).max(1);
+
+            for line in 0..height {
+                let cells: Vec<String> = wrapped
+                    .iter()
+                    .enumerate()
+                    .map(|(column, lines)| {
+                        let text = lines.get(line).map_or("", String::as_str);
+                        let padding = widths[column].saturating_sub(visible_width(text));
+                        let (left, right) = match table.alignments.get(column) {
+                            Some(Alignment::Right) => (padding, 0),
+                            Some(Alignment::Center) => (padding / 2, padding - padding / 2),
+                            _ => (0, padding),
+                        };
+
+                        format!(" {}{}{} ", " ".repeat(left), text, " ".repeat(right))
+                    })
+                    .collect();
+
+                self.emit(&format!("|{}|", cells.join("|")));
+            }
+
+            // the head is the first row
+            match i {
+                0 if table.rows.len() > 1 => self.emit(&border('=')),
+                _ => self.emit(&border('-')),
+            }
+        }
+    }
+}
+
+impl<'a> Walker<'a> for TextWriter {
+    fn start(&mut self, tag: Tag<'a>) {
+        match tag {
+            Tag::Paragraph => {
+                self.flush();
+                self.separate();
+            }
+            Tag::Heading(level, _, _) => {
+                self.flush();
+                self.separate();
+                self.heading = Some(level);
+
+                if self.ansi {
+                    self.style.bold = true;
+                    self.style.underline = level == HeadingLevel::H1;
+                }
+            }
+            Tag::BlockQuote => {
+                self.flush();
+                self.separate();
+
+                let bar = match self.ansi {
+                    true => "\x1b[2m│\x1b[0m ",
+                    false => "> ",
+                };
+                self.prefixes.push(Prefix {
+                    first: bar.to_string(),
+                    rest: bar.to_string(),
+                    used: true,
+                });
+            }
+            Tag::CodeBlock(_) => {
+                self.flush();
+                self.separate();
+                self.code_block = Some(String::new());
+            }
+            Tag::List(start) => {
+                self.flush();
+
+                // nested lists hang right under their item
+                if self.lists.is_empty() {
+                    self.separate();
+                }
+
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.flush();
+
+                let marker = match self.lists.last().copied().flatten() {
+                    Some(number) => format!("{}. ", number),
+                    None if self.ansi => "• ".to_string(),
+                    None => "- ".to_string(),
+                };
+                self.push_prefix(marker);
+            }
+            Tag::FootnoteDefinition(label) => {
+                self.flush();
+                self.separate();
+
+                let id = self.footnote_id(&label);
+                self.push_prefix(format!("[^{}]: ", id));
+            }
+            Tag::Table(alignments) => {
+                self.flush();
+                self.separate();
+                self.table = Some(TableState {
+                    alignments,
+                    rows: vec![],
+                    head: false,
+                });
+            }
+            Tag::TableHead => {
+                let table = self.table.as_mut().unwrap();
+                table.head = true;
+                table.rows.push(vec![]);
+                self.bold += 1;
+                self.update_style();
+            }
+            Tag::TableRow => self.table.as_mut().unwrap().rows.push(vec![]),
+            Tag::TableCell => {}
+            Tag::Emphasis => {
+                self.italic += 1;
+                self.marker("_");
+            }
+            Tag::Strong => {
+                self.bold += 1;
+                self.marker("*");
+            }
+            Tag::Strikethrough => {
+                self.strike += 1;
+                self.marker("~~");
+            }
+            Tag::Link(_, url, _) => {
+                let link = match url.starts_with('#') {
+                    true => None,
+                    false => Some((self.url(&url), self.spans.len())),
+                };
+
+                self.links.push(link);
+                self.update_style();
+            }
+            Tag::Image(_, url, _) => self.image = Some((url.to_string(), String::new())),
+        }
+    }
+
+    fn end(&mut self, tag: Tag<'a>) {
+        match tag {
+            Tag::Paragraph => {
+                self.flush();
+                self.separate();
+            }
+            Tag::Heading(level, _, _) => {
+                self.heading = None;
+                self.style.bold = false;
+                self.style.underline = false;
+
+                let spans = std::mem::take(&mut self.spans);
+                let lines = self.wrap(&spans, self.line_width());
+                let width = lines.iter().map(|line| visible_width(line)).max();
+
+                for line in &lines {
+                    let line = match (self.ansi, level) {
+                        (false, HeadingLevel::H1 | HeadingLevel::H2) | (true, _) => line.clone(),
+                        (false, level) => format!("{} {}", "#".repeat(level as usize), line),
+                    };
+                    self.emit(&line);
+                }
+
+                let underline = match level {
+                    HeadingLevel::H1 => '=',
+                    _ => '-',
+                };
+
+                if let (false, Some(width), HeadingLevel::H1 | HeadingLevel::H2) =
+                    (self.ansi, width, level)
+                {
+                    self.emit(&underline.to_string().repeat(width));
+                }
+
+                self.separate();
+            }
+            Tag::BlockQuote => {
+                self.flush();
+                self.prefixes.pop();
+
+                // the empty line after a quote is outside of it
+                match self.at_blank {
+                    true => {
+                        self.lines.pop();
+                        self.at_blank = false;
+                        self.separate();
+                    }
+                    false => self.separate(),
+                }
+            }
+            Tag::CodeBlock(_) => {
+                let code = self.code_block.take().unwrap();
+
+                for line in code.trim_end_matches('\n').split('\n') {
+                    let line = match self.ansi {
+                        true => format!("    \x1b[36m{}\x1b[0m", line),
+                        false => format!("    {}", line),
+                    };
+                    self.emit(line.trim_end());
+                }
+
+                self.separate();
+            }
+            Tag::List(_) => {
+                self.flush();
+                self.lists.pop();
+
+                if self.lists.is_empty() {
+                    self.separate();
+                }
+            }
+            Tag::Item => {
+                self.flush();
+                self.prefixes.pop();
+
+                if let Some(Some(number)) = self.lists.last_mut() {
+                    *number += 1;
+                }
+            }
+            Tag::FootnoteDefinition(_) => {
+                self.flush();
+                self.prefixes.pop();
+                self.separate();
+            }
+            Tag::Table(_) => {
+                let table = self.table.take().unwrap();
+                self.table(table);
+                self.separate();
+            }
+            Tag::TableHead => {
+                self.table.as_mut().unwrap().head = false;
+                self.bold -= 1;
+                self.update_style();
+            }
+            Tag::TableRow => {}
+            Tag::TableCell => {
+                let cell = std::mem::take(&mut self.spans);
+                let table = self.table.as_mut().unwrap();
+                table.rows.last_mut().unwrap().push(cell);
+            }
+            Tag::Emphasis => {
+                self.marker("_");
+                self.italic -= 1;
+                self.update_style();
+            }
+            Tag::Strong => {
+                self.marker("*");
+                self.bold -= 1;
+                self.update_style();
+            }
+            Tag::Strikethrough => {
+                self.marker("~~");
+                self.strike -= 1;
+                self.update_style();
+            }
+            Tag::Link(..) => {
+                let link = self.links.pop().unwrap();
+                self.update_style();
+
+                // links that show their url aren't numbered
+                if let Some((url, start)) = link {
+                    let text: String = self.spans[start..]
+                        .iter()
+                        .map(|span| span.text.as_str())
+                        .collect();
+                    let shown = self.urls[url].trim_start_matches("mailto:");
+
+                    if text != shown {
+                        let reference = format!("[{}]", self.reference(url));
+                        self.push(&reference);
+                    }
+                }
+            }
+            Tag::Image(..) => {
+                let (url, alt) = self.image.take().unwrap();
+                let url = self.url(&url);
+                let image = format!("[image: {}][{}]", alt, self.reference(url));
+                self.push(&image);
+            }
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if let Some(code) = &mut self.code_block {
+            code.push_str(text);
+        } else if let Some((_, alt)) = &mut self.image {
+            alt.push_str(text);
+        } else {
+            self.push(text);
+        }
+    }
+
+    fn code(&mut self, code: &str) {
+        match self.ansi {
+            true => self.push_styled(code, |style| style.code = true),
+            false => self.push(&format!("`{}`", code)),
+        }
+    }
+
+    fn footnote_reference(&mut self, label: &str) {
+        let id = self.footnote_id(label);
+        self.push(&format!("[^{}]", id));
+    }
+
+    fn soft_break(&mut self) {
+        self.push(" ");
+    }
+
+    fn hard_break(&mut self) {
+        self.push("\n");
+    }
+
+    fn rule(&mut self) {
+        self.flush();
+        self.separate();
+
+        let rule = match self.ansi {
+            true => format!("\x1b[2m{}\x1b[0m", "─".repeat(self.line_width())),
+            false => "-".repeat(self.line_width()),
+        };
+        self.emit(&rule);
+        self.separate();
+    }
+
+    fn task_list_marker(&mut self, checked: bool) {
+        match checked {
+            true => self.push("[x] "),
+            false => self.push("[ ] "),
+        }
+    }
+
+    // a form feed makes printers start a new page
+    fn page_break(&mut self) {
+        if !self.ansi {
+            self.separate();
+            self.lines.push("\x0c".to_string());
+            self.at_blank = false;
+        }
+    }
+}
+
+// the words of some inline content, each made of the styled pieces in it, an empty
+// word stands for a space and none for a line break
+fn words(spans: &[Span]) -> Vec<Option<Vec<Span>>> {
+    let mut words = vec![];
+    let mut word: Vec<Span> = vec![];
+
+    for span in spans {
+        for character in span.text.chars() {
+            if character == '\n' || character.is_whitespace() {
+                if !word.is_empty() {
+                    words.push(Some(std::mem::take(&mut word)));
+                }
+
+                words.push(match character {
+                    '\n' => None,
+                    _ => Some(vec![]),
+                });
+                continue;
+            }
+
+            match word.last_mut() {
+                Some(last) if last.style == span.style => last.text.push(character),
+                _ => word.push(Span {
+                    text: character.to_string(),
+                    style: span.style,
+                }),
+            }
+        }
+    }
+
+    if !word.is_empty() {
+        words.push(Some(word));
+    }
+
+    words
+}
+
+// columns taken in a terminal, escape sequences take none
+fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut characters = text.chars().peekable();
+
+    while let Some(character) = characters.next() {
+        if character != '\x1b' {
+            width += character.width().unwrap_or(0);
+            continue;
+        }
+
+        match characters.next() {
+            // csi, up to the final byte
+            Some('[') => {
+                for character in characters.by_ref() {
+                    if ('@'..='~').contains(&character) {
+                        break;
+                    }
+                }
+            }
+            // osc, up to the string terminator
+            Some(']') => {
+                while let Some(character) = characters.next() {
+                    if character == '\x07' {
+                        break;
+                    }
+
+                    if character == '\x1b' && characters.peek() == Some(&'\\') {
+                        characters.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    width
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_at_the_given_width() {
+        let markdown = "a paragraph long enough to need a few lines at thirty columns\n\n\
+            > quoted text is wrapped inside its prefix as well\n";
+        let text = PlainText::render_document(markdown, 30).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(
+            lines,
+            [
+                "a paragraph long enough to",
+                "need a few lines at thirty",
+                "columns",
+                "",
+                "> quoted text is wrapped",
+                "> inside its prefix as well",
+            ]
+        );
+        assert!(lines.iter().all(|line| visible_width(line) <= 30));
+    }
+
+    #[test]
+    fn lines_keep_a_minimum_width() {
+        let text = PlainText::render_document("one two three four five six\n", 5).unwrap();
+
+        assert_eq!(text, "one two three four\nfive six\n");
+    }
+}
@@ -0,0 +1,255 @@
+use std::fs;
+use std::path::Path;
+
+use printpdf::{Cmyk, Color, Rgb, SpotColor};
+use serde::Deserialize;
+
+use crate::assets::{read_if_exists, Assets, DEFAULT_STYLE_SHEET};
+use crate::error::{Error, Result};
+use crate::render::Style;
+
+const DARK_STYLE_SHEET: &str = include_str!("../assets/styles/dark.css");
+
+// a theme keeps both backends in sync: css for the html path,
+// a matching style for the inhouse path
+#[derive(Clone, Debug)]
+pub struct Theme {
+    pub name: String,
+    pub style_sheet: String,
+    pub style: Style,
+}
+
+// on-disk theme description, every field is optional and falls back to `extends`
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    extends: Option<String>,
+    style_sheet: Option<String>,
+    background: Option<String>,
+    text: Option<String>,
+    rule: Option<String>,
+    link: Option<String>,
+}
+
+impl Theme {
+    pub fn light() -> Theme {
+        Theme {
+            name: "light".to_string(),
+            style_sheet: DEFAULT_STYLE_SHEET.to_string(),
+            style: Style::default(),
+        }
+    }
+
+    pub fn dark() -> Theme {
+        Theme {
+            name: "dark".to_string(),
+            style_sheet: DARK_STYLE_SHEET.to_string(),
+            style: Style {
+                background_color: Some(rgb(0x0d, 0x11, 0x17)),
+                text_color: rgb(0xc9, 0xd1, 0xd9),
+                rule_color: rgb(0x30, 0x36, 0x3d),
+                link_color: rgb(0x58, 0xa6, 0xff),
+                code_background: rgb(0x16, 0x1b, 0x22),
+                ..Style::default()
+            },
+        }
+    }
+
+    pub fn builtin(name: &str) -> Option<Theme> {
+        match name {
+            "light" => Some(Theme::light()),
+            "dark" => Some(Theme::dark()),
+            _ => None,
+        }
+    }
+
+    // `spec` is either the name of a builtin theme or a path to a theme file,
+    // css of builtin themes can be overridden with `styles/<name>.css` in the override dirs
+    pub fn resolve(spec: &str, project_root: impl AsRef<Path>) -> Result<Theme> {
+        match Theme::builtin(spec) {
+            Some(mut theme) => {
+                for dir in Assets::override_dirs(project_root) {
+                    let path = dir.join("styles").join(format!("{}.css", spec));
+
+                    if let Some(style_sheet) = read_if_exists(path)? {
+                        theme.style_sheet = String::from_utf8_lossy(&style_sheet).into_owned();
+                    }
+                }
+
+                Ok(theme)
+            }
+            None if Path::new(spec).is_file() => Theme::from_file(spec),
+            None => Err(Error::Config(format!(
+                "unknown theme \"{}\", expected light, dark or a path to a theme file",
+                spec
+            ))),
+        }
+    }
+
+    // theme files are toml, e.g.
+    //
+    // extends = "dark"
+    // style_sheet = "solarized.css"
+    // background = "#002b36"
+    // text = "#839496"
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Theme> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)?;
+        let file: ThemeFile = toml::from_str(&source)
+            .map_err(|err| Error::Config(format!("{}: {}", path.display(), err)))?;
+
+        let base = file.extends.as_deref().unwrap_or("light");
+        let mut theme = Theme::builtin(base)
+            .ok_or_else(|| Error::Config(format!("unknown base theme \"{}\"", base)))?;
+
+        theme.name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or(theme.name);
+
+        if let Some(style_sheet) = file.style_sheet {
+            let relative_to = path.parent().unwrap_or(Path::new("."));
+            theme.style_sheet = fs::read_to_string(relative_to.join(style_sheet))?;
+        }
+
+        if let Some(background) = file.background {
+            theme.style.background_color = match background.as_str() {
+                "none" => None,
+                color => Some(parse_color(color)?),
+            };
+        }
+
+        if let Some(text) = file.text {
+            theme.style.text_color = parse_color(&text)?;
+        }
+
+        if let Some(rule) = file.rule {
+            theme.style.rule_color = parse_color(&rule)?;
+        }
+
+        if let Some(link) = file.link {
+            theme.style.link_color = parse_color(&link)?;
+        }
+
+        Ok(theme)
+    }
+}
+
+fn named_color(name: &str) -> Option<Color> {
+    match name.to_lowercase().as_str() {
+        "black" => Some(rgb(0, 0, 0)),
+        "white" => Some(rgb(255, 255, 255)),
+        "gray" | "grey" => Some(rgb(128, 128, 128)),
+        "silver" => Some(rgb(192, 192, 192)),
+        "red" => Some(rgb(255, 0, 0)),
+        "green" => Some(rgb(0, 128, 0)),
+        "blue" => Some(rgb(0, 0, 255)),
+        "navy" => Some(rgb(0, 0, 128)),
+        _ => None,
+    }
+}
+
+fn rgb(r: u8, g: u8, b: u8) -> Color {
+    Color::Rgb(Rgb::new(
+        r as f32 / 255.0,
+        g as f32 / 255.0,
+        b as f32 / 255.0,
+        None,
+    ))
+}
+
+// `color` in css notation, for the html path
+pub(crate) fn css_color(color: &Color) -> String {
+    let (r, g, b) = rgb_channels(color);
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+    format!("rgb({}, {}, {})", channel(r), channel(g), channel(b))
+}
+
+// like `RRGGBB`, for formats that want colors as bare hex digits
+pub(crate) fn hex_color(color: &Color) -> String {
+    let (r, g, b) = rgb_channels(color);
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+    format!("{:02X}{:02X}{:02X}", channel(r), channel(g), channel(b))
+}
+
+// red, green and blue from 0 to 1, cmyk is converted naively
+pub(crate) fn rgb_channels(color: &Color) -> (f32, f32, f32) {
+    match color {
+        Color::Rgb(rgb) => (rgb.r, rgb.g, rgb.b),
+        Color::Greyscale(grey) => (grey.percent, grey.percent, grey.percent),
+        Color::Cmyk(Cmyk { c, m, y, k, .. }) | Color::SpotColor(SpotColor { c, m, y, k }) => (
+            (1.0 - c) * (1.0 - k),
+            (1.0 - m) * (1.0 - k),
+            (1.0 - y) * (1.0 - k),
+        ),
+    }
+}
+
+// accepts "#rgb", "#rrggbb", "rgb(r, g, b)" and a handful of color names
+pub(crate) fn parse_color(color: &str) -> Result<Color> {
+    let invalid = || Error::Config(format!("invalid color \"{}\"", color));
+
+    if let Some(color) = named_color(color) {
+        return Ok(color);
+    }
+
+    if let Some(channels) = color
+        .strip_prefix("rgba(")
+        .or_else(|| color.strip_prefix("rgb("))
+        .and_then(|channels| channels.strip_suffix(')'))
+    {
+        // alpha is dropped, pdf fills are opaque
+        let channels = channels
+            .split(',')
+            .take(3)
+            .map(|channel| channel.trim().parse::<u8>().map_err(|_| invalid()))
+            .collect::<Result<Vec<u8>>>()?;
+
+        return match channels.as_slice() {
+            [r, g, b] => Ok(rgb(*r, *g, *b)),
+            _ => Err(invalid()),
+        };
+    }
+
+    let hex = color.strip_prefix('#').ok_or_else(invalid)?;
+
+    // the channels below are sliced by byte
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+
+    let channel = |digits: &str| u8::from_str_radix(digits, 16).map_err(|_| invalid());
+
+    match hex.len() {
+        3 => {
+            let expand = |i: usize| channel(&hex[i..i + 1].repeat(2));
+            Ok(rgb(expand(0)?, expand(1)?, expand(2)?))
+        }
+        6 => Ok(rgb(
+            channel(&hex[0..2])?,
+            channel(&hex[2..4])?,
+            channel(&hex[4..6])?,
+        )),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_colors() {
+        assert!(parse_color("#fff").is_ok());
+        assert!(parse_color("#1a2b3c").is_ok());
+        assert!(parse_color("#ggg").is_err());
+    }
+
+    #[test]
+    fn rejects_non_ascii_hex_colors() {
+        assert!(parse_color("#é1").is_err());
+        assert!(parse_color("#aé111").is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use pulldown_cmark::{Event, Tag};
+
+use crate::page_break;
+
+// helpers the writers share
+
+// for text and attribute values in xml and html
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// the anchor github gives a heading, lowercase with dashes for spaces
+pub(crate) fn slug(title: &str) -> String {
+    title
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+// whether `url` has a scheme like `https:` or `mailto:`, everything else is a path
+// relative to the document or a fragment of it
+pub(crate) fn is_absolute_url(url: &str) -> bool {
+    url.split_once(':').is_some_and(|(scheme, _)| {
+        // longer than a windows drive letter
+        scheme.len() > 1
+            && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    })
+}
+
+// whether `events` start with the paragraph a page break was rewritten to, see page_break
+pub(crate) fn is_page_break(events: &[Event]) -> bool {
+    matches!(
+        events,
+        [Event::Start(Tag::Paragraph), Event::Text(text), Event::End(Tag::Paragraph), ..]
+            if text.as_ref() == page_break::MARKER
+    )
+}
+
+// the file extension and pixel size of png, jpeg and gif images
+pub(crate) fn image_info(data: &[u8]) -> Option<(&'static str, (u32, u32))> {
+    let be16 = |at: usize| Some(u16::from_be_bytes([*data.get(at)?, *data.get(at + 1)?]) as u32);
+
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        let be32 = |at: usize| Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?));
+        return Some(("png", (be32(16)?, be32(20)?)));
+    }
+
+    if data.starts_with(b"GIF8") {
+        let le16 =
+            |at: usize| Some(u16::from_le_bytes([*data.get(at)?, *data.get(at + 1)?]) as u32);
+        return Some(("gif", (le16(6)?, le16(8)?)));
+    }
+
+    if data.starts_with(&[0xff, 0xd8]) {
+        // walks the segments up to the start of frame, which has the size
+        let mut at = 2;
+
+        while at + 4 <= data.len() {
+            if data[at] != 0xff {
+                return None;
+            }
+
+            let marker = data[at + 1];
+            let length = be16(at + 2)? as usize;
+
+            if (0xc0..=0xcf).contains(&marker) && !matches!(marker, 0xc4 | 0xc8 | 0xcc) {
+                return Some(("jpeg", (be16(at + 7)?, be16(at + 5)?)));
+            }
+
+            at += 2 + length;
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_markup_characters() {
+        assert_eq!(
+            escape(r#"<a href="x">&</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn slugs_headings_like_github() {
+        assert_eq!(slug(" Getting Started! "), "getting-started");
+        assert_eq!(slug("snake_case & kebab-case"), "snake_case--kebab-case");
+    }
+
+    #[test]
+    fn tells_absolute_urls_from_paths() {
+        assert!(is_absolute_url("https://example.com/a.md"));
+        assert!(is_absolute_url("mailto:someone@example.com"));
+        assert!(!is_absolute_url("#overview"));
+        assert!(!is_absolute_url("other.md#a:b"));
+        assert!(!is_absolute_url("C:/docs/other.md"));
+    }
+
+    #[test]
+    fn finds_marked_page_breaks() {
+        let marked = page_break::mark("before\n\n\\pagebreak\n\nafter\n", false);
+        let events: Vec<_> = pulldown_cmark::Parser::new(&marked).collect();
+        let breaks: Vec<_> = (0..events.len())
+            .filter(|i| is_page_break(&events[*i..]))
+            .collect();
+
+        assert_eq!(breaks, [3]);
+    }
+
+    #[test]
+    fn reads_image_sizes_from_headers() {
+        let png = tiny_skia::Pixmap::new(3, 2).unwrap().encode_png().unwrap();
+        let gif = b"GIF89a\x05\x00\x07\x00";
+
+        assert_eq!(image_info(&png), Some(("png", (3, 2))));
+        assert_eq!(image_info(gif), Some(("gif", (5, 7))));
+        assert_eq!(image_info(b"not an image"), None);
+    }
+}
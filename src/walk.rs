@@ -0,0 +1,61 @@
+use pulldown_cmark::{Event, Options, Tag};
+
+use crate::util;
+
+// the markdown every output reads, page breaks have to be marked before
+pub(crate) fn parse(markdown: &str) -> Vec<Event<'_>> {
+    pulldown_cmark::Parser::new_ext(
+        markdown,
+        Options::ENABLE_STRIKETHROUGH
+            | Options::ENABLE_TABLES
+            | Options::ENABLE_FOOTNOTES
+            | Options::ENABLE_TASKLISTS,
+    )
+    .collect()
+}
+
+// what the docx, odt, tex and text writers do with the events of a document, `walk`
+// calls them in document order
+pub(crate) trait Walker<'a> {
+    fn start(&mut self, tag: Tag<'a>);
+    fn end(&mut self, tag: Tag<'a>);
+    fn text(&mut self, text: &str);
+    fn code(&mut self, code: &str);
+    fn footnote_reference(&mut self, label: &str);
+    fn soft_break(&mut self);
+    fn hard_break(&mut self);
+    fn rule(&mut self);
+    fn task_list_marker(&mut self, checked: bool);
+    // stands for the whole marker paragraph, see page_break
+    fn page_break(&mut self);
+
+    // raw html is left out, like on the html path
+    fn html(&mut self, _html: &str) {}
+}
+
+pub(crate) fn walk<'a>(events: &[Event<'a>], walker: &mut impl Walker<'a>) {
+    let mut position = 0;
+
+    while position < events.len() {
+        if util::is_page_break(&events[position..]) {
+            walker.page_break();
+            position += 3;
+            continue;
+        }
+
+        match events[position].clone() {
+            Event::Start(tag) => walker.start(tag),
+            Event::End(tag) => walker.end(tag),
+            Event::Text(text) => walker.text(&text),
+            Event::Code(code) => walker.code(&code),
+            Event::Html(html) => walker.html(&html),
+            Event::FootnoteReference(label) => walker.footnote_reference(&label),
+            Event::SoftBreak => walker.soft_break(),
+            Event::HardBreak => walker.hard_break(),
+            Event::Rule => walker.rule(),
+            Event::TaskListMarker(checked) => walker.task_list_marker(checked),
+        }
+
+        position += 1;
+    }
+}